@@ -0,0 +1,28 @@
+/// Names of the general-purpose registers in the o32 ABI, by number
+pub const REGISTER_NAMES: [&str; 32] = [
+    "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
+    "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "t8", "t9", "k0", "k1", "gp", "sp", "fp",
+    "ra",
+];
+
+/// Number of the general-purpose register named `name` in the ABI, without `$`, where `s8` is
+/// `fp`
+pub fn register_number(name: &str) -> Option<i32> {
+    match name {
+        "s8" => Some(30),
+        _ => REGISTER_NAMES
+            .iter()
+            .position(|n| *n == name)
+            .map(|n| n as i32),
+    }
+}
+
+#[test]
+fn test_register_number() {
+    assert_eq!(register_number("zero"), Some(0));
+    assert_eq!(register_number("t8"), Some(24));
+    assert_eq!(register_number("t9"), Some(25));
+    assert_eq!(register_number("s8"), Some(30));
+    assert_eq!(register_number("fp"), Some(30));
+    assert_eq!(register_number("s9"), None);
+}
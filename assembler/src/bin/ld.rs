@@ -0,0 +1,44 @@
+use clap::Parser;
+use mips_assembler::link::link_files;
+use mips_assembler::{BResult, Endian};
+use std::fs::File;
+use std::io::prelude::*;
+
+#[derive(Debug, Parser)]
+#[clap(name = "mips-ld", version = "v1.0.0", about = "Minimum mips linker")]
+struct Args {
+    /// Store data as big-endian [default: false]
+    #[arg(short, long = "big-endian", default_value_t = false)]
+    be: bool,
+
+    /// Read object files made by `mma -c`.
+    #[arg(value_name = "Input File", required = true)]
+    input: Vec<String>,
+
+    /// Place the output into <Output File>.
+    #[arg(value_name = "Output File", short, long, default_value = "output")]
+    output: String,
+
+    /// Write an ELF32 executable instead of the file header format.
+    #[arg(long = "elf", default_value_t = false)]
+    elf: bool,
+}
+
+fn main() -> BResult<()> {
+    let args = Args::parse();
+
+    let endian = if args.be { Endian::Big } else { Endian::Little };
+    let program = link_files(&args.input)?;
+
+    let mut code = Vec::new();
+    if args.elf {
+        program.write_elf(endian, &mut code)?;
+    } else {
+        program.write_code(endian, &mut code)?;
+    }
+
+    let mut output = File::create(args.output)?;
+    output.write_all(&code)?;
+
+    Ok(())
+}
@@ -1,5 +1,27 @@
-use clap::Parser;
-use mips_assembler::{assemble, assemble_to_u8, BResult, Endian};
+use clap::{Parser, ValueEnum};
+use mips_assembler::source::Source;
+use mips_assembler::{
+    assemble_source_to_object_with, assemble_source_to_program_with, AssembleOptions, BResult,
+    Endian,
+};
+use std::fs::File;
+use std::io::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    /// File header followed by the sections
+    Binary,
+    /// Intel HEX
+    Ihex,
+    /// Motorola S-record
+    Srec,
+    /// Verilog `$readmemh` memory image
+    Readmemh,
+    /// Verilog `$readmemb` memory image
+    Readmemb,
+    /// Logisim ROM image of the text section
+    Logisim,
+}
 
 #[derive(Debug, Parser)]
 #[clap(name = "mimi", version = "v1.0.0", about = "Minimum mips assembler")]
@@ -9,14 +31,48 @@ struct Args {
     #[arg(short, long = "big-endian", default_value_t = false)]
     be: bool,
 
-    /// Read assembly from <Input File>.
-    #[arg(value_name = "Input File")]
-    input: String,
+    /// Read assembly from <Input File>. Multiple files share one symbol table.
+    #[arg(value_name = "Input File", required = true)]
+    input: Vec<String>,
+
+    /// Search <Directory> for files given to `.include`.
+    #[arg(value_name = "Directory", short = 'I', long = "include")]
+    include: Vec<String>,
 
     /// Place the output into <Output File>.
     #[arg(value_name = "Output File", short, long, default_value = "output")]
     output: String,
 
+    /// Assemble into a relocatable object file which is linked by mips-ld.
+    #[arg(short = 'c', long = "compile", default_value_t = false)]
+    compile: bool,
+
+    /// Write an ELF32 executable instead of the file header format.
+    #[arg(long = "elf", default_value_t = false)]
+    elf: bool,
+
+    /// Write the program in <Format> instead of the file header format.
+    #[arg(
+        value_name = "Format",
+        short = 'f',
+        long = "format",
+        default_value = "binary"
+    )]
+    format: Format,
+
+    /// Fill the delay slot of every branch and jump with a nop, unless `.set noreorder` is given.
+    #[arg(long = "delay-slots", default_value_t = false)]
+    delay_slots: bool,
+
+    /// Write the source position of every instruction and the address of every label to <File>.
+    #[arg(value_name = "File", short = 'g', long = "debug-info")]
+    debug_info: Option<String>,
+
+    /// Write a listing of the address, encoding and source line of every instruction, and the
+    /// symbol table, to <File>.
+    #[arg(value_name = "File", short = 'l', long = "listing")]
+    listing: Option<String>,
+
     /// If it is valid, print the result in text format to standard output.
     #[arg(short = 's', long = "string", default_value_t = false)]
     string: bool,
@@ -26,17 +82,56 @@ fn main() -> BResult<()> {
     let args = Args::parse();
 
     let endian = if args.be { Endian::Big } else { Endian::Little };
-    if args.string {
-        let code = assemble_to_u8(endian, &args.input)?;
+    let options = AssembleOptions {
+        delay_slots: args.delay_slots,
+    };
+    let source = Source::load(&args.input, &args.include)?;
+    let mut code = Vec::new();
+    let mut image = None;
+    if args.compile {
+        if args.debug_info.is_some() || args.listing.is_some() || args.format != Format::Binary {
+            return Err(
+                "debug info, listings and other formats are only written for programs, not object files"
+                    .into(),
+            );
+        }
+        assemble_source_to_object_with(&source, &options)?.write_code(endian, &mut code)?;
+    } else {
+        let program = assemble_source_to_program_with(&source, &options)?;
+        if args.elf && args.format != Format::Binary {
+            return Err("--elf cannot be combined with --format".into());
+        }
+        match args.format {
+            Format::Binary if args.elf => program.write_elf(endian, &mut code)?,
+            Format::Binary => program.write_code(endian, &mut code)?,
+            Format::Ihex => image = Some(program.intel_hex(endian)?),
+            Format::Srec => image = Some(program.s_record(endian)?),
+            Format::Readmemh => image = Some(program.readmem(false)),
+            Format::Readmemb => image = Some(program.readmem(true)),
+            Format::Logisim => image = Some(program.logisim()),
+        }
+        if let Some(path) = args.debug_info.as_ref() {
+            std::fs::write(path, program.debug_info())?;
+        }
+        if let Some(path) = args.listing.as_ref() {
+            std::fs::write(path, program.listing(&source))?;
+        }
+    }
+
+    // Images in text formats are written as they are
+    if let Some(image) = image {
+        code = image.into_bytes();
+        if args.string {
+            print!("{}", String::from_utf8_lossy(&code));
+        }
+    } else if args.string {
         for c in code.chunks(4) {
             println!("{:08b}{:08b}{:08b}{:08b}", c[0], c[1], c[2], c[3]);
         }
     }
-    assemble(
-        if args.be { Endian::Big } else { Endian::Little },
-        args.input,
-        args.output,
-    )?;
+
+    let mut output = File::create(args.output)?;
+    output.write_all(&code)?;
 
     Ok(())
 }
@@ -0,0 +1,181 @@
+use crate::source::Source;
+use crate::Instruction;
+use crate::Program;
+use crate::KTEXT_ADDRESS;
+use crate::TEXT_ADDRESS;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// Position of an instruction in the source
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Position {
+    /// Index into `LineTable::files`
+    pub file: usize,
+    /// Line number (1-origin)
+    pub line: usize,
+    /// Column the instruction starts at after any labels (1-origin)
+    pub column: usize,
+}
+
+/// Source position of every instruction in the text and kernel text sections
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct LineTable {
+    pub files: Vec<PathBuf>,
+    /// Position of the instruction at each address
+    pub lines: BTreeMap<u32, Position>,
+}
+
+impl LineTable {
+    /// File and position of the instruction at `address`
+    pub fn get(&self, address: u32) -> Option<(&PathBuf, Position)> {
+        let position = self.lines.get(&address)?;
+        Some((&self.files[position.file], *position))
+    }
+}
+
+/// Column (1-origin) of `text` where the instruction starts, after any label definitions
+fn column(text: &str) -> usize {
+    let mut rest = text;
+    loop {
+        let trimmed = rest.trim_start();
+        let label = trimmed.find(':').filter(|&end| {
+            end > 0
+                && trimmed[..end]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        match label {
+            Some(end) => rest = &trimmed[end + 1..],
+            None => return text.len() - trimmed.len() + 1,
+        }
+    }
+}
+
+/// Gives each instruction of `tokens` the position of its line in `source`.
+///
+/// Columns are found in `original`, the source before macro expansion, so instructions
+/// expanded from a macro point at its invocation.
+pub fn gen_line_table(
+    original: &Source,
+    source: &Source,
+    lines: &[usize],
+    tokens: &[Instruction],
+) -> LineTable {
+    let texts: HashMap<_, _> = original
+        .lines
+        .iter()
+        .map(|(location, text)| ((location.file, location.line), text.as_str()))
+        .collect();
+
+    let mut table = LineTable {
+        files: source.files.clone(),
+        lines: BTreeMap::new(),
+    };
+    let mut text = TEXT_ADDRESS as u32;
+    let mut ktext = KTEXT_ADDRESS as u32;
+    let mut in_ktext = false;
+
+    for (line, token) in lines.iter().zip(tokens.iter()) {
+        if token.starts_section() {
+            in_ktext = token.is_ktext();
+        }
+        if let Instruction::I { .. } | Instruction::R { .. } | Instruction::J { .. } = token {
+            let address = if in_ktext { &mut ktext } else { &mut text };
+            if let Some(location) = source.location(*line) {
+                let text = texts.get(&(location.file, location.line));
+                table.lines.insert(
+                    *address,
+                    Position {
+                        file: location.file,
+                        line: location.line,
+                        column: text.map(|t| column(t)).unwrap_or(1),
+                    },
+                );
+            }
+            *address += 4;
+        }
+    }
+
+    table
+}
+
+impl Program {
+    /// Debug information: the source position of every instruction and the address of every
+    /// label, one record per line.
+    ///
+    /// ```text
+    /// file 0 main.s
+    /// line 0x00400000 0 3 5
+    /// label 0x00400000 main
+    /// ```
+    pub fn debug_info(&self) -> String {
+        let mut output = String::new();
+        for (idx, file) in self.lines.files.iter().enumerate() {
+            output.push_str(&format!("file {} {}\n", idx, file.display()));
+        }
+        for (address, p) in self.lines.lines.iter() {
+            output.push_str(&format!(
+                "line {:#010x} {} {} {}\n",
+                address, p.file, p.line, p.column
+            ));
+        }
+        for (address, name) in self.label_addresses() {
+            output.push_str(&format!("label {:#010x} {}\n", address, name));
+        }
+        output
+    }
+}
+
+#[test]
+fn test_column() {
+    assert_eq!(column("addi $t0, $zero, 1"), 1);
+    assert_eq!(column("    jr $ra"), 5);
+    assert_eq!(column("main: L: lw $a0, 0($t0)"), 10);
+    assert_eq!(column("\tsyscall # a: b"), 2);
+}
+
+#[test]
+fn test_line_table() {
+    use crate::assemble_source_to_program;
+
+    let input = r#"main:
+        la $t0, A
+        lw $a0, 0($t0)
+        .data
+        A: .word 5
+        .ktext
+        eret
+        .text
+L:      jr $ra"#;
+    let program = assemble_source_to_program(&Source::from_string("main.s", input)).unwrap();
+    let table = &program.lines;
+    let line = |address| table.get(address).map(|(_, p)| (p.line, p.column));
+
+    // la is two instructions on the same line
+    assert_eq!(line(0x00400000), Some((2, 9)));
+    assert_eq!(line(0x00400004), Some((2, 9)));
+    assert_eq!(line(0x00400008), Some((3, 9)));
+    assert_eq!(line(0x0040000c), Some((9, 9)));
+    assert_eq!(line(KTEXT_ADDRESS as u32), Some((7, 9)));
+    assert_eq!(table.lines.len(), 5);
+    assert_eq!(table.get(0x00400000).unwrap().0, &PathBuf::from("main.s"));
+
+    let debug_info = program.debug_info();
+    assert!(debug_info.starts_with("file 0 main.s\nline 0x00400000 0 2 9\n"));
+    assert!(debug_info.ends_with("label 0x0040000c L\nlabel 0x10010000 A\n"));
+}
+
+#[test]
+fn test_line_table_macro() {
+    use crate::assemble_source_to_program;
+
+    let input = r#".macro inc(%r)
+  addi %r, %r, 1
+.end_macro
+    inc($t0)"#;
+    let program = assemble_source_to_program(&Source::from_string("main.s", input)).unwrap();
+
+    // The expanded instruction points at the invocation
+    let (_, position) = program.lines.get(0x00400000).unwrap();
+    assert_eq!((position.line, position.column), (4, 5));
+}
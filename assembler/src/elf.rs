@@ -0,0 +1,314 @@
+use crate::object::SymbolSection;
+use crate::write_code;
+use crate::BResult;
+use crate::Binary;
+use crate::Endian;
+use crate::Program;
+use crate::DATA_ADDRESS;
+use crate::KTEXT_ADDRESS;
+use crate::TEXT_ADDRESS;
+
+const ELF_HEADER_SIZE: Binary = 52;
+const PROGRAM_HEADER_SIZE: Binary = 32;
+const SECTION_HEADER_SIZE: Binary = 40;
+const SYMBOL_SIZE: Binary = 16;
+
+const ET_EXEC: u16 = 2;
+const EM_MIPS: u16 = 8;
+const EF_MIPS_ABI_O32: Binary = 0x1000;
+
+const PT_LOAD: Binary = 1;
+const PF_X: Binary = 1;
+const PF_W: Binary = 2;
+const PF_R: Binary = 4;
+
+const SHT_PROGBITS: Binary = 1;
+const SHT_SYMTAB: Binary = 2;
+const SHT_STRTAB: Binary = 3;
+const SHF_WRITE: Binary = 1;
+const SHF_ALLOC: Binary = 2;
+const SHF_EXECINSTR: Binary = 4;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+
+/// Section indices in the section header table
+const SHN_TEXT: u16 = 1;
+const SHN_DATA: u16 = 2;
+const SHN_KTEXT: u16 = 3;
+const SHN_STRTAB: Binary = 5;
+
+fn write_half(endian: Endian, half: u16, output: &mut Vec<u8>) {
+    match endian {
+        Endian::Big => output.extend(half.to_be_bytes()),
+        Endian::Little => output.extend(half.to_le_bytes()),
+    }
+}
+
+/// String table which starts with an empty string
+struct StringTable(Vec<u8>);
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable(vec![0])
+    }
+
+    fn add(&mut self, s: &str) -> Binary {
+        let idx = self.0.len() as Binary;
+        self.0.extend(s.as_bytes());
+        self.0.push(0);
+        idx
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SectionHeader {
+    name: Binary,
+    kind: Binary,
+    flags: Binary,
+    addr: Binary,
+    offset: Binary,
+    size: Binary,
+    link: Binary,
+    info: Binary,
+    align: Binary,
+    entsize: Binary,
+}
+
+impl SectionHeader {
+    fn write_code(&self, endian: Endian, output: &mut Vec<u8>) -> BResult<()> {
+        for field in [
+            self.name,
+            self.kind,
+            self.flags,
+            self.addr,
+            self.offset,
+            self.size,
+            self.link,
+            self.info,
+            self.align,
+            self.entsize,
+        ] {
+            write_code(endian, field, output)?;
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    /// Writes the program as an ELF32 MIPS executable.
+    ///
+    /// The text, data and kernel text sections are loaded by one PT_LOAD segment each, and
+    /// the labels are written to `.symtab`.
+    pub fn write_elf(&self, endian: Endian, output: &mut Vec<u8>) -> BResult<()> {
+        let text_addr = TEXT_ADDRESS;
+        let data_addr = DATA_ADDRESS;
+        let text_size = 4 * self.text.len() as Binary;
+        let data_size = 4 * self.data.len() as Binary;
+        let ktext_size = 4 * self.ktext.len() as Binary;
+
+        let phnum = 1 + !self.data.is_empty() as Binary + !self.ktext.is_empty() as Binary;
+        let text_offset = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE * phnum;
+        let data_offset = text_offset + text_size;
+        let ktext_offset = data_offset + data_size;
+
+        // Symbols: locals must precede globals
+        let mut strtab = StringTable::new();
+        let mut symtab = vec![0; SYMBOL_SIZE as usize];
+        let mut symbols: Vec<_> = self.symbols.iter().collect();
+        symbols.sort_by_key(|s| s.global);
+        let first_global = 1 + symbols.iter().filter(|s| !s.global).count() as Binary;
+
+        for symbol in symbols {
+            let (value, shndx) = match symbol.section {
+                SymbolSection::Text => (text_addr + 4 * symbol.offset, SHN_TEXT),
+                SymbolSection::Data => (data_addr + 4 * symbol.offset, SHN_DATA),
+                SymbolSection::KText => (KTEXT_ADDRESS + 4 * symbol.offset, SHN_KTEXT),
+                SymbolSection::Undefined => (0, 0),
+            };
+            let bind = if symbol.global { STB_GLOBAL } else { STB_LOCAL };
+
+            write_code(endian, strtab.add(&symbol.name), &mut symtab)?;
+            write_code(endian, value, &mut symtab)?;
+            write_code(endian, 0, &mut symtab)?;
+            symtab.push(bind << 4);
+            symtab.push(0);
+            write_half(endian, shndx, &mut symtab);
+        }
+
+        let mut shstrtab = StringTable::new();
+        let names = [
+            ".text",
+            ".data",
+            ".ktext",
+            ".symtab",
+            ".strtab",
+            ".shstrtab",
+        ]
+        .map(|n| shstrtab.add(n));
+
+        let symtab_offset = ktext_offset + ktext_size;
+        let strtab_offset = symtab_offset + symtab.len() as Binary;
+        let shstrtab_offset = strtab_offset + strtab.0.len() as Binary;
+        let shoff = (shstrtab_offset + shstrtab.0.len() as Binary + 3) & !3;
+
+        // ELF header
+        output.extend(b"\x7fELF");
+        output.push(1); // ELFCLASS32
+        output.push(match endian {
+            Endian::Little => 1,
+            Endian::Big => 2,
+        });
+        output.push(1); // EV_CURRENT
+        output.extend([0; 9]);
+        write_half(endian, ET_EXEC, output);
+        write_half(endian, EM_MIPS, output);
+        write_code(endian, 1, output)?;
+        write_code(endian, self.header.entry_address(), output)?;
+        write_code(endian, ELF_HEADER_SIZE, output)?;
+        write_code(endian, shoff, output)?;
+        write_code(endian, EF_MIPS_ABI_O32, output)?;
+        write_half(endian, ELF_HEADER_SIZE as u16, output);
+        write_half(endian, PROGRAM_HEADER_SIZE as u16, output);
+        write_half(endian, phnum as u16, output);
+        write_half(endian, SECTION_HEADER_SIZE as u16, output);
+        write_half(endian, 7, output);
+        write_half(endian, 6, output);
+
+        // Program headers
+        let mut segments = vec![(text_offset, text_addr, text_size, PF_R | PF_X)];
+        if !self.data.is_empty() {
+            segments.push((data_offset, data_addr, data_size, PF_R | PF_W));
+        }
+        if !self.ktext.is_empty() {
+            segments.push((ktext_offset, KTEXT_ADDRESS, ktext_size, PF_R | PF_X));
+        }
+        for (offset, addr, size, flags) in segments {
+            for field in [PT_LOAD, offset, addr, addr, size, size, flags, 4] {
+                write_code(endian, field, output)?;
+            }
+        }
+
+        // Contents
+        for code in self
+            .text
+            .iter()
+            .chain(self.data.iter())
+            .chain(self.ktext.iter())
+        {
+            write_code(endian, *code, output)?;
+        }
+        output.extend(&symtab);
+        output.extend(&strtab.0);
+        output.extend(&shstrtab.0);
+        output.resize(shoff as usize, 0);
+
+        // Section headers
+        let text = SectionHeader {
+            name: names[0],
+            kind: SHT_PROGBITS,
+            flags: SHF_ALLOC | SHF_EXECINSTR,
+            addr: text_addr,
+            offset: text_offset,
+            size: text_size,
+            link: 0,
+            info: 0,
+            align: 4,
+            entsize: 0,
+        };
+        let data = SectionHeader {
+            name: names[1],
+            flags: SHF_ALLOC | SHF_WRITE,
+            addr: data_addr,
+            offset: data_offset,
+            size: data_size,
+            ..text
+        };
+        let ktext = SectionHeader {
+            name: names[2],
+            addr: KTEXT_ADDRESS,
+            offset: ktext_offset,
+            size: ktext_size,
+            ..text
+        };
+        let symtab = SectionHeader {
+            name: names[3],
+            kind: SHT_SYMTAB,
+            flags: 0,
+            addr: 0,
+            offset: symtab_offset,
+            size: symtab.len() as Binary,
+            link: SHN_STRTAB,
+            info: first_global,
+            entsize: SYMBOL_SIZE,
+            ..text
+        };
+        let strtab = SectionHeader {
+            name: names[4],
+            kind: SHT_STRTAB,
+            offset: strtab_offset,
+            size: strtab.0.len() as Binary,
+            link: 0,
+            info: 0,
+            align: 1,
+            entsize: 0,
+            ..symtab
+        };
+        let shstrtab = SectionHeader {
+            name: names[5],
+            offset: shstrtab_offset,
+            size: shstrtab.0.len() as Binary,
+            ..strtab
+        };
+
+        output.extend([0; SECTION_HEADER_SIZE as usize]);
+        for section in [text, data, ktext, symtab, strtab, shstrtab] {
+            section.write_code(endian, output)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_elf() {
+    use crate::assemble_source_to_program;
+    use crate::read_code;
+    use crate::source::Source;
+
+    let input = r#"
+        .text
+        .globl main
+        main: addi $t0, $zero, %lo(A)
+        L: j L
+        .data
+        A: .word 5
+        "#;
+    let program = assemble_source_to_program(&Source::from_string("main.s", input)).unwrap();
+
+    for endian in [Endian::Little, Endian::Big] {
+        let mut output = Vec::new();
+        program.write_elf(endian, &mut output).unwrap();
+        let word = |offset: usize| read_code(endian, &output[offset..offset + 4]);
+
+        assert_eq!(&output[..4], b"\x7fELF");
+        assert_eq!(output[5], if endian == Endian::Little { 1 } else { 2 });
+        // e_entry
+        assert_eq!(word(24), TEXT_ADDRESS);
+        // First program header loads the text section
+        assert_eq!(word(52), PT_LOAD);
+        assert_eq!(word(52 + 8), TEXT_ADDRESS);
+        assert_eq!(word(52 + 16), 8);
+        // Text section follows the program headers
+        assert_eq!(word(116), program.text[0]);
+        // Data section
+        assert_eq!(word(124), 5);
+
+        // .symtab: null, L and A (local), main (global)
+        let shoff = word(32) as usize;
+        let symtab = shoff + 4 * SECTION_HEADER_SIZE as usize;
+        assert_eq!(word(symtab + 4), SHT_SYMTAB);
+        assert_eq!(word(symtab + 20), 4 * SYMBOL_SIZE);
+        assert_eq!(word(symtab + 28), 3);
+    }
+}
@@ -1,29 +1,23 @@
+use crate::instruction::get_data_section;
 use crate::write_code;
 use crate::BResult;
 use crate::Binary;
 use crate::Endian;
 use crate::FileHeader;
 use crate::Instruction;
-use crate::SectionType;
+use crate::TEXT_ADDRESS;
 
 impl FileHeader {
-    const HEADER_SIZE: Binary = 3;
+    pub const HEADER_SIZE: Binary = 4;
 
     pub fn new(sections: &Vec<Vec<&Instruction>>) -> Self {
         let entry_point = FileHeader::HEADER_SIZE;
         let start_text = FileHeader::HEADER_SIZE;
 
-        let mut text_section = &sections[0];
-
-        for s in &sections[1..] {
-            match s.get(0) {
-                Some(Instruction::Section(SectionType::Text)) => text_section = s,
-                _ => {}
-            }
-        }
-
-        let start_data = text_section
+        let start_data = sections
             .iter()
+            .filter(|s| !s[0].is_ktext())
+            .flatten()
             .filter(|v| match v {
                 Instruction::I { .. } | Instruction::R { .. } | Instruction::J { .. } => true,
                 _ => false,
@@ -31,17 +25,26 @@ impl FileHeader {
             .count() as Binary
             + start_text;
 
+        let start_ktext = get_data_section(sections).len() as Binary + start_data;
+
         Self {
             entry_point,
             start_text,
             start_data,
+            start_ktext,
         }
     }
 
+    /// Address of the entry point once the text section is loaded at `TEXT_ADDRESS`
+    pub fn entry_address(&self) -> Binary {
+        TEXT_ADDRESS + 4 * (self.entry_point - self.start_text)
+    }
+
     pub fn write_code(&self, endian: Endian, output: &mut Vec<u8>) -> BResult<()> {
         write_code(endian, self.entry_point, output)?;
         write_code(endian, self.start_text, output)?;
         write_code(endian, self.start_data, output)?;
+        write_code(endian, self.start_ktext, output)?;
         Ok(())
     }
 }
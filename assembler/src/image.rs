@@ -0,0 +1,188 @@
+use crate::instruction::write_data_section;
+use crate::BResult;
+use crate::Binary;
+use crate::Endian;
+use crate::Program;
+use crate::DATA_ADDRESS;
+use crate::KTEXT_ADDRESS;
+use crate::TEXT_ADDRESS;
+
+/// Bytes in each data record of Intel HEX and S-record images
+const RECORD_SIZE: usize = 16;
+
+/// Words on each line of a Logisim image
+const LOGISIM_WORDS_PER_LINE: usize = 8;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Intel HEX record, whose checksum is the two's complement of the sum of its bytes
+fn ihex_record(kind: u8, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend(data);
+    let checksum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    format!(":{}{:02X}\n", hex(&bytes), checksum.wrapping_neg())
+}
+
+/// S-record, whose checksum is the ones' complement of the sum of its bytes
+fn srec_record(kind: u8, address: &[u8], data: &[u8]) -> String {
+    let mut bytes = vec![(address.len() + data.len() + 1) as u8];
+    bytes.extend(address);
+    bytes.extend(data);
+    let checksum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    format!("S{}{}{:02X}\n", kind, hex(&bytes), !checksum)
+}
+
+impl Program {
+    /// Address, name and words of the sections which are not empty
+    fn sections(&self) -> Vec<(u32, &'static str, &Vec<Binary>)> {
+        [
+            (TEXT_ADDRESS, ".text", &self.text),
+            (DATA_ADDRESS, ".data", &self.data),
+            (KTEXT_ADDRESS, ".ktext", &self.ktext),
+        ]
+        .into_iter()
+        .filter(|(_, _, words)| !words.is_empty())
+        .map(|(address, name, words)| (address as u32, name, words))
+        .collect()
+    }
+
+    /// Address and bytes of each section in `endian`, split into data records
+    fn records(&self, endian: Endian) -> BResult<Vec<(u32, Vec<u8>)>> {
+        let mut records = Vec::new();
+        for (address, _, words) in self.sections() {
+            let mut bytes = Vec::new();
+            write_data_section(endian, words, &mut bytes)?;
+            for (n, chunk) in bytes.chunks(RECORD_SIZE).enumerate() {
+                records.push((address + (n * RECORD_SIZE) as u32, chunk.to_vec()));
+            }
+        }
+        Ok(records)
+    }
+
+    /// Intel HEX image of the sections at their load addresses, with an extended linear
+    /// address record whenever the upper half of the address changes
+    pub fn intel_hex(&self, endian: Endian) -> BResult<String> {
+        let mut output = String::new();
+        let mut upper = None;
+        for (address, bytes) in self.records(endian)? {
+            let high = (address >> 16) as u16;
+            if upper != Some(high) {
+                upper = Some(high);
+                output.push_str(&ihex_record(0x04, 0, &high.to_be_bytes()));
+            }
+            output.push_str(&ihex_record(0x00, address as u16, &bytes));
+        }
+        let entry = self.header.entry_address() as u32;
+        output.push_str(&ihex_record(0x05, 0, &entry.to_be_bytes()));
+        output.push_str(&ihex_record(0x01, 0, &[]));
+        Ok(output)
+    }
+
+    /// Motorola S-record image of the sections at their load addresses, using 32bit addresses
+    pub fn s_record(&self, endian: Endian) -> BResult<String> {
+        let mut output = srec_record(0, &[0, 0], b"mips");
+        let records = self.records(endian)?;
+        for (address, bytes) in records.iter() {
+            output.push_str(&srec_record(3, &address.to_be_bytes(), bytes));
+        }
+        if let Ok(count) = u16::try_from(records.len()) {
+            output.push_str(&srec_record(5, &count.to_be_bytes(), &[]));
+        }
+        let entry = self.header.entry_address() as u32;
+        output.push_str(&srec_record(7, &entry.to_be_bytes(), &[]));
+        Ok(output)
+    }
+
+    /// Verilog `$readmemh` image of word-wide memory, `$readmemb` if `binary`
+    ///
+    /// Each section starts at the word address `@<address / 4>` of its load address.
+    pub fn readmem(&self, binary: bool) -> String {
+        let mut output = String::new();
+        for (address, name, words) in self.sections() {
+            output.push_str(&format!(
+                "// {} {:#010x}\n@{:08x}\n",
+                name,
+                address,
+                address / 4
+            ));
+            for word in words.iter() {
+                if binary {
+                    output.push_str(&format!("{:032b}\n", word));
+                } else {
+                    output.push_str(&format!("{:08x}\n", word));
+                }
+            }
+        }
+        output
+    }
+
+    /// Logisim `v2.0 raw` image of the text section, for a ROM which is addressed by
+    /// `(pc - 0x00400000) / 4`
+    pub fn logisim(&self) -> String {
+        let mut output = "v2.0 raw\n".to_string();
+        for words in self.text.chunks(LOGISIM_WORDS_PER_LINE) {
+            let words: Vec<_> = words.iter().map(|w| format!("{:x}", w)).collect();
+            output.push_str(&words.join(" "));
+            output.push('\n');
+        }
+        output
+    }
+}
+
+#[test]
+fn test_records() {
+    assert_eq!(ihex_record(0x04, 0, &[0x00, 0x40]), ":020000040040BA\n");
+    assert_eq!(ihex_record(0x01, 0, &[]), ":00000001FF\n");
+    assert_eq!(srec_record(0, &[0, 0], b"HDR"), "S00600004844521B\n");
+    assert_eq!(
+        srec_record(3, &[0x00, 0x40, 0x00, 0x00], &[0x01, 0x00, 0x08, 0x20]),
+        "S30900400000010008208D\n"
+    );
+}
+
+#[test]
+fn test_images() {
+    use crate::assemble_source_to_program;
+    use crate::source::Source;
+
+    let input = r#"addi $t0, $zero, 1
+    jr $ra
+.data
+.word 5"#;
+    let program = assemble_source_to_program(&Source::from_string("main.s", input)).unwrap();
+
+    let ihex = program.intel_hex(Endian::Big).unwrap();
+    let lines: Vec<&str> = ihex.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            ":020000040040BA",
+            ":080000002008000103E00008E4",
+            ":020000041001E9",
+            ":0400000000000005F7",
+            ":0400000500400000B7",
+            ":00000001FF",
+        ]
+    );
+
+    let srec = program.s_record(Endian::Little).unwrap();
+    let lines: Vec<&str> = srec.lines().collect();
+    assert_eq!(lines[1], "S30D00400000010008200800E0039E");
+    assert_eq!(lines[2], "S3091001000005000000E0");
+    assert_eq!(lines[3], "S5030002FA");
+    assert_eq!(lines[4], "S70500400000BA");
+
+    assert_eq!(
+        program.readmem(false),
+        "// .text 0x00400000\n@00100000\n20080001\n03e00008\n\
+         // .data 0x10010000\n@04004000\n00000005\n"
+    );
+    assert!(program
+        .readmem(true)
+        .contains("@00100000\n00100000000010000000000000000001\n"));
+    assert_eq!(program.logisim(), "v2.0 raw\n20080001 3e00008\n");
+}
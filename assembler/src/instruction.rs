@@ -1,7 +1,9 @@
 use crate::write_code;
 use crate::BResult;
 use crate::Endian;
-use crate::FileHeader;
+use crate::DATA_ADDRESS;
+use crate::KTEXT_ADDRESS;
+use crate::TEXT_ADDRESS;
 use std::collections::HashMap;
 
 use crate::Binary;
@@ -10,18 +12,37 @@ use crate::Operand;
 use crate::Operation;
 use crate::SectionType;
 
+pub fn hi(address: Binary) -> Binary {
+    (address.wrapping_add(0x8000) >> 16) & 0xffff
+}
+
+pub fn lo(address: Binary) -> Binary {
+    address & 0xffff
+}
+
+fn lookup(symbol_table: &HashMap<&str, Binary>, name: &str) -> Binary {
+    if let Some(b) = symbol_table.get(name) {
+        *b
+    } else {
+        panic!("Label {} is not defined", name);
+    }
+}
+
 impl<'a> Operand<'a> {
     fn to_binary(&self, symbol_table: &HashMap<&str, Binary>) -> Binary {
         match self {
             Operand::Register(b) => *b,
-            Operand::Label(name) => {
-                if let Some(b) = symbol_table.get(name) {
-                    *b
-                } else {
-                    panic!("Label {} is not defined", name);
-                }
-            }
+            Operand::Label(name) => lookup(symbol_table, name),
             Operand::Constant(b) => *b,
+            Operand::Hi(name) => hi(lookup(symbol_table, name)),
+            Operand::Lo(name) => lo(lookup(symbol_table, name)),
+        }
+    }
+
+    pub fn label(&self) -> Option<&'a str> {
+        match self {
+            Operand::Label(name) | Operand::Hi(name) | Operand::Lo(name) => Some(*name),
+            _ => None,
         }
     }
 }
@@ -30,6 +51,26 @@ impl Operation {
     fn to_binary(&self) -> Binary {
         self.0
     }
+
+    /// `beq`, `bne`, `bc1f` and `bc1t`, whose immediate is relative to the next instruction
+    pub fn is_branch(&self) -> bool {
+        self.0 == 0x4 || self.0 == 0x5 || self.0 == 0x11
+    }
+}
+
+/// Offset field of a branch at `address` to `target`
+pub fn branch_offset(address: Binary, target: Binary) -> Binary {
+    (target.wrapping_sub(address + 4) >> 2) & 0xffff
+}
+
+/// Whether `value` fits the 16-bit immediate field, read as signed or as unsigned
+pub fn fits_immediate(value: Binary) -> bool {
+    (-0x8000..=0xffff).contains(&value)
+}
+
+/// Target field of a jump to `target`
+pub fn jump_target(target: Binary) -> Binary {
+    (target >> 2) & 0x3ffffff
 }
 
 pub fn get_data_section(sections: &Vec<Vec<&Instruction>>) -> Vec<Binary> {
@@ -57,6 +98,26 @@ pub fn get_data_section(sections: &Vec<Vec<&Instruction>>) -> Vec<Binary> {
     output
 }
 
+/// Encodes the instructions of the text sections, or of the `.ktext` sections if `kernel`.
+pub fn gen_text_section(
+    sections: &Vec<Vec<&Instruction>>,
+    symbol_table: &HashMap<&str, Binary>,
+    kernel: bool,
+) -> Vec<Binary> {
+    let mut address = if kernel { KTEXT_ADDRESS } else { TEXT_ADDRESS };
+
+    sections
+        .iter()
+        .filter(|s| s[0].is_ktext() == kernel)
+        .flatten()
+        .filter_map(|t| {
+            let code = t.code(address, symbol_table)?;
+            address += 4;
+            Some(code)
+        })
+        .collect()
+}
+
 pub fn write_data_section(
     endian: Endian,
     codes: &Vec<Binary>,
@@ -68,60 +129,69 @@ pub fn write_data_section(
     Ok(())
 }
 
-pub fn gen_symbol_table<'a>(
-    sections: &'a Vec<Vec<&Instruction>>,
-    file_header: &FileHeader,
-) -> HashMap<&'a str, Binary> {
-    let mut table = HashMap::new();
-
-    let mut text_section = &sections[0][1..];
-
-    for s in &sections[1..] {
-        match s.get(0) {
-            Some(Instruction::Section(SectionType::Text)) => text_section = &s[1..],
-            _ => {}
+/// Inserts a `nop` into the delay slot of every branch and jump outside of `.set noreorder`.
+pub fn fill_delay_slots<'a>(
+    lines: Vec<usize>,
+    tokens: Vec<Instruction<'a>>,
+) -> (Vec<usize>, Vec<Instruction<'a>>) {
+    let mut output = (Vec::new(), Vec::new());
+    let mut reorder = true;
+
+    for (line, token) in lines.into_iter().zip(tokens) {
+        if let Instruction::Section(SectionType::Reorder(r)) = token {
+            reorder = r;
         }
-    }
+        let fill = reorder && token.has_delay_slot();
 
-    let mut count = file_header.start_text;
-    for ins in text_section {
-        match ins {
-            Instruction::LabelDef { name } => {
-                table.insert(*name, count);
-            }
-            _ => {
-                count += 1;
-            }
+        output.0.push(line);
+        output.1.push(token);
+        if fill {
+            output.0.push(line);
+            output.1.push(Instruction::nop());
         }
     }
 
-    if sections.len() < 2 {
-        return table;
-    }
+    output
+}
+
+/// Maps labels to their addresses in memory.
+pub fn gen_symbol_table<'a>(sections: &'a Vec<Vec<&Instruction>>) -> HashMap<&'a str, Binary> {
+    let mut table = HashMap::new();
 
-    let mut data_section = &sections[1][1..];
+    let mut text = TEXT_ADDRESS;
+    let mut data = DATA_ADDRESS;
+    let mut ktext = KTEXT_ADDRESS;
 
-    for s in &sections[1..] {
-        match s.get(0) {
-            Some(Instruction::Section(SectionType::Data)) => data_section = &s[1..],
-            _ => {}
-        }
-    }
+    for s in sections {
+        let in_data = matches!(s.get(0), Some(Instruction::Section(SectionType::Data)));
+        let in_ktext = s[0].is_ktext();
 
-    let mut count = file_header.start_data;
-    for ins in data_section {
-        match ins {
-            Instruction::LabelDef { name } => {
-                table.insert(*name, count);
-            }
-            Instruction::Section(SectionType::Word(v)) => {
-                count += v.len() as Binary;
-            }
-            Instruction::Section(SectionType::Space(n)) => {
-                count += *n / 4;
-            }
-            _ => {
-                count += 1;
+        for ins in s {
+            match ins {
+                Instruction::LabelDef { name } if in_data => {
+                    table.insert(*name, data);
+                }
+                Instruction::LabelDef { name } if in_ktext => {
+                    table.insert(*name, ktext);
+                }
+                Instruction::LabelDef { name } => {
+                    table.insert(*name, text);
+                }
+                Instruction::I { .. } | Instruction::R { .. } | Instruction::J { .. }
+                    if in_ktext =>
+                {
+                    ktext += 4;
+                }
+                Instruction::I { .. } | Instruction::R { .. } | Instruction::J { .. } => {
+                    text += 4;
+                }
+                Instruction::Section(SectionType::Word(v)) => {
+                    data += 4 * v.len() as Binary;
+                }
+                Instruction::Section(SectionType::Space(n)) => {
+                    data += *n / 4 * 4;
+                }
+                _ => {}
             }
         }
     }
@@ -156,14 +226,85 @@ impl<'a> Instruction<'a> {
         Self::J { op, ad }
     }
 
-    pub fn code(&self, symbol_table: &HashMap<&str, Binary>) -> Option<Binary> {
+    /// `.text`, `.data` and `.ktext`, which start a new section
+    pub fn starts_section(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Section(SectionType::Text)
+                | Instruction::Section(SectionType::Data)
+                | Instruction::Section(SectionType::KText)
+        )
+    }
+
+    /// `sll $zero, $zero, 0`
+    pub fn nop() -> Self {
+        Self::ri(
+            Operation(0x0),
+            Operand::Register(0x0),
+            Operand::Register(0x0),
+            Operand::Register(0x0),
+            Operand::Constant(0x0),
+            Operand::Constant(0x0),
+        )
+    }
+
+    /// Branches and jumps, which are followed by a delay slot
+    pub fn has_delay_slot(&self) -> bool {
+        match self {
+            Instruction::I { op, .. } => op.is_branch(),
+            Instruction::J { .. } => true,
+            Instruction::R { op, fc, .. } => {
+                op.0 == 0x0 && matches!(fc, Operand::Constant(0x8) | Operand::Constant(0x9))
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_ktext(&self) -> bool {
+        matches!(self, Instruction::Section(SectionType::KText))
+    }
+
+    /// Labels referred to by the operands
+    pub fn labels(&self) -> Vec<&'a str> {
+        let operands = match self {
+            Instruction::I { rs, rt, im, .. } => vec![rs, rt, im],
+            Instruction::R {
+                rs, rt, rd, sh, fc, ..
+            } => vec![rs, rt, rd, sh, fc],
+            Instruction::J { ad, .. } => vec![ad],
+            _ => vec![],
+        };
+
+        operands.into_iter().filter_map(|o| o.label()).collect()
+    }
+
+    /// Label used as the whole immediate, which is not a branch target
+    pub fn immediate_label(&self) -> Option<&'a str> {
+        match self {
+            Instruction::I {
+                op,
+                im: Operand::Label(name),
+                ..
+            } if !op.is_branch() => Some(*name),
+            _ => None,
+        }
+    }
+
+    /// Encodes the instruction placed at `address`.
+    pub fn code(&self, address: Binary, symbol_table: &HashMap<&str, Binary>) -> Option<Binary> {
         let mut code = 0;
         match self {
             Instruction::I { op, rs, rt, im } => {
+                let im = match im {
+                    Operand::Label(_) if op.is_branch() => {
+                        branch_offset(address, im.to_binary(symbol_table))
+                    }
+                    _ => im.to_binary(symbol_table),
+                };
                 code |= op.to_binary() << 26;
                 code |= rs.to_binary(symbol_table) << 21;
                 code |= rt.to_binary(symbol_table) << 16;
-                code |= 0b000000_00000_00000_11111_11111_111111 & im.to_binary(symbol_table);
+                code |= 0b000000_00000_00000_11111_11111_111111 & im;
             }
             Instruction::R {
                 op,
@@ -182,7 +323,7 @@ impl<'a> Instruction<'a> {
             }
             Instruction::J { op, ad } => {
                 code |= op.to_binary() << 26;
-                code |= ad.to_binary(symbol_table);
+                code |= jump_target(ad.to_binary(symbol_table));
             }
             Instruction::LabelDef { .. } => return None,
             Instruction::Section(_) => return None,
@@ -193,7 +334,7 @@ impl<'a> Instruction<'a> {
 
 #[test]
 fn test_label() {
-    use crate::parse;
+    use crate::parser::parse;
     let input = r#"
         .text
         L1: addi $t0, $zero, L3
@@ -209,23 +350,98 @@ fn test_label() {
     } else {
         tokens.insert(0, Instruction::Section(SectionType::Text));
     }
-    let sections = tokens.split_rinclusive(|t| match t {
-        Instruction::Section(SectionType::Text) | Instruction::Section(SectionType::Data) => false,
-        _ => true,
-    });
-    let file_header = FileHeader::new(&sections);
-    let symbol_table = gen_symbol_table(&sections, &file_header);
-
-    assert_eq!(symbol_table.get("L1"), Some(&3));
-    assert_eq!(symbol_table.get("L2"), Some(&4));
-    assert_eq!(symbol_table.get("L3"), Some(&5));
-    assert_eq!(symbol_table.get("L4"), Some(&10));
-    assert_eq!(symbol_table.get("L5"), Some(&14));
+    let sections = tokens.split_rinclusive(|t| !t.starts_section());
+    let symbol_table = gen_symbol_table(&sections);
+
+    assert_eq!(symbol_table.get("L1"), Some(&0x00400000));
+    assert_eq!(symbol_table.get("L2"), Some(&0x00400004));
+    assert_eq!(symbol_table.get("L3"), Some(&0x10010000));
+    assert_eq!(symbol_table.get("L4"), Some(&0x10010014));
+    assert_eq!(symbol_table.get("L5"), Some(&0x10010024));
+}
+
+#[test]
+fn test_label_multiple_sections() {
+    use crate::parser::parse;
+    let input = r#"
+        .text
+        .globl main
+        main: addi $t0, $zero, L3
+        .data
+        L3: .word 1, 2
+        .text
+        L1: addi $t0, $zero, L4
+        .data
+        L4: .space 8
+        "#;
+
+    let tokens = parse(input).unwrap();
+    let sections = tokens.split_rinclusive(|t| !t.starts_section());
+    let symbol_table = gen_symbol_table(&sections);
+
+    assert_eq!(symbol_table.get("main"), Some(&0x00400000));
+    assert_eq!(symbol_table.get("L1"), Some(&0x00400004));
+    assert_eq!(symbol_table.get("L3"), Some(&0x10010000));
+    assert_eq!(symbol_table.get("L4"), Some(&0x10010008));
+}
+
+#[test]
+fn test_ktext_section() {
+    use crate::parser::parse;
+    let input = r#"
+        .text
+        main: j main
+        .ktext
+        handler: addi $k0, $zero, 1
+        eret
+        .text
+        L1: j main
+        "#;
+
+    let tokens = parse(input).unwrap();
+    let sections = tokens.split_rinclusive(|t| !t.starts_section());
+    let symbol_table = gen_symbol_table(&sections);
+
+    assert_eq!(symbol_table.get("main"), Some(&0x00400000));
+    assert_eq!(symbol_table.get("L1"), Some(&0x00400004));
+    assert_eq!(symbol_table.get("handler"), Some(&KTEXT_ADDRESS));
+
+    let text = gen_text_section(&sections, &symbol_table, false);
+    let ktext = gen_text_section(&sections, &symbol_table, true);
+    assert_eq!(text.len(), 2);
+    assert_eq!(text[1], (0x2 << 26) | jump_target(0x00400000));
+    assert_eq!(ktext, vec![(0x8 << 26) | (26 << 16) | 1, 0x42000018]);
+}
+
+#[test]
+fn test_fill_delay_slots() {
+    use crate::parser::parse;
+    let input = r#"
+        L1: beq $t0, $zero, L1
+        addi $t0, $t0, 1
+        .set noreorder
+        j L1
+        addi $t0, $t0, 2
+        .set reorder
+        jr $ra
+        "#;
+
+    let tokens = parse(input).unwrap();
+    let lines = (0..tokens.len()).collect();
+    let (lines, tokens) = fill_delay_slots(lines, tokens);
+
+    assert_eq!(tokens[2], Instruction::nop());
+    assert_eq!(lines[2], 1);
+    assert!(tokens[5].has_delay_slot());
+    assert_ne!(tokens[6], Instruction::nop());
+    assert!(tokens[8].has_delay_slot());
+    assert_eq!(tokens[9], Instruction::nop());
+    assert_eq!(tokens.len(), 10);
 }
 
 #[test]
 fn test_data_section() {
-    use crate::parse;
+    use crate::parser::parse;
     let input = r#"
         .text
         L1: addi $t0, $zero, L3
@@ -237,10 +453,7 @@ fn test_data_section() {
         "#;
 
     let tokens = parse(input).unwrap();
-    let sections = tokens.split_rinclusive(|t| match t {
-        Instruction::Section(SectionType::Text) | Instruction::Section(SectionType::Data) => false,
-        _ => true,
-    });
+    let sections = tokens.split_rinclusive(|t| !t.starts_section());
 
     let data = get_data_section(&sections);
 
@@ -1,18 +1,42 @@
+pub mod debug;
+pub mod elf;
 pub mod header;
+pub mod image;
 pub mod instruction;
+pub mod link;
+pub mod listing;
+pub mod macros;
+pub mod object;
 pub mod parser;
+pub mod program;
+pub mod pseudo;
+pub mod source;
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 
-use instruction::{gen_symbol_table, get_data_section, write_data_section, SplitRInclusive};
-use parser::parse;
+use debug::{gen_line_table, LineTable};
+use instruction::{
+    fill_delay_slots, fits_immediate, gen_symbol_table, gen_text_section, get_data_section,
+    SplitRInclusive,
+};
+use object::{Object, Symbol, SymbolSection};
+use parser::parse_lines;
+use source::Source;
 
 pub type BResult<T> = Result<T, Box<dyn Error>>;
 pub type Binary = i32;
 
+/// Address at which the text section is loaded
+pub const TEXT_ADDRESS: Binary = 0x0040_0000;
+/// Address at which the data section is loaded
+pub const DATA_ADDRESS: Binary = 0x1001_0000;
+/// Address at which the kernel text section is loaded, the exception vector
+pub const KTEXT_ADDRESS: Binary = 0x8000_0180u32 as Binary;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Endian {
     Little,
@@ -23,16 +47,41 @@ pub enum Endian {
 pub enum SectionType {
     Text,
     Data,
+    /// `.ktext`: exception handlers
+    KText,
     Word(Vec<Binary>),
     Space(Binary),
     Globl(Vec<String>),
+    /// `.set reorder` (true) and `.set noreorder` (false)
+    Reorder(bool),
+}
+
+/// Options of the assembler
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct AssembleOptions {
+    /// Fill the delay slot of every branch and jump with a `nop`, except after `.set noreorder`
+    pub delay_slots: bool,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct FileHeader {
-    entry_point: Binary,
-    start_text: Binary,
-    start_data: Binary,
+    pub entry_point: Binary,
+    pub start_text: Binary,
+    pub start_data: Binary,
+    pub start_ktext: Binary,
+}
+
+/// Assembled program with the labels defined in it
+#[derive(Debug, PartialEq)]
+pub struct Program {
+    pub header: FileHeader,
+    pub text: Vec<Binary>,
+    pub data: Vec<Binary>,
+    pub ktext: Vec<Binary>,
+    /// Offsets are relative to the start of each section.
+    pub symbols: Vec<Symbol>,
+    /// Source locations of the instructions, empty for a linked program
+    pub lines: LineTable,
 }
 
 #[derive(Debug, PartialEq)]
@@ -40,6 +89,10 @@ pub enum Operand<'a> {
     Register(Binary),
     Label(&'a str),
     Constant(Binary),
+    /// `%hi(label)`: upper half of the address, adjusted for the sign of `%lo`
+    Hi(&'a str),
+    /// `%lo(label)`: lower half of the address
+    Lo(&'a str),
 }
 
 #[derive(Debug, PartialEq)]
@@ -79,52 +132,216 @@ fn write_code(endian: Endian, code: Binary, output: &mut Vec<u8>) -> BResult<()>
     Ok(())
 }
 
-pub fn assemble_to_u8_from_string(endian: Endian, source: String) -> BResult<Vec<u8>> {
-    let mut output = Vec::new();
+fn read_code(endian: Endian, input: &[u8]) -> Binary {
+    let bytes = [input[0], input[1], input[2], input[3]];
+    match endian {
+        Endian::Big => Binary::from_be_bytes(bytes),
+        Endian::Little => Binary::from_le_bytes(bytes),
+    }
+}
 
-    // Parse input data
-    let mut tokens = parse(&source)?;
+/// Reports labels which are defined twice.
+fn check_duplicates(source: &Source, lines: &[usize], tokens: &[Instruction]) -> BResult<()> {
+    let mut defined = HashMap::new();
+
+    for (line, token) in lines.iter().zip(tokens.iter()) {
+        if let Instruction::LabelDef { name } = token {
+            if let Some(first) = defined.insert(*name, *line) {
+                let first = source.error(first, "first defined here");
+                return Err(source
+                    .error(
+                        *line,
+                        &format!("label {} is already defined ({})", name, first),
+                    )
+                    .into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reports labels which are never defined.
+fn check_undefined(
+    source: &Source,
+    lines: &[usize],
+    tokens: &[Instruction],
+    symbol_table: &HashMap<&str, Binary>,
+) -> BResult<()> {
+    for (line, token) in lines.iter().zip(tokens.iter()) {
+        for name in token.labels() {
+            if !symbol_table.contains_key(name) {
+                return Err(source
+                    .error(*line, &format!("label {} is not defined", name))
+                    .into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reports labels used as immediates whose address does not fit in 16 bits.
+fn check_immediates(
+    source: &Source,
+    lines: &[usize],
+    tokens: &[Instruction],
+    symbol_table: &HashMap<&str, Binary>,
+) -> BResult<()> {
+    for (line, token) in lines.iter().zip(tokens.iter()) {
+        if let Some(name) = token.immediate_label() {
+            let address = symbol_table[name];
+            if !fits_immediate(address) {
+                let message = format!(
+                    "address {:#010x} of label {} does not fit in 16 bits, use la or %hi/%lo",
+                    address, name
+                );
+                return Err(source.error(*line, &message).into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the expanded `text` of `source`, returning the line of each instruction.
+fn parse_source<'a>(
+    source: &Source,
+    text: &'a str,
+    options: &AssembleOptions,
+) -> BResult<(Vec<usize>, Vec<Instruction<'a>>)> {
+    let (mut lines, mut tokens): (Vec<usize>, Vec<Instruction>) = parse_lines(text)
+        .map_err(|(line, rest)| {
+            let rest = rest.lines().next().unwrap_or_default();
+            source.error(line, &format!("unexpected \"{}\"", rest))
+        })?
+        .into_iter()
+        .unzip();
     if let Some(Instruction::Section(SectionType::Text)) = tokens.get(0) {
     } else {
         tokens.insert(0, Instruction::Section(SectionType::Text));
+        lines.insert(0, 0);
     }
+    if options.delay_slots {
+        (lines, tokens) = fill_delay_slots(lines, tokens);
+    }
+
+    check_duplicates(source, &lines, &tokens)?;
+
+    Ok((lines, tokens))
+}
+
+pub fn assemble_source_to_program(source: &Source) -> BResult<Program> {
+    assemble_source_to_program_with(source, &AssembleOptions::default())
+}
+
+pub fn assemble_source_to_program_with(
+    source: &Source,
+    options: &AssembleOptions,
+) -> BResult<Program> {
+    // Expand macros
+    let original = source;
+    let source = source.expand_macros()?;
+    let text = source.text();
+
+    // Parse input data
+    let (lines, tokens) = parse_source(&source, &text, options)?;
 
     // Split each section
-    let sections = tokens.split_rinclusive(|t| match t {
-        Instruction::Section(SectionType::Text) | Instruction::Section(SectionType::Data) => false,
-        _ => true,
-    });
+    let sections = tokens.split_rinclusive(|t| !t.starts_section());
 
     // Create file header
     let file_header = FileHeader::new(&sections);
 
     // Gen symbol table
-    let symbol_table = gen_symbol_table(&sections, &file_header);
+    let symbol_table = gen_symbol_table(&sections);
+    check_undefined(&source, &lines, &tokens, &symbol_table)?;
+    check_immediates(&source, &lines, &tokens, &symbol_table)?;
 
-    // Write file header
-    file_header.write_code(endian, &mut output)?;
-
-    // Write text section
-    for code in tokens.iter().filter_map(|t| t.code(&symbol_table)) {
-        write_code(endian, code, &mut output)?;
-    }
+    // Gen text sections
+    let text = gen_text_section(&sections, &symbol_table, false);
+    let ktext = gen_text_section(&sections, &symbol_table, true);
 
-    // Gen global data and write data section
+    // Gen global data
     let data = get_data_section(&sections);
-    write_data_section(endian, &data, &mut output)?;
+
+    let lines = gen_line_table(original, &source, &lines, &tokens);
+
+    let symbols = Object::new(&tokens)
+        .symbols
+        .into_iter()
+        .filter(|s| s.section != SymbolSection::Undefined)
+        .collect();
+
+    Ok(Program {
+        header: file_header,
+        text,
+        data,
+        ktext,
+        symbols,
+        lines,
+    })
+}
+
+pub fn assemble_source(endian: Endian, source: &Source) -> BResult<Vec<u8>> {
+    let mut output = Vec::new();
+    assemble_source_to_program(source)?.write_code(endian, &mut output)?;
 
     Ok(output)
 }
 
+/// Assembles `source` into a relocatable object.
+///
+/// Labels which are not defined are left to the linker.
+pub fn assemble_source_to_object(source: &Source) -> BResult<Object> {
+    assemble_source_to_object_with(source, &AssembleOptions::default())
+}
+
+pub fn assemble_source_to_object_with(
+    source: &Source,
+    options: &AssembleOptions,
+) -> BResult<Object> {
+    let source = source.expand_macros()?;
+    let text = source.text();
+    let (_, tokens) = parse_source(&source, &text, options)?;
+
+    Ok(Object::new(&tokens))
+}
+
+pub fn assemble_files_to_object<P: AsRef<Path>, Q: AsRef<Path>>(
+    inputs: &[P],
+    include_dirs: &[Q],
+) -> BResult<Object> {
+    assemble_source_to_object(&Source::load(inputs, include_dirs)?)
+}
+
+pub fn assemble_files_to_program<P: AsRef<Path>, Q: AsRef<Path>>(
+    inputs: &[P],
+    include_dirs: &[Q],
+) -> BResult<Program> {
+    assemble_source_to_program(&Source::load(inputs, include_dirs)?)
+}
+
+pub fn assemble_to_u8_from_string(endian: Endian, source: String) -> BResult<Vec<u8>> {
+    assemble_source(endian, &Source::from_string("<input>", &source))
+}
+
+/// Assembles `inputs` into one program with a shared symbol table.
+///
+/// `.include` searches the directory of the including file and then `include_dirs`.
+pub fn assemble_files_to_u8<P: AsRef<Path>, Q: AsRef<Path>>(
+    endian: Endian,
+    inputs: &[P],
+    include_dirs: &[Q],
+) -> BResult<Vec<u8>> {
+    assemble_source(endian, &Source::load(inputs, include_dirs)?)
+}
+
 pub fn assemble_to_u8<P: AsRef<Path> + std::fmt::Display>(
     endian: Endian,
     input: P,
 ) -> BResult<Vec<u8>> {
-    let mut source = String::new();
-    let mut input = File::open(input)?;
-    input.read_to_string(&mut source)?;
-
-    assemble_to_u8_from_string(endian, source)
+    assemble_files_to_u8::<P, &str>(endian, &[input], &[])
 }
 
 pub fn assemble<P: AsRef<Path> + std::fmt::Display>(
@@ -138,3 +355,16 @@ pub fn assemble<P: AsRef<Path> + std::fmt::Display>(
 
     Ok(())
 }
+
+pub fn assemble_files<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
+    endian: Endian,
+    inputs: &[P],
+    include_dirs: &[Q],
+    output: R,
+) -> BResult<()> {
+    let data = assemble_files_to_u8(endian, inputs, include_dirs)?;
+    let mut output = File::create(output)?;
+    output.write_all(&data)?;
+
+    Ok(())
+}
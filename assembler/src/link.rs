@@ -0,0 +1,279 @@
+use crate::debug::LineTable;
+use crate::instruction::fits_immediate;
+use crate::object::{Object, RelocationType, Symbol, SymbolSection};
+use crate::BResult;
+use crate::Binary;
+use crate::Endian;
+use crate::FileHeader;
+use crate::Program;
+use crate::DATA_ADDRESS;
+use crate::KTEXT_ADDRESS;
+use crate::TEXT_ADDRESS;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+/// Start of the text, data and kernel text sections of an object in the output, in words
+struct Layout {
+    text: Binary,
+    data: Binary,
+    ktext: Binary,
+}
+
+impl Layout {
+    fn offset(&self, section: SymbolSection, offset: Binary) -> Option<Binary> {
+        match section {
+            SymbolSection::Text => Some(self.text + offset),
+            SymbolSection::Data => Some(self.data + offset),
+            SymbolSection::KText => Some(self.ktext + offset),
+            SymbolSection::Undefined => None,
+        }
+    }
+
+    fn address(&self, section: SymbolSection, offset: Binary) -> Option<Binary> {
+        match section {
+            SymbolSection::Text => Some(TEXT_ADDRESS + 4 * (self.text + offset)),
+            SymbolSection::Data => Some(DATA_ADDRESS + 4 * (self.data + offset)),
+            SymbolSection::KText => Some(KTEXT_ADDRESS + 4 * (self.ktext + offset)),
+            SymbolSection::Undefined => None,
+        }
+    }
+}
+
+/// Links `objects`, each paired with a name used in diagnostics, into a program.
+///
+/// Text sections are placed in order followed by data sections and kernel text sections.
+/// The entry point is the global symbol `main`, or the start of the text section.
+pub fn link_program<S: AsRef<str>>(objects: &[(S, Object)]) -> BResult<Program> {
+    let start_text = FileHeader::HEADER_SIZE;
+    let start_data = start_text
+        + objects
+            .iter()
+            .map(|(_, o)| o.text.len() as Binary)
+            .sum::<Binary>();
+
+    let mut layouts = Vec::new();
+    let (mut text, mut data, mut ktext) = (0, 0, 0);
+    for (_, object) in objects {
+        layouts.push(Layout { text, data, ktext });
+        text += object.text.len() as Binary;
+        data += object.data.len() as Binary;
+        ktext += object.ktext.len() as Binary;
+    }
+    let start_ktext = start_data + data;
+
+    let mut errors = Vec::new();
+
+    // Resolve global symbols
+    let mut globals: HashMap<&str, (Binary, &str)> = HashMap::new();
+    for ((name, object), layout) in objects.iter().zip(layouts.iter()) {
+        for symbol in object.symbols.iter().filter(|s| s.global) {
+            if let Some(address) = layout.address(symbol.section, symbol.offset) {
+                if let Some((_, first)) = globals.insert(&symbol.name, (address, name.as_ref())) {
+                    errors.push(format!(
+                        "duplicate symbol {} in {} and {}",
+                        symbol.name,
+                        first,
+                        name.as_ref()
+                    ));
+                }
+            }
+        }
+    }
+
+    // Apply relocations
+    let mut text = Vec::new();
+    let mut ktext = Vec::new();
+    for ((name, object), layout) in objects.iter().zip(layouts.iter()) {
+        let base = text.len();
+        let kbase = ktext.len();
+        text.extend(object.text.iter());
+        ktext.extend(object.ktext.iter());
+
+        for relocation in object.relocations.iter() {
+            let symbol = &object.symbols[relocation.symbol];
+            let address = match layout.address(symbol.section, symbol.offset) {
+                Some(address) => address,
+                None => match globals.get(symbol.name.as_str()) {
+                    Some((address, _)) => *address,
+                    None => {
+                        let error = format!(
+                            "undefined symbol {} referenced in {}",
+                            symbol.name,
+                            name.as_ref()
+                        );
+                        if !errors.contains(&error) {
+                            errors.push(error);
+                        }
+                        continue;
+                    }
+                },
+            };
+
+            if relocation.kind == RelocationType::Absolute && !fits_immediate(address) {
+                errors.push(format!(
+                    "address {:#010x} of symbol {} does not fit in 16 bits in {}, use la or %hi/%lo",
+                    address,
+                    symbol.name,
+                    name.as_ref()
+                ));
+                continue;
+            }
+
+            let offset = relocation.offset as usize;
+            let code = match relocation.section {
+                SymbolSection::Text if offset < object.text.len() => Some(&mut text[base + offset]),
+                SymbolSection::KText if offset < object.ktext.len() => {
+                    Some(&mut ktext[kbase + offset])
+                }
+                _ => None,
+            };
+            match (code, layout.address(relocation.section, relocation.offset)) {
+                (Some(code), Some(pc)) => *code = relocation.kind.apply(*code, pc, address),
+                _ => errors.push(format!("invalid relocation in {}", name.as_ref())),
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n").into());
+    }
+
+    let header = FileHeader {
+        entry_point: globals
+            .get("main")
+            .map(|(a, _)| start_text + (*a - TEXT_ADDRESS) / 4)
+            .unwrap_or(start_text),
+        start_text,
+        start_data,
+        start_ktext,
+    };
+
+    let mut symbols = Vec::new();
+    for ((_, object), layout) in objects.iter().zip(layouts.iter()) {
+        for symbol in object.symbols.iter() {
+            if let Some(offset) = layout.offset(symbol.section, symbol.offset) {
+                symbols.push(Symbol {
+                    offset,
+                    ..symbol.clone()
+                });
+            }
+        }
+    }
+
+    Ok(Program {
+        header,
+        text,
+        data: objects
+            .iter()
+            .flat_map(|(_, o)| o.data.iter().copied())
+            .collect(),
+        ktext,
+        symbols,
+        lines: LineTable::default(),
+    })
+}
+
+pub fn link<S: AsRef<str>>(endian: Endian, objects: &[(S, Object)]) -> BResult<Vec<u8>> {
+    let mut output = Vec::new();
+    link_program(objects)?.write_code(endian, &mut output)?;
+
+    Ok(output)
+}
+
+pub fn link_files<P: AsRef<Path>>(inputs: &[P]) -> BResult<Program> {
+    let mut objects = Vec::new();
+
+    for input in inputs {
+        let name = input.as_ref().display().to_string();
+        let mut buffer = Vec::new();
+        File::open(input)?.read_to_end(&mut buffer)?;
+        let object = Object::from_u8(&buffer).map_err(|e| format!("{}: {}", name, e))?;
+        objects.push((name, object));
+    }
+
+    link_program(&objects)
+}
+
+#[test]
+fn test_link() {
+    use crate::parser::parse;
+    let main = parse(
+        r#"
+        .globl main
+        main: j print
+        .data
+        A: .word 7
+        "#,
+    )
+    .unwrap();
+    let print = parse(
+        r#"
+        .globl print
+        print: addi $a0, $zero, %lo(A)
+        syscall
+        .data
+        A: .word 8
+        "#,
+    )
+    .unwrap();
+
+    let objects = [
+        ("main.o", Object::new(&main)),
+        ("print.o", Object::new(&print)),
+    ];
+    let output = link(Endian::Big, &objects).unwrap();
+    let words: Vec<Binary> = output
+        .chunks(4)
+        .map(|c| crate::read_code(Endian::Big, c))
+        .collect();
+
+    // Header
+    assert_eq!(&words[..4], &[4, 4, 7, 9]);
+    // j print
+    assert_eq!(words[4], (0x2 << 26) | (0x00400004 >> 2));
+    // addi $a0, $zero, %lo(A) refers to the local A of print.o
+    assert_eq!(words[5] & 0xffff, 4);
+    // Data
+    assert_eq!(&words[7..], &[7, 8]);
+}
+
+#[test]
+fn test_link_errors() {
+    use crate::parser::parse;
+    let a = parse(".globl main\nmain: j missing").unwrap();
+    let b = parse(".globl main\nmain: syscall").unwrap();
+
+    let objects = [("a.o", Object::new(&a)), ("b.o", Object::new(&b))];
+    let err = link(Endian::Little, &objects).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "duplicate symbol main in a.o and b.o\nundefined symbol missing referenced in a.o"
+    );
+}
+
+#[test]
+fn test_link_absolute() {
+    use crate::parser::parse;
+    let object =
+        Object::new(&parse(".globl main\nmain: addi $t0, $zero, A\n.data\nA: .word 1").unwrap());
+    let err = link(Endian::Little, &[("a.o", object)]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "address 0x10010000 of symbol A does not fit in 16 bits in a.o, use la or %hi/%lo"
+    );
+}
+
+#[test]
+fn test_link_invalid_relocation() {
+    use crate::parser::parse;
+    let mut object = Object::new(&parse(".globl main\nmain: j main").unwrap());
+    object.relocations[0].offset = 100;
+    let err = link(Endian::Little, &[("a.o", object.clone())]).unwrap_err();
+    assert_eq!(err.to_string(), "invalid relocation in a.o");
+
+    object.relocations[0].offset = 0;
+    object.relocations[0].section = SymbolSection::Data;
+    assert!(link(Endian::Little, &[("a.o", object)]).is_err());
+}
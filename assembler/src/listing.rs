@@ -0,0 +1,131 @@
+use crate::object::SymbolSection;
+use crate::source::Source;
+use crate::Program;
+use crate::DATA_ADDRESS;
+use crate::KTEXT_ADDRESS;
+use crate::TEXT_ADDRESS;
+use std::collections::BTreeMap;
+
+/// Width of the address and code columns, which are blank for lines without instructions
+const BLANK: &str = "                      ";
+
+impl Program {
+    /// Word of the text or kernel text section at `address`
+    fn instruction_at(&self, address: u32) -> Option<u32> {
+        let (base, section) = if address >= KTEXT_ADDRESS as u32 {
+            (KTEXT_ADDRESS as u32, &self.ktext)
+        } else {
+            (TEXT_ADDRESS as u32, &self.text)
+        };
+        let index = address.checked_sub(base)? / 4;
+        section.get(index as usize).map(|word| *word as u32)
+    }
+
+    /// Assembler listing of the program assembled from `source`: the address and encoding of
+    /// every instruction next to the line it comes from, followed by the data section and the
+    /// symbol table.
+    ///
+    /// ```text
+    /// 0x00400000 0x3c081001     1  main: la $t0, A
+    /// 0x00400004 0x25080000
+    ///                           2  .data
+    /// ```
+    pub fn listing(&self, source: &Source) -> String {
+        let mut addresses: BTreeMap<(usize, usize), Vec<u32>> = BTreeMap::new();
+        for (address, position) in self.lines.lines.iter() {
+            addresses
+                .entry((position.file, position.line))
+                .or_default()
+                .push(*address);
+        }
+
+        let mut output = String::new();
+        let mut file = None;
+        for (location, text) in source.lines.iter() {
+            if file != Some(location.file) {
+                file = Some(location.file);
+                output.push_str(&format!("{}\n", source.files[location.file].display()));
+            }
+            // The `.text` which starts each input file is not in the file
+            if location.line == 0 {
+                continue;
+            }
+            let lines = addresses
+                .get(&(location.file, location.line))
+                .map(|a| a.as_slice())
+                .unwrap_or_default();
+            let mut codes = lines
+                .iter()
+                .filter_map(|address| Some((*address, self.instruction_at(*address)?)));
+            match codes.next() {
+                Some((address, code)) => output.push_str(&format!(
+                    "{:#010x} {:#010x} {:>5}  {}\n",
+                    address, code, location.line, text
+                )),
+                None => output.push_str(&format!("{}{:>5}  {}\n", BLANK, location.line, text)),
+            }
+            // Instructions expanded from the line, e.g. by `la` or a macro
+            for (address, code) in codes {
+                output.push_str(&format!("{:#010x} {:#010x}\n", address, code));
+            }
+        }
+
+        if !self.data.is_empty() {
+            output.push_str("\nData\n");
+            for (n, words) in self.data.chunks(4).enumerate() {
+                let words: Vec<_> = words.iter().map(|w| format!("{:#010x}", w)).collect();
+                output.push_str(&format!(
+                    "{:#010x} {}\n",
+                    DATA_ADDRESS as u32 + 16 * n as u32,
+                    words.join(" ")
+                ));
+            }
+        }
+
+        output.push_str("\nSymbols\n");
+        let mut symbols: Vec<_> = self
+            .symbols
+            .iter()
+            .filter_map(|symbol| {
+                let (base, section) = match symbol.section {
+                    SymbolSection::Text => (TEXT_ADDRESS, ".text"),
+                    SymbolSection::Data => (DATA_ADDRESS, ".data"),
+                    SymbolSection::KText => (KTEXT_ADDRESS, ".ktext"),
+                    SymbolSection::Undefined => return None,
+                };
+                Some(((base + 4 * symbol.offset) as u32, section, symbol))
+            })
+            .collect();
+        symbols.sort_by_key(|(address, _, _)| *address);
+        for (address, section, symbol) in symbols {
+            let global = if symbol.global { " global" } else { "" };
+            output.push_str(&format!(
+                "{:#010x} {:<7} {}{}\n",
+                address, section, symbol.name, global
+            ));
+        }
+        output
+    }
+}
+
+#[test]
+fn test_listing() {
+    use crate::assemble_source_to_program;
+
+    let input = r#"main: la $t0, A
+    lw $a0, 0($t0)
+    jr $ra
+.data
+A: .word 5, 6"#;
+    let source = Source::from_string("main.s", input);
+    let program = assemble_source_to_program(&source).unwrap();
+    let listing = program.listing(&source);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[0], "main.s");
+    assert_eq!(lines[1], "0x00400000 0x3c081001     1  main: la $t0, A");
+    assert_eq!(lines[2], "0x00400004 0x25080000");
+    assert_eq!(lines[3], "0x00400008 0x8d040000     2      lw $a0, 0($t0)");
+    assert_eq!(lines[5], "                          4  .data");
+    assert!(listing.contains("\nData\n0x10010000 0x00000005 0x00000006\n"));
+    assert!(listing.ends_with("Symbols\n0x00400000 .text   main\n0x10010000 .data   A\n"));
+}
@@ -0,0 +1,340 @@
+use crate::pseudo;
+use std::collections::HashMap;
+
+const MAX_DEPTH: usize = 64;
+
+/// `%hi` and `%lo`, which are left for the parser when they are not parameters
+const OPERATORS: [&str; 2] = ["hi", "lo"];
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+    labels: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct Macros {
+    table: HashMap<(String, usize), Macro>,
+    counter: usize,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(idx) => &line[..idx],
+        None => line,
+    }
+}
+
+fn ident(i: &str) -> (&str, &str) {
+    let end = i.find(|c| !is_ident_char(c)).unwrap_or(i.len());
+    (&i[..end], &i[end..])
+}
+
+/// Returns the rest of `code` if it starts with the directive `name`.
+fn directive<'a>(code: &'a str, name: &str) -> Option<&'a str> {
+    let rest = code.strip_prefix(name)?;
+    match rest.chars().next() {
+        Some(c) if is_ident_char(c) => None,
+        _ => Some(rest),
+    }
+}
+
+/// Splits `label: rest` into the label and the rest of the line.
+fn split_label(code: &str) -> (Option<&str>, &str) {
+    let (name, rest) = ident(code.trim_start());
+    match rest.strip_prefix(':') {
+        Some(rest) if !name.is_empty() => (Some(name), rest),
+        _ => (None, code),
+    }
+}
+
+/// Parses `name(%a, %b)` or `name %a, %b`.
+fn parse_arguments(i: &str) -> Result<Vec<String>, String> {
+    let i = i.trim();
+    let inner = match i.strip_prefix('(') {
+        Some(rest) => rest
+            .strip_suffix(')')
+            .ok_or_else(|| format!("expected ')' in {}", i))?,
+        None => i,
+    };
+
+    Ok(inner
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|a| !a.is_empty())
+        .map(|a| a.to_string())
+        .collect())
+}
+
+fn parse_header(i: &str) -> Result<(String, Vec<String>), String> {
+    let (name, rest) = ident(i.trim_start());
+    if name.is_empty() {
+        return Err("expected macro name".into());
+    }
+
+    let mut params = Vec::new();
+    for p in parse_arguments(rest)? {
+        match p.strip_prefix('%') {
+            Some(p) if !p.is_empty() && p.chars().all(is_ident_char) => params.push(p.to_string()),
+            _ => return Err(format!("invalid macro parameter {}", p)),
+        }
+    }
+
+    Ok((name.to_string(), params))
+}
+
+fn parse_call(code: &str) -> Option<(&str, Vec<String>)> {
+    let (name, rest) = ident(code.trim_start());
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    match rest.chars().next() {
+        None | Some('(') => {}
+        Some(c) if c.is_whitespace() => {}
+        _ => return None,
+    }
+
+    Some((name, parse_arguments(rest).ok()?))
+}
+
+impl Macro {
+    /// Replaces parameters with `args` and renames the labels defined in the body.
+    fn substitute(&self, line: &str, args: &[String], suffix: &str) -> Result<String, String> {
+        let mut output = String::new();
+        let mut i = line;
+
+        while let Some(c) = i.chars().next() {
+            if c == '#' {
+                output.push_str(i);
+                break;
+            } else if c == '%' {
+                let (name, rest) = ident(&i[1..]);
+                match self.params.iter().position(|p| p == name) {
+                    Some(idx) => output.push_str(&args[idx]),
+                    None if OPERATORS.contains(&name) => {
+                        output.push('%');
+                        output.push_str(name);
+                    }
+                    None => return Err(format!("undefined macro parameter %{}", name)),
+                }
+                i = rest;
+            } else if is_ident_char(c) {
+                let (name, rest) = ident(i);
+                output.push_str(name);
+                if !output[..output.len() - name.len()].ends_with('$')
+                    && self.labels.iter().any(|l| l == name)
+                {
+                    output.push_str(suffix);
+                }
+                i = rest;
+            } else {
+                output.push(c);
+                i = &i[c.len_utf8()..];
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+impl Macros {
+    fn define(&mut self, name: String, params: Vec<String>, body: Vec<String>) {
+        let labels = body
+            .iter()
+            .filter_map(|l| split_label(strip_comment(l)).0)
+            .map(|l| l.to_string())
+            .collect();
+
+        self.table.insert(
+            (name, params.len()),
+            Macro {
+                params,
+                body,
+                labels,
+            },
+        );
+    }
+
+    fn expand(
+        &mut self,
+        origin: usize,
+        line: &str,
+        depth: usize,
+        output: &mut Vec<(usize, String)>,
+    ) -> Result<(), String> {
+        let (label, code) = split_label(strip_comment(line));
+
+        let call = parse_call(code).and_then(|(name, args)| {
+            let m = self.table.get(&(name.to_string(), args.len()))?;
+            Some((m.clone(), args))
+        });
+        let (m, args) = match (call, pseudo::expand(code)) {
+            (Some(call), _) => call,
+            (None, Some(lines)) => {
+                if let Some(label) = label {
+                    output.push((origin, format!("{}:", label)));
+                }
+                output.extend(lines.into_iter().map(|l| (origin, l)));
+                return Ok(());
+            }
+            (None, None) => {
+                output.push((origin, line.to_string()));
+                return Ok(());
+            }
+        };
+
+        if depth >= MAX_DEPTH {
+            return Err("macro expansion is too deep".into());
+        }
+
+        if let Some(label) = label {
+            output.push((origin, format!("{}:", label)));
+        }
+
+        let suffix = format!("_M{}", self.counter);
+        self.counter += 1;
+
+        for l in m.body.iter() {
+            let l = m.substitute(l, &args, &suffix)?;
+            self.expand(origin, &l, depth + 1, output)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Expands macro definitions and invocations in `lines`.
+///
+/// Each output line is paired with the index of the input line it originates from.
+/// On failure the index of the offending line is returned with the message.
+pub fn expand(lines: &[&str]) -> Result<Vec<(usize, String)>, (usize, String)> {
+    let mut macros = Macros::default();
+    let mut output = Vec::new();
+    let mut lines = lines.iter().enumerate();
+
+    while let Some((n, line)) = lines.next() {
+        let code = strip_comment(line).trim();
+
+        if let Some(header) = directive(code, ".macro") {
+            let (name, params) = parse_header(header).map_err(|e| (n, e))?;
+            let mut body = Vec::new();
+            loop {
+                match lines.next() {
+                    Some((m, l)) => {
+                        let code = strip_comment(l).trim();
+                        if directive(code, ".end_macro").is_some() {
+                            break;
+                        } else if directive(code, ".macro").is_some() {
+                            return Err((m, "nested macro definition".into()));
+                        }
+                        body.push(l.to_string());
+                    }
+                    None => return Err((n, format!("macro {} is missing .end_macro", name))),
+                }
+            }
+            macros.define(name, params, body);
+        } else if directive(code, ".end_macro").is_some() {
+            return Err((n, ".end_macro without .macro".into()));
+        } else {
+            macros.expand(n, line, 0, &mut output).map_err(|e| (n, e))?;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Expands `.macro name(%a, %b)` ... `.end_macro` definitions in the MARS style.
+///
+/// Labels defined inside a macro body get a `_M<n>` suffix which is unique for each expansion.
+pub fn expand_macros(source: &str) -> Result<String, String> {
+    let lines: Vec<&str> = source.lines().collect();
+
+    match expand(&lines) {
+        Ok(output) => Ok(output
+            .into_iter()
+            .map(|(_, l)| l)
+            .collect::<Vec<_>>()
+            .join("\n")),
+        Err((n, e)) => Err(format!("Line: {} {}", n + 1, e)),
+    }
+}
+
+#[test]
+fn test_expand_macros() {
+    let input = r#"
+.macro print_int(%x)
+  add $a0, $zero, %x
+  syscall
+.end_macro
+print_int($t0)
+L: print_int ($t1) # comment
+"#;
+
+    assert_eq!(
+        expand_macros(input).unwrap(),
+        "\n  add $a0, $zero, $t0\n  syscall\nL:\n  add $a0, $zero, $t1\n  syscall"
+    );
+}
+
+#[test]
+fn test_macro_local_label() {
+    let input = r#".macro wait %n
+  addi $t0, $zero, %n
+loop: addi $t0, $t0, -1
+  bne $t0, $zero, loop
+.end_macro
+wait 3
+wait(4)"#;
+
+    let output = expand_macros(input).unwrap();
+    assert!(output.contains("loop_M0: addi $t0, $t0, -1"));
+    assert!(output.contains("bne $t0, $zero, loop_M0"));
+    assert!(output.contains("loop_M1: addi $t0, $t0, -1"));
+    assert!(output.contains("bne $t0, $zero, loop_M1"));
+}
+
+#[test]
+fn test_macro_overload_and_nesting() {
+    let input = r#".macro inc(%r)
+  addi %r, %r, 1
+.end_macro
+.macro inc(%r, %n)
+  addi %r, %r, %n
+  inc(%r)
+.end_macro
+inc($t0, 2)"#;
+
+    assert_eq!(
+        expand_macros(input).unwrap(),
+        "  addi $t0, $t0, 2\n  addi $t0, $t0, 1"
+    );
+}
+
+#[test]
+fn test_macro_hi_lo() {
+    let input = r#".macro load(%r, %label)
+  lui %r, %hi(%label)
+  addiu %r, %r, %lo(%label)
+.end_macro
+load($t0, main)"#;
+
+    assert_eq!(
+        expand_macros(input).unwrap(),
+        "  lui $t0, %hi(main)\n  addiu $t0, $t0, %lo(main)"
+    );
+    assert_eq!(
+        expand_macros(".macro m(%hi)\n addi %hi, %hi, 1\n.end_macro\nm($t0)").unwrap(),
+        " addi $t0, $t0, 1"
+    );
+}
+
+#[test]
+fn test_macro_errors() {
+    assert!(expand_macros(".macro m(%a)\n add %b, %b, %b\n.end_macro\nm($t0)").is_err());
+    assert!(expand_macros(".macro m(%a)\n add %a, %a, %a\n").is_err());
+    assert!(expand_macros(".macro m\n m\n.end_macro\nm").is_err());
+}
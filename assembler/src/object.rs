@@ -0,0 +1,541 @@
+use crate::instruction::{branch_offset, hi, jump_target, lo};
+use crate::read_code;
+use crate::write_code;
+use crate::BResult;
+use crate::Binary;
+use crate::Endian;
+use crate::Instruction;
+use crate::Operand;
+use crate::SectionType;
+use std::collections::HashMap;
+
+/// "MMO2"
+const MAGIC: Binary = 0x4d4d4f32;
+/// "MMO1", before the kernel text section
+const MAGIC_V1: Binary = 0x4d4d4f31;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SymbolSection {
+    Undefined,
+    Text,
+    Data,
+    KText,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub section: SymbolSection,
+    /// Offset in words from the start of the section
+    pub offset: Binary,
+    /// Declared by `.globl`, or referred to without being defined
+    pub global: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RelocationType {
+    /// Target of `beq` and `bne`
+    Branch,
+    /// Target of `j`
+    Jump,
+    /// `%hi(label)`
+    Hi,
+    /// `%lo(label)`
+    Lo,
+    /// A label used as an immediate
+    Absolute,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Relocation {
+    /// Section of the patched instruction, `Text` or `KText`
+    pub section: SymbolSection,
+    /// Offset in words from the start of the section
+    pub offset: Binary,
+    pub kind: RelocationType,
+    /// Index into `Object::symbols`
+    pub symbol: usize,
+}
+
+/// Relocatable object file
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Object {
+    pub text: Vec<Binary>,
+    pub data: Vec<Binary>,
+    pub ktext: Vec<Binary>,
+    pub symbols: Vec<Symbol>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl SymbolSection {
+    fn to_binary(self) -> Binary {
+        match self {
+            SymbolSection::Undefined => 0,
+            SymbolSection::Text => 1,
+            SymbolSection::Data => 2,
+            SymbolSection::KText => 3,
+        }
+    }
+
+    fn from_binary(b: Binary) -> BResult<Self> {
+        match b {
+            0 => Ok(SymbolSection::Undefined),
+            1 => Ok(SymbolSection::Text),
+            2 => Ok(SymbolSection::Data),
+            3 => Ok(SymbolSection::KText),
+            _ => Err(format!("invalid symbol section {}", b).into()),
+        }
+    }
+}
+
+impl RelocationType {
+    fn to_binary(self) -> Binary {
+        match self {
+            RelocationType::Branch => 0,
+            RelocationType::Jump => 1,
+            RelocationType::Hi => 2,
+            RelocationType::Lo => 3,
+            RelocationType::Absolute => 4,
+        }
+    }
+
+    fn from_binary(b: Binary) -> BResult<Self> {
+        match b {
+            0 => Ok(RelocationType::Branch),
+            1 => Ok(RelocationType::Jump),
+            2 => Ok(RelocationType::Hi),
+            3 => Ok(RelocationType::Lo),
+            4 => Ok(RelocationType::Absolute),
+            _ => Err(format!("invalid relocation type {}", b).into()),
+        }
+    }
+
+    /// Patches the field of `code`, placed at `pc`, with `address`.
+    pub fn apply(self, code: Binary, pc: Binary, address: Binary) -> Binary {
+        match self {
+            RelocationType::Branch => (code & !0xffff) | branch_offset(pc, address),
+            RelocationType::Lo | RelocationType::Absolute => (code & !0xffff) | lo(address),
+            RelocationType::Hi => (code & !0xffff) | hi(address),
+            RelocationType::Jump => (code & !0x3ffffff) | jump_target(address),
+        }
+    }
+}
+
+fn relocation_type(token: &Instruction, operand: &Operand) -> RelocationType {
+    match (token, operand) {
+        (_, Operand::Hi(_)) => RelocationType::Hi,
+        (_, Operand::Lo(_)) => RelocationType::Lo,
+        (Instruction::J { .. }, _) => RelocationType::Jump,
+        (Instruction::I { op, .. }, _) if op.is_branch() => RelocationType::Branch,
+        _ => RelocationType::Absolute,
+    }
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+    cursor: usize,
+    endian: Endian,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> BResult<&'a [u8]> {
+        let end = self
+            .cursor
+            .checked_add(n)
+            .ok_or("unexpected end of object file")?;
+        let b = self
+            .input
+            .get(self.cursor..end)
+            .ok_or("unexpected end of object file")?;
+        self.cursor = end;
+        Ok(b)
+    }
+
+    fn word(&mut self) -> BResult<Binary> {
+        Ok(read_code(self.endian, self.bytes(4)?))
+    }
+}
+
+impl Object {
+    pub fn new(tokens: &[Instruction]) -> Self {
+        let mut object = Object::default();
+        let mut index = HashMap::new();
+
+        let globals: Vec<&str> = tokens
+            .iter()
+            .filter_map(|t| match t {
+                Instruction::Section(SectionType::Globl(names)) => Some(names),
+                _ => None,
+            })
+            .flatten()
+            .map(|n| n.as_str())
+            .collect();
+
+        // Define labels
+        let mut section = SymbolSection::Text;
+        let mut text = 0;
+        let mut data = 0;
+        let mut ktext = 0;
+        for token in tokens {
+            match token {
+                Instruction::Section(SectionType::Text) => section = SymbolSection::Text,
+                Instruction::Section(SectionType::Data) => section = SymbolSection::Data,
+                Instruction::Section(SectionType::KText) => section = SymbolSection::KText,
+                Instruction::LabelDef { name } => {
+                    let offset = match section {
+                        SymbolSection::Data => data,
+                        SymbolSection::KText => ktext,
+                        _ => text,
+                    };
+                    index.insert(*name, object.symbols.len());
+                    object.symbols.push(Symbol {
+                        name: name.to_string(),
+                        section,
+                        offset,
+                        global: globals.contains(name),
+                    });
+                }
+                Instruction::I { .. } | Instruction::R { .. } | Instruction::J { .. }
+                    if section == SymbolSection::KText =>
+                {
+                    ktext += 1
+                }
+                Instruction::I { .. } | Instruction::R { .. } | Instruction::J { .. } => text += 1,
+                Instruction::Section(SectionType::Word(v)) => data += v.len() as Binary,
+                Instruction::Section(SectionType::Space(n)) => data += *n / 4,
+                _ => {}
+            }
+        }
+
+        // Encode instructions leaving the fields of labels zero
+        let mut zero = HashMap::new();
+        let mut section = SymbolSection::Text;
+        for token in tokens {
+            match token {
+                Instruction::Section(SectionType::KText) => section = SymbolSection::KText,
+                t if t.starts_section() => section = SymbolSection::Text,
+                _ => {}
+            }
+            let code = match section {
+                SymbolSection::KText => &mut object.ktext,
+                _ => &mut object.text,
+            };
+
+            let operands = match token {
+                Instruction::I { im, .. } => vec![im],
+                Instruction::J { ad, .. } => vec![ad],
+                _ => vec![],
+            };
+
+            for operand in operands {
+                if let Some(name) = operand.label() {
+                    let symbol = *index.entry(name).or_insert_with(|| {
+                        object.symbols.push(Symbol {
+                            name: name.to_string(),
+                            section: SymbolSection::Undefined,
+                            offset: 0,
+                            global: true,
+                        });
+                        object.symbols.len() - 1
+                    });
+                    object.relocations.push(Relocation {
+                        section,
+                        offset: code.len() as Binary,
+                        kind: relocation_type(token, operand),
+                        symbol,
+                    });
+                    zero.insert(name, 0);
+                }
+            }
+
+            if let Some(c) = token.code(4 * code.len() as Binary, &zero) {
+                code.push(c);
+            }
+        }
+
+        // Data section
+        let mut in_data = false;
+        for token in tokens {
+            match token {
+                Instruction::Section(SectionType::Text) => in_data = false,
+                Instruction::Section(SectionType::Data) => in_data = true,
+                Instruction::Section(SectionType::Word(v)) if in_data => {
+                    object.data.extend(v.iter())
+                }
+                Instruction::Section(SectionType::Space(n)) if in_data => {
+                    object.data.extend((0..(*n / 4)).map(|_| 0))
+                }
+                _ => {}
+            }
+        }
+
+        object
+    }
+
+    pub fn write_code(&self, endian: Endian, output: &mut Vec<u8>) -> BResult<()> {
+        write_code(endian, MAGIC, output)?;
+        write_code(endian, self.text.len() as Binary, output)?;
+        write_code(endian, self.data.len() as Binary, output)?;
+        write_code(endian, self.ktext.len() as Binary, output)?;
+        write_code(endian, self.symbols.len() as Binary, output)?;
+        write_code(endian, self.relocations.len() as Binary, output)?;
+
+        for code in self
+            .text
+            .iter()
+            .chain(self.data.iter())
+            .chain(self.ktext.iter())
+        {
+            write_code(endian, *code, output)?;
+        }
+
+        for symbol in self.symbols.iter() {
+            write_code(endian, symbol.section.to_binary(), output)?;
+            write_code(endian, symbol.offset, output)?;
+            write_code(endian, symbol.global as Binary, output)?;
+            write_code(endian, symbol.name.len() as Binary, output)?;
+            output.extend(symbol.name.as_bytes());
+            output.extend(std::iter::repeat_n(0, (4 - symbol.name.len() % 4) % 4));
+        }
+
+        for relocation in self.relocations.iter() {
+            write_code(endian, relocation.section.to_binary(), output)?;
+            write_code(endian, relocation.offset, output)?;
+            write_code(endian, relocation.kind.to_binary(), output)?;
+            write_code(endian, relocation.symbol as Binary, output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads an object file written in either endian.
+    pub fn from_u8(input: &[u8]) -> BResult<Self> {
+        let magic = input.get(..4).ok_or("not an object file")?;
+        let (endian, v1) = [Endian::Big, Endian::Little]
+            .into_iter()
+            .find_map(|endian| match read_code(endian, magic) {
+                MAGIC => Some((endian, false)),
+                MAGIC_V1 => Some((endian, true)),
+                _ => None,
+            })
+            .ok_or("not an object file")?;
+
+        let mut reader = Reader {
+            input,
+            cursor: 4,
+            endian,
+        };
+
+        let text_len = reader.word()?;
+        let data_len = reader.word()?;
+        let ktext_len = if v1 { 0 } else { reader.word()? };
+        let symbols_len = reader.word()?;
+        let relocations_len = reader.word()?;
+
+        let mut object = Object::default();
+        for _ in 0..text_len {
+            object.text.push(reader.word()?);
+        }
+        for _ in 0..data_len {
+            object.data.push(reader.word()?);
+        }
+        for _ in 0..ktext_len {
+            object.ktext.push(reader.word()?);
+        }
+        for _ in 0..symbols_len {
+            let section = SymbolSection::from_binary(reader.word()?)?;
+            let offset = reader.word()?;
+            let global = reader.word()? != 0;
+            let len = reader.word()? as usize;
+            let name = String::from_utf8(reader.bytes(len)?.to_vec())?;
+            reader.bytes((4 - len % 4) % 4)?;
+
+            object.symbols.push(Symbol {
+                name,
+                section,
+                offset,
+                global,
+            });
+        }
+        for _ in 0..relocations_len {
+            let section = if v1 {
+                SymbolSection::Text
+            } else {
+                SymbolSection::from_binary(reader.word()?)?
+            };
+            let offset = reader.word()?;
+            let kind = RelocationType::from_binary(reader.word()?)?;
+            let symbol = reader.word()? as usize;
+            if symbol >= object.symbols.len() {
+                return Err(format!("invalid symbol index {}", symbol).into());
+            }
+            let len = match section {
+                SymbolSection::Text => object.text.len(),
+                SymbolSection::KText => object.ktext.len(),
+                _ => return Err(format!("invalid relocation section {:?}", section).into()),
+            };
+            if offset < 0 || offset as usize >= len {
+                return Err(format!("invalid relocation offset {}", offset).into());
+            }
+
+            object.relocations.push(Relocation {
+                section,
+                offset,
+                kind,
+                symbol,
+            });
+        }
+
+        Ok(object)
+    }
+}
+
+#[test]
+fn test_object() {
+    use crate::parser::parse;
+    let input = r#"
+        .text
+        .globl main
+        main: lui $t0, %hi(L)
+        addi $t0, $t0, %lo(L)
+        loop: bne $t0, $zero, loop
+        j print
+        .data
+        L: .word 1, 2
+        "#;
+
+    let tokens = parse(input).unwrap();
+    let object = Object::new(&tokens);
+
+    assert_eq!(object.text.len(), 4);
+    assert_eq!(object.data, vec![1, 2]);
+    assert_eq!(
+        object.symbols,
+        vec![
+            Symbol {
+                name: "main".into(),
+                section: SymbolSection::Text,
+                offset: 0,
+                global: true,
+            },
+            Symbol {
+                name: "loop".into(),
+                section: SymbolSection::Text,
+                offset: 2,
+                global: false,
+            },
+            Symbol {
+                name: "L".into(),
+                section: SymbolSection::Data,
+                offset: 0,
+                global: false,
+            },
+            Symbol {
+                name: "print".into(),
+                section: SymbolSection::Undefined,
+                offset: 0,
+                global: true,
+            },
+        ]
+    );
+
+    let kinds: Vec<(Binary, RelocationType, usize)> = object
+        .relocations
+        .iter()
+        .map(|r| (r.offset, r.kind, r.symbol))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (0, RelocationType::Hi, 2),
+            (1, RelocationType::Lo, 2),
+            (2, RelocationType::Branch, 1),
+            (3, RelocationType::Jump, 3),
+        ]
+    );
+
+    for endian in [Endian::Little, Endian::Big] {
+        let mut output = Vec::new();
+        object.write_code(endian, &mut output).unwrap();
+        assert_eq!(Object::from_u8(&output).unwrap(), object);
+    }
+}
+
+#[test]
+fn test_object_ktext() {
+    use crate::parser::parse;
+    let input = r#"
+        .globl main
+        main: j main
+        .ktext
+        handler: j handler
+        eret
+        "#;
+
+    let tokens = parse(input).unwrap();
+    let object = Object::new(&tokens);
+
+    assert_eq!(object.text.len(), 1);
+    assert_eq!(object.ktext.len(), 2);
+    assert_eq!(object.symbols[1].section, SymbolSection::KText);
+    assert_eq!(object.relocations[1].section, SymbolSection::KText);
+    assert_eq!(object.relocations[1].offset, 0);
+
+    let mut output = Vec::new();
+    object.write_code(Endian::Big, &mut output).unwrap();
+    assert_eq!(Object::from_u8(&output).unwrap(), object);
+}
+
+#[test]
+fn test_object_v1() {
+    // "MMO1" object with `main: j main` and no kernel text section
+    let words: [Binary; 11] = [MAGIC_V1, 1, 0, 1, 1, 0x08000000, 1, 0, 1, 4, 0];
+    let mut input: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    input[40..44].copy_from_slice(b"main");
+    input.extend([0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+
+    let object = Object::from_u8(&input).unwrap();
+    assert_eq!(object.text, vec![0x08000000]);
+    assert!(object.ktext.is_empty());
+    assert_eq!(object.symbols[0].name, "main");
+    assert_eq!(
+        object.relocations,
+        vec![Relocation {
+            section: SymbolSection::Text,
+            offset: 0,
+            kind: RelocationType::Jump,
+            symbol: 0,
+        }]
+    );
+}
+
+#[test]
+fn test_object_invalid() {
+    use crate::parser::parse;
+    let object = Object::new(&parse("main: j main").unwrap());
+    let mut output = Vec::new();
+    object.write_code(Endian::Little, &mut output).unwrap();
+
+    // The last words are the section, offset, type and symbol of the relocation
+    let n = output.len();
+    let mut invalid = output.clone();
+    invalid[n - 12..n - 8].copy_from_slice(&100i32.to_le_bytes());
+    assert_eq!(
+        Object::from_u8(&invalid).unwrap_err().to_string(),
+        "invalid relocation offset 100"
+    );
+    let mut invalid = output.clone();
+    invalid[n - 16..n - 12].copy_from_slice(&2i32.to_le_bytes());
+    assert_eq!(
+        Object::from_u8(&invalid).unwrap_err().to_string(),
+        "invalid relocation section Data"
+    );
+
+    // The length of the name of the symbol
+    let mut invalid = output.clone();
+    invalid[n - 24..n - 20].copy_from_slice(&(-1i32).to_le_bytes());
+    assert_eq!(
+        Object::from_u8(&invalid).unwrap_err().to_string(),
+        "unexpected end of object file"
+    );
+}
@@ -3,12 +3,14 @@ use crate::Instruction;
 use crate::Operand;
 use crate::Operation;
 use crate::SectionType;
+use mips_abi::register_number;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::bytes::complete::take_while;
 use nom::character::is_alphabetic;
 use nom::combinator::map;
 use nom::combinator::map_opt;
+use nom::combinator::opt;
 use nom::multi::separated_list0;
 use nom::number::complete::double;
 use nom::sequence::preceded;
@@ -16,6 +18,11 @@ use nom::sequence::terminated;
 use nom::sequence::tuple;
 use nom::IResult;
 
+/// `fmt` field of single precision, double precision and 32bit integer operands
+const FMT_S: Binary = 16;
+const FMT_D: Binary = 17;
+const FMT_W: Binary = 20;
+
 fn sp(i: &str) -> IResult<&str, &str> {
     let chars = " \t\r\n";
 
@@ -27,49 +34,16 @@ fn comma(i: &str) -> IResult<&str, ()> {
 }
 
 fn string(i: &str) -> IResult<&str, &str> {
-    take_while(move |c: char| is_alphabetic(c as u8) || ('0' <= c && c <= '9'))(i)
+    take_while(move |c: char| is_alphabetic(c as u8) || ('0' <= c && c <= '9') || c == '_')(i)
 }
 
 fn number(input: &str) -> IResult<&str, Binary> {
     map(double, |n| n as Binary)(input)
 }
 
+/// Register named in the o32 ABI, which the emulator shares
 fn binary_from_name(i: &str) -> IResult<&str, Binary> {
-    map_opt(string, |i| match i {
-        "zero" => Some(0),
-        "at" => Some(1),
-        "v0" => Some(2),
-        "v1" => Some(3),
-        "a0" => Some(4),
-        "a1" => Some(5),
-        "a2" => Some(6),
-        "a3" => Some(7),
-        "t0" => Some(8),
-        "t1" => Some(9),
-        "t2" => Some(10),
-        "t3" => Some(11),
-        "t4" => Some(12),
-        "t5" => Some(13),
-        "t6" => Some(14),
-        "t7" => Some(15),
-        "s0" => Some(16),
-        "s1" => Some(17),
-        "s2" => Some(18),
-        "s3" => Some(19),
-        "s4" => Some(20),
-        "s5" => Some(21),
-        "s6" => Some(22),
-        "s7" => Some(23),
-        "s8" => Some(24),
-        "s9" => Some(25),
-        "k0" => Some(26),
-        "k1" => Some(27),
-        "gp" => Some(28),
-        "sp" => Some(29),
-        "fp" => Some(30),
-        "ra" => Some(31),
-        _ => None,
-    })(i)
+    map_opt(string, register_number)(i)
 }
 
 pub fn label(i: &str) -> IResult<&str, Operand> {
@@ -81,13 +55,30 @@ fn operand(i: &str) -> IResult<&str, Operand> {
         Operand::Register(b)
     });
     let constant = map(number, |n| Operand::Constant(n));
-    preceded(sp, alt((rgt, constant, label)))(i)
+    let hi = map(preceded(tag("%hi("), terminated(string, tag(")"))), |s| {
+        Operand::Hi(s)
+    });
+    let lo = map(preceded(tag("%lo("), terminated(string, tag(")"))), |s| {
+        Operand::Lo(s)
+    });
+    preceded(sp, alt((rgt, constant, hi, lo, label)))(i)
 }
 
 fn c_operand(i: &str) -> IResult<&str, Operand> {
     preceded(comma, operand)(i)
 }
 
+/// Floating-point register `$f0`-`$f31`
+fn fp_operand(i: &str) -> IResult<&str, Operand<'_>> {
+    map(preceded(sp, preceded(tag("$f"), number)), |b| {
+        Operand::Register(b)
+    })(i)
+}
+
+fn c_fp_operand(i: &str) -> IResult<&str, Operand<'_>> {
+    preceded(comma, fp_operand)(i)
+}
+
 struct Op3<'a> {
     rs: Operand<'a>,
     rt: Operand<'a>,
@@ -141,6 +132,9 @@ fn jump_instruction(i: &str) -> IResult<&str, Instruction> {
     let j = map(tuple((tag("j"), preceded(sp, label))), |(_, ad)| {
         Instruction::ji(Operation(0x2), ad)
     });
+    let jal = map(tuple((tag("jal"), preceded(sp, label))), |(_, ad)| {
+        Instruction::ji(Operation(0x3), ad)
+    });
     let jr = map(tuple((tag("jr"), operand)), |(_, rs)| {
         Instruction::ri(
             Operation(0x0),
@@ -151,7 +145,7 @@ fn jump_instruction(i: &str) -> IResult<&str, Instruction> {
             Operand::Constant(0x8),
         )
     });
-    alt((jr, j))(i)
+    alt((jr, jal, j))(i)
 }
 
 fn memory_instruction(i: &str) -> IResult<&str, Instruction> {
@@ -174,10 +168,17 @@ fn arithmetic_with_immediate(i: &str) -> IResult<&str, Instruction> {
     let lui = map(tag("lui"), |_| Operation(0xf));
     let ori = map(tag("ori"), |_| Operation(0xd));
 
-    map(
-        tuple((alt((addiu, addi, lui, ori)), op2im)),
-        |(op, op2im)| Instruction::ii(op, op2im.rs, op2im.rt, op2im.im),
-    )(i)
+    let lui2 = map(tuple((tag("lui"), operand, c_operand)), |(_, rt, im)| {
+        Instruction::ii(Operation(0xf), Operand::Register(0x0), rt, im)
+    });
+
+    alt((
+        map(
+            tuple((alt((addiu, addi, lui, ori)), op2im)),
+            |(op, op2im)| Instruction::ii(op, op2im.rs, op2im.rt, op2im.im),
+        ),
+        lui2,
+    ))(i)
 }
 
 fn arithmetic_with_register(i: &str) -> IResult<&str, Instruction> {
@@ -219,6 +220,195 @@ fn move_from(i: &str) -> IResult<&str, Instruction> {
     })(i)
 }
 
+fn coprocessor0(i: &str) -> IResult<&str, Instruction<'_>> {
+    let mfc0 = map(tag("mfc0"), |_| 0x0);
+    let mtc0 = map(tag("mtc0"), |_| 0x4);
+    let move_cp0 = map(
+        tuple((alt((mfc0, mtc0)), operand, c_operand)),
+        |(rs, rt, rd)| {
+            Instruction::ri(
+                Operation(0x10),
+                Operand::Constant(rs),
+                rt,
+                rd,
+                Operand::Constant(0x0),
+                Operand::Constant(0x0),
+            )
+        },
+    );
+    let eret = map(tag("eret"), |_| {
+        Instruction::ri(
+            Operation(0x10),
+            Operand::Constant(0x10),
+            Operand::Register(0x0),
+            Operand::Register(0x0),
+            Operand::Constant(0x0),
+            Operand::Constant(0x18),
+        )
+    });
+
+    alt((move_cp0, eret))(i)
+}
+
+fn coprocessor1(i: &str) -> IResult<&str, Instruction<'_>> {
+    use nom::character::complete::char;
+    let fmt = |i| {
+        alt((
+            map(tag(".s"), |_| FMT_S),
+            map(tag(".d"), |_| FMT_D),
+            map(tag(".w"), |_| FMT_W),
+        ))(i)
+    };
+    // Condition code, 0 if it is left out
+    let cc = |i| {
+        map(opt(terminated(preceded(sp, number), comma)), |cc| {
+            cc.unwrap_or(0)
+        })(i)
+    };
+
+    let add = map(tag("add"), |_| 0x0);
+    let sub = map(tag("sub"), |_| 0x1);
+    let mul = map(tag("mul"), |_| 0x2);
+    let div = map(tag("div"), |_| 0x3);
+    let arithmetic = map(
+        tuple((
+            alt((add, sub, mul, div)),
+            fmt,
+            fp_operand,
+            c_fp_operand,
+            c_fp_operand,
+        )),
+        |(fc, fmt, fd, fs, ft)| {
+            Instruction::ri(
+                Operation(0x11),
+                Operand::Constant(fmt),
+                ft,
+                fs,
+                fd,
+                Operand::Constant(fc),
+            )
+        },
+    );
+
+    let abs = map(tag("abs"), |_| 0x5);
+    let mov = map(tag("mov"), |_| 0x6);
+    let neg = map(tag("neg"), |_| 0x7);
+    let unary = map(
+        tuple((alt((abs, mov, neg)), fmt, fp_operand, c_fp_operand)),
+        |(fc, fmt, fd, fs)| {
+            Instruction::ri(
+                Operation(0x11),
+                Operand::Constant(fmt),
+                Operand::Register(0x0),
+                fs,
+                fd,
+                Operand::Constant(fc),
+            )
+        },
+    );
+
+    // `cvt.<to>.<from>`
+    let to = alt((
+        map(tag(".s"), |_| 0x20),
+        map(tag(".d"), |_| 0x21),
+        map(tag(".w"), |_| 0x24),
+    ));
+    let cvt = map(
+        tuple((tag("cvt"), to, fmt, fp_operand, c_fp_operand)),
+        |(_, fc, fmt, fd, fs)| {
+            Instruction::ri(
+                Operation(0x11),
+                Operand::Constant(fmt),
+                Operand::Register(0x0),
+                fs,
+                fd,
+                Operand::Constant(fc),
+            )
+        },
+    );
+
+    let eq = map(tag("c.eq"), |_| 0x32);
+    let lt = map(tag("c.lt"), |_| 0x3c);
+    let le = map(tag("c.le"), |_| 0x3e);
+    let compare = map(
+        tuple((alt((eq, lt, le)), fmt, cc, fp_operand, c_fp_operand)),
+        |(fc, fmt, cc, fs, ft)| {
+            Instruction::ri(
+                Operation(0x11),
+                Operand::Constant(fmt),
+                ft,
+                fs,
+                Operand::Constant(cc << 2),
+                Operand::Constant(fc),
+            )
+        },
+    );
+
+    let bc1f = map(tag("bc1f"), |_| 0);
+    let bc1t = map(tag("bc1t"), |_| 1);
+    let branch = map(
+        tuple((alt((bc1f, bc1t)), cc, preceded(sp, label))),
+        |(tf, cc, offset)| {
+            Instruction::ii(
+                Operation(0x11),
+                Operand::Constant(0x8),
+                Operand::Constant(cc << 2 | tf),
+                offset,
+            )
+        },
+    );
+
+    let mfc1 = map(tag("mfc1"), |_| 0x0);
+    let mtc1 = map(tag("mtc1"), |_| 0x4);
+    let move_cp1 = map(
+        tuple((alt((mfc1, mtc1)), operand, c_fp_operand)),
+        |(rs, rt, fs)| {
+            Instruction::ri(
+                Operation(0x11),
+                Operand::Constant(rs),
+                rt,
+                fs,
+                Operand::Constant(0x0),
+                Operand::Constant(0x0),
+            )
+        },
+    );
+    let cfc1 = map(tag("cfc1"), |_| 0x2);
+    let ctc1 = map(tag("ctc1"), |_| 0x6);
+    let move_control = map(
+        tuple((alt((cfc1, ctc1)), operand, c_operand)),
+        |(rs, rt, fs)| {
+            Instruction::ri(
+                Operation(0x11),
+                Operand::Constant(rs),
+                rt,
+                fs,
+                Operand::Constant(0x0),
+                Operand::Constant(0x0),
+            )
+        },
+    );
+
+    let lwc1 = map(tag("lwc1"), |_| Operation(0x31));
+    let swc1 = map(tag("swc1"), |_| Operation(0x39));
+    let rs = preceded(char('('), terminated(operand, char(')')));
+    let memory = map(
+        tuple((alt((lwc1, swc1)), fp_operand, c_operand, rs)),
+        |(op, ft, im, rs)| Instruction::ii(op, rs, ft, im),
+    );
+
+    alt((
+        arithmetic,
+        unary,
+        cvt,
+        compare,
+        branch,
+        move_cp1,
+        move_control,
+        memory,
+    ))(i)
+}
+
 fn shift_instruction(i: &str) -> IResult<&str, Instruction> {
     let sll = map(tag("sll"), |_| 0x0);
     let srl = map(tag("srl"), |_| 0x2);
@@ -226,9 +416,9 @@ fn shift_instruction(i: &str) -> IResult<&str, Instruction> {
     map(tuple((alt((sll, srl)), op2im)), |(fc, op2im)| {
         Instruction::ri(
             Operation(0x0),
+            Operand::Register(0x0),
             op2im.rs,
             op2im.rt,
-            Operand::Constant(0x0),
             op2im.im,
             Operand::Constant(fc),
         )
@@ -278,6 +468,7 @@ fn def_label(i: &str) -> IResult<&str, Instruction> {
 fn section(i: &str) -> IResult<&str, Instruction> {
     let data = map(tag("data"), |_| Instruction::Section(SectionType::Data));
     let text = map(tag("text"), |_| Instruction::Section(SectionType::Text));
+    let ktext = map(tag("ktext"), |_| Instruction::Section(SectionType::KText));
     let globl = map(
         preceded(
             tuple((tag("globl"), sp)),
@@ -297,10 +488,40 @@ fn section(i: &str) -> IResult<&str, Instruction> {
         |w| Instruction::Section(SectionType::Word(w)),
     );
 
+    let numbers = |i| separated_list0(preceded(sp, terminated(tag(","), sp)), double)(i);
+    let float = map(preceded(tuple((tag("float"), sp)), numbers), |f| {
+        let words = f.iter().map(|f| (*f as f32).to_bits() as Binary).collect();
+        Instruction::Section(SectionType::Word(words))
+    });
+    // The low word comes first, as a little-endian double is laid out in memory
+    let double = map(preceded(tuple((tag("double"), sp)), numbers), |f| {
+        let words = f
+            .iter()
+            .flat_map(|f| {
+                let bits = f.to_bits();
+                [bits as Binary, (bits >> 32) as Binary]
+            })
+            .collect();
+        Instruction::Section(SectionType::Word(words))
+    });
+
     let space = map(preceded(tuple((tag("space"), sp)), number), |n| {
         Instruction::Section(SectionType::Space(n))
     });
-    preceded(tag("."), alt((data, word, space, text, globl)))(i)
+    let set = map(
+        preceded(
+            tuple((tag("set"), sp)),
+            alt((
+                map(tag("noreorder"), |_| false),
+                map(tag("reorder"), |_| true),
+            )),
+        ),
+        |r| Instruction::Section(SectionType::Reorder(r)),
+    );
+    preceded(
+        tag("."),
+        alt((data, word, float, double, space, text, ktext, globl, set)),
+    )(i)
 }
 
 fn comment(i: &str) -> IResult<&str, &str> {
@@ -321,6 +542,7 @@ pub fn one_parse(mut i: &str) -> IResult<&str, Instruction> {
                 section,
                 syscall,
                 def_label,
+                coprocessor1,
                 branch_instruction,
                 memory_instruction,
                 jump_instruction,
@@ -328,6 +550,7 @@ pub fn one_parse(mut i: &str) -> IResult<&str, Instruction> {
                 arithmetic_with_register,
                 arithmetic_with_hi_lo,
                 shift_instruction,
+                coprocessor0,
                 move_from,
             )),
             sp,
@@ -335,26 +558,49 @@ pub fn one_parse(mut i: &str) -> IResult<&str, Instruction> {
     )(i)
 }
 
-pub fn parse(input: &str) -> Result<Vec<Instruction>, String> {
+fn skip(mut i: &str) -> &str {
+    while let Ok((r, _)) = comment(i) {
+        i = r;
+    }
+    sp(i).map(|(r, _)| r).unwrap_or(i)
+}
+
+/// Parses `input` and records the line (0-origin) on which each instruction starts.
+///
+/// On failure the line and the rest of the input are returned.
+pub fn parse_lines(input: &str) -> Result<Vec<(usize, Instruction<'_>)>, (usize, &str)> {
     let mut tokens = Vec::new();
 
+    let mut line = 0;
     let mut i = input;
 
-    while i.len() > 0 {
+    loop {
+        let rest = skip(i);
+        line += i[..i.len() - rest.len()].matches('\n').count();
+        i = rest;
+
+        if i.is_empty() {
+            break;
+        }
+
         if let Ok((rest, instr)) = one_parse(i) {
-            tokens.push(instr);
+            tokens.push((line, instr));
+            line += i[..i.len() - rest.len()].matches('\n').count();
             i = rest;
         } else {
-            let cursor = input.len() - i.len();
-            let line_number = input[..cursor].chars().filter(|c| c == &'\n').count();
-
-            return Err(format!("Line: {} {}", line_number - 0, &input[cursor..]));
+            return Err((line, i));
         }
     }
 
     Ok(tokens)
 }
 
+pub fn parse(input: &str) -> Result<Vec<Instruction>, String> {
+    parse_lines(input)
+        .map(|tokens| tokens.into_iter().map(|(_, t)| t).collect())
+        .map_err(|(line, rest)| format!("Line: {} {}", line, rest))
+}
+
 #[test]
 fn test_one_parse() {
     let input = "addi $1, $2, -10";
@@ -387,12 +633,139 @@ fn test_one_parse() {
         ))
     );
 
+    let input = "add $t8, $t9, $s8";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::ri(
+                Operation(0x0),
+                Operand::Register(25),
+                Operand::Register(30),
+                Operand::Register(24),
+                Operand::Constant(0x0),
+                Operand::Constant(0x20),
+            )
+        ))
+    );
+
+    let input = "jal print";
+    assert_eq!(
+        one_parse(input),
+        Ok(("", Instruction::ji(Operation(0x3), Operand::Label("print"))))
+    );
+
+    let input = "lui $t0, %hi(L)";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::ii(
+                Operation(0xf),
+                Operand::Register(0x0),
+                Operand::Register(0x8),
+                Operand::Hi("L")
+            )
+        ))
+    );
+
+    let input = "addi $t0, $t0, %lo(L)";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::ii(
+                Operation(0x8),
+                Operand::Register(0x8),
+                Operand::Register(0x8),
+                Operand::Lo("L")
+            )
+        ))
+    );
+
+    let input = "add.s $f0, $f1, $f2";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::ri(
+                Operation(0x11),
+                Operand::Constant(FMT_S),
+                Operand::Register(2),
+                Operand::Register(1),
+                Operand::Register(0),
+                Operand::Constant(0x0),
+            )
+        ))
+    );
+
+    let input = "c.lt.d 1, $f2, $f4";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::ri(
+                Operation(0x11),
+                Operand::Constant(FMT_D),
+                Operand::Register(4),
+                Operand::Register(2),
+                Operand::Constant(4),
+                Operand::Constant(0x3c),
+            )
+        ))
+    );
+
+    let input = "bc1t L";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::ii(
+                Operation(0x11),
+                Operand::Constant(0x8),
+                Operand::Constant(0x1),
+                Operand::Label("L")
+            )
+        ))
+    );
+
+    let input = "lwc1 $f2, 4($t0)";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::ii(
+                Operation(0x31),
+                Operand::Register(8),
+                Operand::Register(2),
+                Operand::Constant(4)
+            )
+        ))
+    );
+
     let input = ".data";
     assert_eq!(
         one_parse(input),
         Ok(("", Instruction::Section(SectionType::Data)))
     );
 
+    let input = ".float 1.5, -2";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::Section(SectionType::Word(vec![0x3fc00000, 0xc0000000u32 as Binary]))
+        ))
+    );
+    let input = ".double 1.5";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::Section(SectionType::Word(vec![0, 0x3ff80000]))
+        ))
+    );
+
     let input = ".word 1, 2, 3";
     assert_eq!(
         one_parse(input),
@@ -403,6 +776,34 @@ fn test_one_parse() {
         one_parse(input),
         Ok(("", Instruction::Section(SectionType::Space(20))))
     );
+
+    let input = ".set noreorder";
+    assert_eq!(
+        one_parse(input),
+        Ok(("", Instruction::Section(SectionType::Reorder(false))))
+    );
+
+    let input = ".ktext";
+    assert_eq!(
+        one_parse(input),
+        Ok(("", Instruction::Section(SectionType::KText)))
+    );
+
+    let input = "mfc0 $k0, $14";
+    assert_eq!(
+        one_parse(input),
+        Ok((
+            "",
+            Instruction::ri(
+                Operation(0x10),
+                Operand::Constant(0x0),
+                Operand::Register(26),
+                Operand::Register(14),
+                Operand::Constant(0x0),
+                Operand::Constant(0x0),
+            )
+        ))
+    );
 }
 
 #[test]
@@ -422,3 +823,19 @@ jr $ra
     assert_eq!(o.len(), 7);
     assert_eq!(o[2], Instruction::LabelDef { name: "L" });
 }
+
+#[test]
+fn test_parse_lines() {
+    let input = r#"# comment
+addi $a0, $0, 34
+
+L: syscall # comment
+jr $ra
+# trailing comment"#;
+
+    let o = parse_lines(input).unwrap();
+    let lines: Vec<usize> = o.iter().map(|(l, _)| *l).collect();
+    assert_eq!(lines, vec![1, 3, 3, 4]);
+
+    assert_eq!(parse_lines("addi $a0, $0, 1\n???").unwrap_err(), (1, "???"));
+}
@@ -0,0 +1,65 @@
+use crate::instruction::write_data_section;
+use crate::object::SymbolSection;
+use crate::BResult;
+use crate::Endian;
+use crate::Program;
+use crate::DATA_ADDRESS;
+use crate::KTEXT_ADDRESS;
+use crate::TEXT_ADDRESS;
+use std::collections::BTreeMap;
+
+impl Program {
+    /// Writes the file header followed by the text, data and kernel text sections.
+    pub fn write_code(&self, endian: Endian, output: &mut Vec<u8>) -> BResult<()> {
+        self.header.write_code(endian, output)?;
+        write_data_section(endian, &self.text, output)?;
+        write_data_section(endian, &self.data, output)?;
+        write_data_section(endian, &self.ktext, output)?;
+        Ok(())
+    }
+
+    /// Address and name of every defined label, in order of address
+    pub fn label_addresses(&self) -> Vec<(u32, &str)> {
+        let mut labels: Vec<_> = self
+            .symbols
+            .iter()
+            .filter_map(|symbol| {
+                let base = match symbol.section {
+                    SymbolSection::Text => TEXT_ADDRESS,
+                    SymbolSection::Data => DATA_ADDRESS,
+                    SymbolSection::KText => KTEXT_ADDRESS,
+                    SymbolSection::Undefined => return None,
+                };
+                Some(((base + 4 * symbol.offset) as u32, symbol.name.as_str()))
+            })
+            .collect();
+        labels.sort_by_key(|(address, _)| *address);
+        labels
+    }
+
+    /// Defined labels by address, keeping one of the labels which share an address
+    pub fn labels(&self) -> BTreeMap<u32, String> {
+        self.label_addresses()
+            .into_iter()
+            .map(|(address, name)| (address, name.to_string()))
+            .collect()
+    }
+}
+
+#[test]
+fn test_labels() {
+    use crate::assemble_source_to_program;
+    use crate::source::Source;
+
+    let input = r#"
+        main: addi $t0, $zero, 1
+        L: j L
+        .data
+        A: .word 5
+        "#;
+    let program = assemble_source_to_program(&Source::from_string("main.s", input)).unwrap();
+    let labels = program.labels();
+    assert_eq!(labels[&0x00400000], "main");
+    assert_eq!(labels[&0x00400004], "L");
+    assert_eq!(labels[&0x10010000], "A");
+}
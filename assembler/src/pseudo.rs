@@ -0,0 +1,40 @@
+/// Expands the pseudo-instruction `code` into real instructions.
+///
+/// `la rt, label` loads the address of `label` with `lui` and `addiu`, and `nop` is
+/// `sll $zero, $zero, 0`.
+pub fn expand(code: &str) -> Option<Vec<String>> {
+    let code = code.trim();
+    if code == "nop" {
+        return Some(vec!["sll $zero, $zero, 0".to_string()]);
+    }
+
+    let rest = code.strip_prefix("la")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let (rt, label) = rest.split_once(',')?;
+    let (rt, label) = (rt.trim(), label.trim());
+    if rt.is_empty() || label.is_empty() {
+        return None;
+    }
+
+    Some(vec![
+        format!("lui {}, %hi({})", rt, label),
+        format!("addiu {}, {}, %lo({})", rt, rt, label),
+    ])
+}
+
+#[test]
+fn test_expand() {
+    assert_eq!(
+        expand("  la $t0, A"),
+        Some(vec![
+            "lui $t0, %hi(A)".to_string(),
+            "addiu $t0, $t0, %lo(A)".to_string()
+        ])
+    );
+    assert_eq!(expand("nop"), Some(vec!["sll $zero, $zero, 0".to_string()]));
+    assert_eq!(expand("lw $t0, 0($t1)"), None);
+    assert_eq!(expand("label: la"), None);
+}
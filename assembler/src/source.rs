@@ -0,0 +1,202 @@
+use crate::macros;
+use crate::BResult;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Location {
+    /// Index into `Source::files`
+    pub file: usize,
+    /// Line number (1-origin)
+    pub line: usize,
+}
+
+/// Assembly source collected from one or more files.
+///
+/// Every line remembers the file and the line it originates from, so that
+/// diagnostics can name the file even after `.include` and macro expansion.
+#[derive(Debug, Default, Clone)]
+pub struct Source {
+    pub files: Vec<PathBuf>,
+    pub lines: Vec<(Location, String)>,
+}
+
+fn include_directive(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix(".include")?;
+    let rest = rest.trim().strip_prefix('"')?;
+    let end = rest.find('"')?;
+    Some(&rest[..end])
+}
+
+fn resolve(name: &str, current_dir: &Path, include_dirs: &[PathBuf]) -> Option<PathBuf> {
+    std::iter::once(current_dir)
+        .chain(include_dirs.iter().map(|d| d.as_path()))
+        .map(|d| d.join(name))
+        .find(|p| p.is_file())
+}
+
+impl Source {
+    pub fn from_string(name: &str, text: &str) -> Self {
+        let mut source = Source::default();
+        source.files.push(PathBuf::from(name));
+        source.push_lines(0, text);
+        source
+    }
+
+    /// Reads `inputs` in order. Each input starts in the text section.
+    pub fn load<P: AsRef<Path>, Q: AsRef<Path>>(inputs: &[P], include_dirs: &[Q]) -> BResult<Self> {
+        let include_dirs: Vec<PathBuf> = include_dirs
+            .iter()
+            .map(|d| d.as_ref().to_path_buf())
+            .collect();
+        let mut source = Source::default();
+
+        for input in inputs {
+            let text = fs::read_to_string(input)
+                .map_err(|e| format!("{}: {}", input.as_ref().display(), e))?;
+            let mut stack = vec![fs::canonicalize(input)?];
+
+            source.files.push(input.as_ref().to_path_buf());
+            source.lines.push((
+                Location {
+                    file: source.files.len() - 1,
+                    line: 0,
+                },
+                ".text".into(),
+            ));
+            source.include(source.files.len() - 1, &text, &mut stack, &include_dirs)?;
+        }
+
+        Ok(source)
+    }
+
+    fn push_lines(&mut self, file: usize, text: &str) {
+        for (n, line) in text.lines().enumerate() {
+            self.lines
+                .push((Location { file, line: n + 1 }, line.to_string()));
+        }
+    }
+
+    fn include(
+        &mut self,
+        file: usize,
+        text: &str,
+        stack: &mut Vec<PathBuf>,
+        include_dirs: &[PathBuf],
+    ) -> BResult<()> {
+        let current_dir = self.files[file]
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default();
+
+        for (n, line) in text.lines().enumerate() {
+            let location = Location { file, line: n + 1 };
+            let name = match include_directive(line) {
+                Some(name) => name,
+                None => {
+                    self.lines.push((location, line.to_string()));
+                    continue;
+                }
+            };
+
+            let path = resolve(name, &current_dir, include_dirs).ok_or_else(|| {
+                format!(
+                    "{}: cannot find include file \"{}\"",
+                    self.display(location),
+                    name
+                )
+            })?;
+            let canonical = fs::canonicalize(&path)?;
+
+            if stack.contains(&canonical) {
+                let cycle: Vec<String> = stack
+                    .iter()
+                    .chain(std::iter::once(&canonical))
+                    .map(|p| p.display().to_string())
+                    .collect();
+                return Err(format!(
+                    "{}: include cycle {}",
+                    self.display(location),
+                    cycle.join(" -> ")
+                )
+                .into());
+            }
+
+            let included = fs::read_to_string(&path)
+                .map_err(|e| format!("{}: {}: {}", self.display(location), path.display(), e))?;
+
+            self.files.push(path);
+            stack.push(canonical);
+            self.include(self.files.len() - 1, &included, stack, include_dirs)?;
+            stack.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Expands macros, keeping the origin of every expanded line.
+    pub fn expand_macros(&self) -> Result<Source, String> {
+        let lines: Vec<&str> = self.lines.iter().map(|(_, l)| l.as_str()).collect();
+        let output = macros::expand(&lines).map_err(|(n, e)| self.error(n, &e))?;
+
+        Ok(Source {
+            files: self.files.clone(),
+            lines: output
+                .into_iter()
+                .map(|(n, l)| (self.lines[n].0, l))
+                .collect(),
+        })
+    }
+
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|(_, l)| l.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn location(&self, line: usize) -> Option<Location> {
+        self.lines.get(line).map(|(l, _)| *l)
+    }
+
+    pub fn display(&self, location: Location) -> String {
+        format!("{}:{}", self.files[location.file].display(), location.line)
+    }
+
+    /// Formats a diagnostic for the (0-origin) line of `text()`.
+    pub fn error(&self, line: usize, message: &str) -> String {
+        match self.location(line) {
+            Some(location) => format!("{}: {}", self.display(location), message),
+            None => message.to_string(),
+        }
+    }
+}
+
+#[test]
+fn test_expand_macros_location() {
+    let input = r#"addi $t0, $zero, 1
+.macro inc(%r)
+  addi %r, %r, 1
+  addi %r, %r, 1
+.end_macro
+inc($t0)
+syscall"#;
+
+    let source = Source::from_string("main.s", input)
+        .expand_macros()
+        .unwrap();
+    let lines: Vec<usize> = source.lines.iter().map(|(l, _)| l.line).collect();
+
+    assert_eq!(lines, vec![1, 6, 6, 7]);
+    assert_eq!(source.error(3, "oops"), "main.s:7: oops");
+}
+
+#[test]
+fn test_include_directive() {
+    assert_eq!(
+        include_directive(r#"  .include "lib/print.s""#),
+        Some("lib/print.s")
+    );
+    assert_eq!(include_directive(".text"), None);
+}
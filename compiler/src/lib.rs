@@ -39,7 +39,7 @@ pub fn gen_code(node: Box<Node>, output: &mut String) -> Result<()> {
             gen_code(rhs, output)?;
             pop(output, "$t0")?;
             pop(output, "$t1")?;
-            writeln!(output, "  sub $t0, $t0, $t1")?;
+            writeln!(output, "  sub $t0, $t1, $t0")?;
         }
         Node::Number(n) => {
             writeln!(output, "  ori $t0, $zero, {}", n)?;
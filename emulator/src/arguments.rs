@@ -0,0 +1,61 @@
+use crate::Binary;
+use crate::Emulator;
+use crate::Exception;
+use crate::Register;
+
+impl Emulator {
+    /// Places `args` and `env` on the stack below `$sp` as SPIM does: the strings with a NUL at
+    /// the end, each starting on a word so `lw` can read it, then `argc` at the new `$sp`,
+    /// followed by the `argv` and `envp` arrays, each ended by a null pointer. `argc` is set to
+    /// `$a0`, `argv` to `$a1` and `envp` to `$a2`, so `main` can take them as in C.
+    pub fn set_arguments<S: AsRef<str>>(&mut self, args: &[S], env: &[S]) -> Result<(), Exception> {
+        let mut sp = self.register.get(Register::SP) as u32;
+        let mut strings = |strings: &[S], words: &mut Vec<Binary>| -> Result<(), Exception> {
+            for string in strings {
+                let bytes = string.as_ref().as_bytes();
+                sp = sp.wrapping_sub(bytes.len() as u32 + 1) & !3;
+                for (i, byte) in bytes.iter().chain(&[0]).enumerate() {
+                    self.memory.write_byte(sp.wrapping_add(i as u32), *byte)?;
+                }
+                words.push(sp as Binary);
+            }
+            words.push(0);
+            Ok(())
+        };
+
+        let mut words = vec![args.len() as Binary];
+        strings(args, &mut words)?;
+        strings(env, &mut words)?;
+
+        sp = sp.wrapping_sub(4 * words.len() as u32);
+        for (i, word) in words.iter().enumerate() {
+            self.memory.write_word(sp + 4 * i as u32, *word)?;
+        }
+        let argv = sp + 4;
+        let envp = argv + 4 * (args.len() as u32 + 1);
+        self.register.set(Register::SP, sp as Binary);
+        self.register.set(Register::A0, args.len() as Binary);
+        self.register.set(Register::A1, argv as Binary);
+        self.register.set(Register::A2, envp as Binary);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_set_arguments() {
+    let mut emu = Emulator::new();
+    emu.set_arguments(&["prog.s", "ab"], &["HOME=/"]).unwrap();
+
+    // "prog.s" at 0x7fffeff4, "ab" at 0x7fffeff0 and "HOME=/" at 0x7fffefe8
+    assert_eq!(emu.register.get(Register::SP), 0x7fffefd0);
+    assert_eq!(emu.register.get(Register::A0), 2);
+    assert_eq!(emu.register.get(Register::A1), 0x7fffefd4);
+    assert_eq!(emu.register.get(Register::A2), 0x7fffefe0);
+    let words = emu.memory.range(0x7fffefd0, 24).unwrap();
+    assert_eq!(
+        words.values(crate::dump::Unit::Word),
+        [2, 0x7fffeff4, 0x7fffeff0, 0, 0x7fffefe8, 0]
+    );
+    let strings = emu.memory.range(0x7fffefe8, 19).unwrap();
+    assert_eq!(strings.bytes, b"HOME=/\0\0ab\0\0prog.s\0");
+}
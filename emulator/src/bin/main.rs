@@ -1,5 +1,16 @@
+use mips_emulator::debug::DebugInfo;
+use mips_emulator::elf::is_elf;
 use mips_emulator::{Emulator, Endian};
 
+/// Source position of the pc, if the debug info has it
+fn location(emu: &Emulator) -> String {
+    emu.debug_info
+        .as_ref()
+        .and_then(|d| d.describe(emu.pc as u32))
+        .map(|source| format!(" at {}", source))
+        .unwrap_or_default()
+}
+
 fn main() {
     let mut args = std::env::args();
     args.next();
@@ -7,10 +18,31 @@ fn main() {
     if let Some(input) = args.next() {
         let mut emu = Emulator::new();
 
-        emu.load_program(input, Endian::Little)
-            .expect("failed to load file");
+        let buffer = std::fs::read(input).expect("failed to load file");
+        if is_elf(&buffer) {
+            emu.load_elf_from_u8(&buffer)
+        } else {
+            emu.load_from_u8(&buffer, Endian::Little)
+        }
+        .expect("failed to load file");
+
+        // Debug info written by `mma --debug-info`
+        if let Some(path) = args.next() {
+            match DebugInfo::load(&path) {
+                Ok(debug_info) => emu.debug_info = Some(debug_info),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        emu.map_devices();
 
-        emu.run();
+        if let Err(e) = emu.run() {
+            eprintln!("{} [PC = {:#010x}]{}", e, emu.pc, location(&emu));
+            std::process::exit(1);
+        }
 
         let exit_code = emu.register.get(1) as i32;
 
@@ -0,0 +1,450 @@
+use crate::Access;
+use std::fmt;
+
+/// Block replaced when a set is full
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Replacement {
+    /// Least recently used
+    Lru,
+    /// First in, first out
+    Fifo,
+    /// Pseudo-random, the same on every run
+    Random,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WritePolicy {
+    /// Stores only write the cache, and dirty blocks are written to memory when evicted.
+    /// A store which misses loads the block.
+    WriteBack,
+    /// Stores write memory as well, and a store which misses does not load the block.
+    WriteThrough,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CacheConfig {
+    /// Size of the data in bytes
+    pub size: u32,
+    /// Size of a block in bytes
+    pub block_size: u32,
+    /// Blocks in a set, 1 for a direct mapped cache
+    pub associativity: u32,
+    pub replacement: Replacement,
+    pub write_policy: WritePolicy,
+    /// Record every access in `Cache::trace`
+    pub trace: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            size: 1024,
+            block_size: 16,
+            associativity: 1,
+            replacement: Replacement::Lru,
+            write_policy: WritePolicy::WriteBack,
+            trace: false,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn sets(&self) -> u32 {
+        self.size / (self.block_size * self.associativity)
+    }
+
+    /// Checks that the sizes are powers of two and the cache holds at least one set.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("size", self.size),
+            ("block size", self.block_size),
+            ("associativity", self.associativity),
+        ] {
+            if !value.is_power_of_two() {
+                return Err(format!("cache {} {} is not a power of two", name, value));
+            }
+        }
+        if self.block_size < 4 {
+            return Err(format!(
+                "cache block size {} is less than a word",
+                self.block_size
+            ));
+        }
+        let set_size = self.block_size.checked_mul(self.associativity);
+        if set_size.is_none_or(|set_size| set_size > self.size) {
+            return Err(format!(
+                "cache of {} bytes cannot hold {} blocks of {} bytes",
+                self.size, self.associativity, self.block_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct CacheStats {
+    /// Loads and instruction fetches
+    pub reads: u64,
+    pub writes: u64,
+    pub read_misses: u64,
+    pub write_misses: u64,
+    /// Valid blocks replaced by another
+    pub evictions: u64,
+    /// Dirty blocks written to memory
+    pub write_backs: u64,
+}
+
+impl CacheStats {
+    pub fn accesses(&self) -> u64 {
+        self.reads + self.writes
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.read_misses + self.write_misses
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.accesses() - self.misses()
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        match self.accesses() {
+            0 => 0.0,
+            n => self.hits() as f64 / n as f64,
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} accesses, {} hits, {} misses ({} read, {} write), hit rate {:.2}%, {} evictions, {} write backs",
+            self.accesses(),
+            self.hits(),
+            self.misses(),
+            self.read_misses,
+            self.write_misses,
+            100.0 * self.hit_rate(),
+            self.evictions,
+            self.write_backs
+        )
+    }
+}
+
+/// Access recorded in the trace of a cache
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CacheAccess {
+    pub access: Access,
+    pub address: u32,
+    pub set: u32,
+    pub hit: bool,
+    /// Address of the block replaced by this access
+    pub evicted: Option<u32>,
+}
+
+impl fmt::Display for CacheAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "R",
+            Access::Write => "W",
+            Access::Execute => "X",
+        };
+        write!(
+            f,
+            "{} {:#010x} set {} {}",
+            access,
+            self.address,
+            self.set,
+            if self.hit { "hit" } else { "miss" }
+        )?;
+        if let Some(evicted) = self.evicted {
+            write!(f, ", evicted {:#010x}", evicted)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Line {
+    valid: bool,
+    dirty: bool,
+    tag: u32,
+    /// Time of the last access, for LRU
+    used: u64,
+    /// Time the block was loaded, for FIFO
+    loaded: u64,
+}
+
+/// Set associative cache which keeps track of tags only
+pub struct Cache {
+    pub config: CacheConfig,
+    pub stats: CacheStats,
+    pub trace: Vec<CacheAccess>,
+    sets: Vec<Vec<Line>>,
+    time: u64,
+    /// State of the xorshift generator for random replacement
+    seed: u32,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Result<Self, String> {
+        config.validate()?;
+        Ok(Self {
+            sets: vec![
+                vec![Line::default(); config.associativity as usize];
+                config.sets() as usize
+            ],
+            config,
+            stats: CacheStats::default(),
+            trace: Vec::new(),
+            time: 0,
+            seed: 0x2545_f491,
+        })
+    }
+
+    fn random(&mut self) -> u32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+
+    /// Way of `set` to load a block into
+    fn victim(&mut self, set: usize) -> usize {
+        let lines = &self.sets[set];
+        if let Some(way) = lines.iter().position(|l| !l.valid) {
+            return way;
+        }
+        let ways = lines.len();
+        match self.config.replacement {
+            Replacement::Lru => (0..ways).min_by_key(|&w| lines[w].used).unwrap(),
+            Replacement::Fifo => (0..ways).min_by_key(|&w| lines[w].loaded).unwrap(),
+            Replacement::Random => self.random() as usize % ways,
+        }
+    }
+
+    /// Looks up `address`, loading its block on a miss.
+    pub fn access(&mut self, access: Access, address: u32) -> CacheAccess {
+        let sets = self.config.sets();
+        let block = address / self.config.block_size;
+        let set = block % sets;
+        let tag = block / sets;
+        let write = access == Access::Write;
+        let write_back = self.config.write_policy == WritePolicy::WriteBack;
+        self.time += 1;
+
+        if write {
+            self.stats.writes += 1;
+        } else {
+            self.stats.reads += 1;
+        }
+
+        let mut result = CacheAccess {
+            access,
+            address,
+            set,
+            hit: true,
+            evicted: None,
+        };
+
+        let time = self.time;
+        let lines = &mut self.sets[set as usize];
+        if let Some(line) = lines.iter_mut().find(|l| l.valid && l.tag == tag) {
+            line.used = time;
+            line.dirty |= write && write_back;
+        } else {
+            result.hit = false;
+            if write {
+                self.stats.write_misses += 1;
+            } else {
+                self.stats.read_misses += 1;
+            }
+
+            if !write || write_back {
+                let way = self.victim(set as usize);
+                let line = &mut self.sets[set as usize][way];
+                if line.valid {
+                    self.stats.evictions += 1;
+                    self.stats.write_backs += line.dirty as u64;
+                    result.evicted = Some((line.tag * sets + set) * self.config.block_size);
+                }
+                *line = Line {
+                    valid: true,
+                    dirty: write,
+                    tag,
+                    used: time,
+                    loaded: time,
+                };
+            }
+        }
+
+        if self.config.trace {
+            self.trace.push(result);
+        }
+        result
+    }
+}
+
+/// Caches in front of memory, shared by instructions and data or split between them
+pub enum Caches {
+    Unified(Cache),
+    Split { instruction: Cache, data: Cache },
+}
+
+impl Caches {
+    pub fn new(config: CacheConfig, split: bool) -> Result<Self, String> {
+        Ok(if split {
+            Caches::Split {
+                instruction: Cache::new(config)?,
+                data: Cache::new(config)?,
+            }
+        } else {
+            Caches::Unified(Cache::new(config)?)
+        })
+    }
+
+    /// Passes an instruction fetch to the instruction cache and loads and stores to the data
+    /// cache.
+    pub fn access(&mut self, access: Access, address: u32) -> CacheAccess {
+        match self {
+            Caches::Unified(cache) => cache.access(access, address),
+            Caches::Split { instruction, .. } if access == Access::Execute => {
+                instruction.access(access, address)
+            }
+            Caches::Split { data, .. } => data.access(access, address),
+        }
+    }
+
+    /// Every cache with its name
+    pub fn caches(&self) -> Vec<(&'static str, &Cache)> {
+        match self {
+            Caches::Unified(cache) => vec![("cache", cache)],
+            Caches::Split { instruction, data } => {
+                vec![("instruction cache", instruction), ("data cache", data)]
+            }
+        }
+    }
+
+    /// Statistics of every cache, one per line
+    pub fn report(&self) -> String {
+        self.caches()
+            .iter()
+            .map(|(name, cache)| format!("{}: {}\n", name, cache.stats))
+            .collect()
+    }
+}
+
+#[test]
+fn test_direct_mapped() {
+    let mut cache = Cache::new(CacheConfig {
+        size: 64,
+        block_size: 16,
+        trace: true,
+        ..Default::default()
+    })
+    .unwrap();
+
+    // 0x10010000 and 0x10010040 map to the same set
+    assert!(!cache.access(Access::Read, 0x10010000).hit);
+    assert!(cache.access(Access::Read, 0x1001000c).hit);
+    let access = cache.access(Access::Read, 0x10010040);
+    assert_eq!(access.set, 0);
+    assert_eq!(access.evicted, Some(0x10010000));
+    assert!(!cache.access(Access::Read, 0x10010004).hit);
+
+    assert_eq!(cache.stats.reads, 4);
+    assert_eq!(cache.stats.read_misses, 3);
+    assert_eq!(cache.stats.evictions, 2);
+    assert_eq!(cache.trace.len(), 4);
+    assert_eq!(
+        cache.trace[2].to_string(),
+        "R 0x10010040 set 0 miss, evicted 0x10010000"
+    );
+}
+
+#[test]
+fn test_replacement() {
+    let run = |replacement| {
+        let mut cache = Cache::new(CacheConfig {
+            size: 32,
+            block_size: 16,
+            associativity: 2,
+            replacement,
+            ..Default::default()
+        })
+        .unwrap();
+        // A B A C A: LRU keeps A, FIFO replaces it with C
+        for address in [0x0, 0x10, 0x0, 0x20, 0x0] {
+            cache.access(Access::Read, address);
+        }
+        cache.stats.read_misses
+    };
+
+    assert_eq!(run(Replacement::Lru), 3);
+    assert_eq!(run(Replacement::Fifo), 4);
+}
+
+#[test]
+fn test_write_policy() {
+    let config = CacheConfig {
+        size: 16,
+        block_size: 16,
+        ..Default::default()
+    };
+
+    // Write back allocates on a store and writes the dirty block when it is evicted
+    let mut cache = Cache::new(config).unwrap();
+    cache.access(Access::Write, 0x0);
+    assert!(cache.access(Access::Read, 0x4).hit);
+    cache.access(Access::Read, 0x10);
+    assert_eq!(cache.stats.write_backs, 1);
+
+    // Write through does not allocate on a store
+    let mut cache = Cache::new(CacheConfig {
+        write_policy: WritePolicy::WriteThrough,
+        ..config
+    })
+    .unwrap();
+    cache.access(Access::Write, 0x0);
+    assert!(!cache.access(Access::Read, 0x4).hit);
+    cache.access(Access::Read, 0x10);
+    assert_eq!(cache.stats.write_backs, 0);
+    assert_eq!(cache.stats.misses(), 3);
+
+    assert!(Cache::new(CacheConfig { size: 48, ..config }).is_err());
+}
+
+#[test]
+fn test_split() {
+    let mut caches = Caches::new(CacheConfig::default(), true).unwrap();
+    caches.access(Access::Execute, 0x00400000);
+    caches.access(Access::Read, 0x00400000);
+
+    // The data cache misses on the block already in the instruction cache
+    match &caches {
+        Caches::Split { instruction, data } => {
+            assert_eq!(instruction.stats.read_misses, 1);
+            assert_eq!(data.stats.read_misses, 1);
+        }
+        Caches::Unified(_) => unreachable!(),
+    }
+    assert!(caches
+        .report()
+        .starts_with("instruction cache: 1 accesses, 0 hits"));
+}
+
+#[test]
+fn test_validate() {
+    let config = CacheConfig {
+        size: 1024,
+        block_size: 1 << 16,
+        associativity: 1 << 16,
+        ..Default::default()
+    };
+    assert_eq!(
+        config.validate(),
+        Err("cache of 1024 bytes cannot hold 65536 blocks of 65536 bytes".to_string())
+    );
+    assert!(CacheConfig::default().validate().is_ok());
+}
@@ -0,0 +1,74 @@
+use crate::EmulatorConfig;
+use crate::Permission;
+use crate::Segment;
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        Self {
+            text: Segment {
+                base: 0x0040_0000,
+                size: 0x0004_0000,
+            },
+            small_data: Segment {
+                base: 0x1000_0000,
+                size: 0x0001_0000,
+            },
+            data: Segment {
+                base: 0x1001_0000,
+                size: 0x0003_0000,
+            },
+            heap: Segment {
+                base: 0x1004_0000,
+                size: 0x0ffc_0000,
+            },
+            stack: Segment {
+                base: 0x7ff0_0000,
+                size: 0x0010_0000,
+            },
+            ktext: Segment {
+                base: 0x8000_0000,
+                size: 0x0001_0000,
+            },
+            kdata: Segment {
+                base: 0x9000_0000,
+                size: 0x0010_0000,
+            },
+            stack_pointer: 0x7fff_effc,
+            global_pointer: 0x1000_8000,
+            exception_vector: 0x8000_0180,
+            delay_slot: false,
+            load_delay: false,
+            check_load_hazards: false,
+            check_heap: false,
+            random_seed: 0,
+            clock_start: 0,
+            instructions_per_ms: 1000,
+            trace: true,
+        }
+    }
+}
+
+impl EmulatorConfig {
+    /// Segments with the permission of their pages
+    pub fn segments(&self) -> [(Segment, Permission); 7] {
+        [
+            (self.text, Permission::RX),
+            (self.small_data, Permission::RW),
+            (self.data, Permission::RW),
+            (self.heap, Permission::RW),
+            (self.stack, Permission::RW),
+            (self.ktext, Permission::RX),
+            (self.kdata, Permission::RW),
+        ]
+    }
+}
+
+impl Segment {
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.base && address - self.base < self.size
+    }
+
+    pub fn end(&self) -> u32 {
+        self.base.wrapping_add(self.size)
+    }
+}
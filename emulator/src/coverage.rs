@@ -0,0 +1,136 @@
+use crate::debug::SourceLine;
+use crate::predictor::{is_conditional_branch, Branch};
+use crate::Memory;
+use std::collections::BTreeMap;
+
+/// Instructions executed and branch directions taken by a run
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Coverage {
+    /// Executions of each instruction by address
+    pub executed: BTreeMap<u32, u64>,
+    /// Times each conditional branch was taken and not taken
+    pub branches: BTreeMap<u32, (u64, u64)>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, pc: u32, branch: Option<&Branch>) {
+        *self.executed.entry(pc).or_default() += 1;
+        if let Some(branch) = branch {
+            let (taken, not_taken) = self.branches.entry(pc).or_default();
+            if branch.taken {
+                *taken += 1;
+            } else {
+                *not_taken += 1;
+            }
+        }
+    }
+
+    /// Report in the lcov tracefile format for the instructions in `lines`.
+    ///
+    /// A line is executed as many times as the instruction on it executed most, and each
+    /// conditional branch, which is read from `memory`, has a taken and a not taken direction.
+    pub fn lcov(&self, memory: &Memory, lines: &BTreeMap<u32, SourceLine>) -> String {
+        // Execution count and branches (taken, not taken) of each line by file
+        type Line = (u64, Vec<Option<(u64, u64)>>);
+        let mut files: BTreeMap<&str, BTreeMap<usize, Line>> = BTreeMap::new();
+        for (address, source) in lines.iter() {
+            let count = self.executed.get(address).copied().unwrap_or(0);
+            let line = files
+                .entry(source.file.as_str())
+                .or_default()
+                .entry(source.line)
+                .or_default();
+            line.0 = line.0.max(count);
+
+            let code = memory.fetch(*address).unwrap_or(0);
+            if is_conditional_branch(code) {
+                line.1.push(
+                    (count > 0).then(|| self.branches.get(address).copied().unwrap_or_default()),
+                );
+            }
+        }
+
+        let mut report = String::new();
+        for (file, lines) in files {
+            report.push_str(&format!("TN:\nSF:{}\n", file));
+
+            let mut found = 0;
+            let mut hit = 0;
+            for (line, (_, branches)) in lines.iter() {
+                for (block, branch) in branches.iter().enumerate() {
+                    for (n, count) in [branch.map(|b| b.0), branch.map(|b| b.1)]
+                        .iter()
+                        .enumerate()
+                    {
+                        let taken = match count {
+                            Some(count) => count.to_string(),
+                            None => "-".to_string(),
+                        };
+                        report.push_str(&format!("BRDA:{},{},{},{}\n", line, block, n, taken));
+                        found += 1;
+                        hit += matches!(count, Some(c) if *c > 0) as usize;
+                    }
+                }
+            }
+            report.push_str(&format!("BRF:{}\nBRH:{}\n", found, hit));
+
+            for (line, (count, _)) in lines.iter() {
+                report.push_str(&format!("DA:{},{}\n", line, count));
+            }
+            let executed = lines.values().filter(|(count, _)| *count > 0).count();
+            report.push_str(&format!(
+                "LF:{}\nLH:{}\nend_of_record\n",
+                lines.len(),
+                executed
+            ));
+        }
+        report
+    }
+}
+
+#[test]
+fn test_lcov() {
+    use crate::EmulatorConfig;
+
+    let mut memory = Memory::new(&EmulatorConfig::default());
+    // L: addi $t0, $t0, -1; bne $t0, $zero, L; jr $ra; syscall
+    for (i, code) in [0x2108ffff, 0x1500fffe, 0x03e00008, 0x0000000c]
+        .iter()
+        .enumerate()
+    {
+        memory.load_word(0x00400000 + 4 * i as u32, *code).unwrap();
+    }
+
+    let mut coverage = Coverage::new();
+    for taken in [true, false] {
+        coverage.record(0x00400000, None);
+        let branch = Branch {
+            pc: 0x00400004,
+            target: 0x00400000,
+            taken,
+        };
+        coverage.record(0x00400004, Some(&branch));
+    }
+    coverage.record(0x00400008, None);
+
+    let line = |line| SourceLine {
+        file: "main.s".to_string(),
+        line,
+        column: 1,
+    };
+    let lines = BTreeMap::from([
+        (0x00400000, line(2)),
+        (0x00400004, line(3)),
+        (0x00400008, line(4)),
+        (0x0040000c, line(5)),
+    ]);
+    assert_eq!(
+        coverage.lcov(&memory, &lines),
+        "TN:\nSF:main.s\nBRDA:3,0,0,1\nBRDA:3,0,1,1\nBRF:2\nBRH:2\n\
+         DA:2,2\nDA:3,2\nDA:4,1\nDA:5,0\nLF:4\nLH:3\nend_of_record\n"
+    );
+}
@@ -0,0 +1,94 @@
+use crate::Binary;
+use crate::Cp0;
+
+/// Interrupts are enabled
+pub const IE: Binary = 0x1;
+/// Exception level, set while an exception is handled
+pub const EXL: Binary = 0x2;
+/// Interrupt pending and mask bits of Cause and Status
+const INTERRUPTS: Binary = 0xff00;
+/// Pending bits of Cause which are raised by devices (IP2-IP7)
+const HARDWARE_INTERRUPTS: Binary = 0xfc00;
+/// Pending bits of Cause which the program may set (IP0 and IP1)
+const SOFTWARE_INTERRUPTS: Binary = 0x0300;
+const EXC_CODE: Binary = 0x7c;
+
+impl Cp0 {
+    pub const STATUS: Binary = 12;
+    pub const CAUSE: Binary = 13;
+    pub const EPC: Binary = 14;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, idx: Binary) -> Binary {
+        match idx {
+            Cp0::STATUS => self.status,
+            Cp0::CAUSE => self.cause,
+            Cp0::EPC => self.epc,
+            _ => 0,
+        }
+    }
+
+    pub fn set(&mut self, idx: Binary, value: Binary) {
+        match idx {
+            Cp0::STATUS => self.status = value,
+            Cp0::CAUSE => {
+                self.cause = (self.cause & !SOFTWARE_INTERRUPTS) | (value & SOFTWARE_INTERRUPTS)
+            }
+            Cp0::EPC => self.epc = value,
+            _ => {}
+        }
+    }
+
+    /// Sets the hardware interrupt lines of Cause, bit 0 of `lines` being IP2.
+    pub fn set_interrupts(&mut self, lines: u32) {
+        self.cause =
+            (self.cause & !HARDWARE_INTERRUPTS) | ((lines << 10) as Binary & HARDWARE_INTERRUPTS);
+    }
+
+    /// An interrupt is pending, not masked, and interrupts are enabled outside of a handler
+    pub fn interrupt_pending(&self) -> bool {
+        self.status & (IE | EXL) == IE && self.status & self.cause & INTERRUPTS != 0
+    }
+
+    /// Enters the exception level for the exception `code` taken at `pc`.
+    pub fn enter(&mut self, code: Binary, pc: Binary) {
+        self.epc = pc;
+        self.cause = (self.cause & !EXC_CODE) | ((code << 2) & EXC_CODE);
+        self.status |= EXL;
+    }
+
+    /// Leaves the exception level, returning the address to continue from.
+    pub fn eret(&mut self) -> Binary {
+        self.status &= !EXL;
+        self.epc
+    }
+}
+
+#[test]
+fn test_cp0() {
+    let mut cp0 = Cp0::new();
+
+    cp0.set_interrupts(1 << 5);
+    assert_eq!(cp0.cause, 0x8000);
+    // Disabled
+    assert!(!cp0.interrupt_pending());
+    cp0.set(Cp0::STATUS, 0x8000 | IE);
+    assert!(cp0.interrupt_pending());
+    // Masked
+    cp0.set(Cp0::STATUS, 0x0400 | IE);
+    assert!(!cp0.interrupt_pending());
+
+    // Only the software interrupts are writable
+    cp0.set(Cp0::CAUSE, -1);
+    assert_eq!(cp0.cause, 0x8300);
+
+    cp0.set(Cp0::STATUS, 0xff00 | IE);
+    cp0.enter(0, 0x0040_0010);
+    assert_eq!(cp0.get(Cp0::EPC), 0x0040_0010);
+    assert!(!cp0.interrupt_pending());
+    assert_eq!(cp0.eret(), 0x0040_0010);
+    assert!(cp0.interrupt_pending());
+}
@@ -0,0 +1,246 @@
+use crate::decode::{II, RI};
+use crate::emu::opcode;
+use crate::Binary;
+use crate::Cp1;
+use crate::Exception;
+use crate::Memory;
+use crate::Register;
+
+/// `fmt` field of single precision operands
+pub const FMT_S: Binary = 16;
+/// `fmt` field of double precision operands
+pub const FMT_D: Binary = 17;
+/// `fmt` field of 32bit integer operands
+pub const FMT_W: Binary = 20;
+/// `rs` field of `bc1f` and `bc1t`
+pub const BC: Binary = 8;
+
+/// Bits of FCSR which select the rounding mode
+const ROUNDING_MODE: u32 = 0x3;
+
+/// Bit of FCSR which holds the condition code `cc`
+fn condition_bit(cc: Binary) -> u32 {
+    match cc {
+        0 => 1 << 23,
+        _ => 1 << (24 + cc),
+    }
+}
+
+impl Cp1 {
+    /// Control register which `cfc1` and `ctc1` access as FCSR
+    pub const FCSR: Binary = 31;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn single(&self, idx: Binary) -> f32 {
+        f32::from_bits(self.fpr[idx as usize])
+    }
+
+    pub fn set_single(&mut self, idx: Binary, value: f32) {
+        self.fpr[idx as usize] = value.to_bits();
+    }
+
+    /// Double held by the even register of `idx` and the next one
+    pub fn double(&self, idx: Binary) -> f64 {
+        let idx = idx as usize & !1;
+        f64::from_bits((self.fpr[idx + 1] as u64) << 32 | self.fpr[idx] as u64)
+    }
+
+    pub fn set_double(&mut self, idx: Binary, value: f64) {
+        let idx = idx as usize & !1;
+        let bits = value.to_bits();
+        self.fpr[idx] = bits as u32;
+        self.fpr[idx + 1] = (bits >> 32) as u32;
+    }
+
+    pub fn condition(&self, cc: Binary) -> bool {
+        self.fcsr & condition_bit(cc) != 0
+    }
+
+    /// Whether `bc1f` or `bc1t` with the `rt` field `rt` is taken
+    pub fn branch_taken(&self, rt: Binary) -> bool {
+        self.condition(rt >> 2) == (rt & 1 == 1)
+    }
+
+    pub fn set_condition(&mut self, cc: Binary, value: bool) {
+        if value {
+            self.fcsr |= condition_bit(cc);
+        } else {
+            self.fcsr &= !condition_bit(cc);
+        }
+    }
+
+    /// Rounds `value` to an integer in the rounding mode of FCSR.
+    pub fn round(&self, value: f64) -> f64 {
+        match self.fcsr & ROUNDING_MODE {
+            0 => value.round_ties_even(),
+            1 => value.trunc(),
+            2 => value.ceil(),
+            _ => value.floor(),
+        }
+    }
+
+    /// Operand `idx` in the format `fmt`
+    fn read(&self, fmt: Binary, idx: Binary) -> f64 {
+        match fmt {
+            FMT_S => self.single(idx) as f64,
+            FMT_D => self.double(idx),
+            _ => self.fpr[idx as usize] as i32 as f64,
+        }
+    }
+
+    /// Writes `value` to `idx` in the format `fmt`.
+    ///
+    /// Integers out of range and NaN are written as `0x7fffffff`, the default result of an
+    /// invalid conversion.
+    fn write(&mut self, fmt: Binary, idx: Binary, value: f64) {
+        match fmt {
+            FMT_S => self.set_single(idx, value as f32),
+            FMT_D => self.set_double(idx, value),
+            _ => {
+                let value = self.round(value);
+                self.fpr[idx as usize] = if value >= i32::MIN as f64 && value <= i32::MAX as f64 {
+                    value as i32 as u32
+                } else {
+                    i32::MAX as u32
+                };
+            }
+        }
+    }
+}
+
+/// Arithmetic, conversion and comparison of the format `i.rs`, where `ft` is `i.rt`, `fs` is
+/// `i.rd` and `fd` is `i.sh`
+fn arithmetic(cp1: &mut Cp1, i: &RI) -> bool {
+    let fmt = i.rs;
+    let (fs, ft, fd) = (cp1.read(fmt, i.rd), cp1.read(fmt, i.rt), i.sh);
+    let float = fmt == FMT_S || fmt == FMT_D;
+    match i.fc {
+        0x0 if float => cp1.write(fmt, fd, fs + ft),
+        0x1 if float => cp1.write(fmt, fd, fs - ft),
+        0x2 if float => cp1.write(fmt, fd, fs * ft),
+        0x3 if float => cp1.write(fmt, fd, fs / ft),
+        0x5 if float => cp1.write(fmt, fd, fs.abs()),
+        // Moves the bits, which may be a NaN
+        0x6 if fmt == FMT_S => cp1.fpr[fd as usize] = cp1.fpr[i.rd as usize],
+        0x6 if fmt == FMT_D => cp1.set_double(fd, cp1.double(i.rd)),
+        0x7 if float => cp1.write(fmt, fd, -fs),
+        0x20 if fmt != FMT_S => cp1.write(FMT_S, fd, fs),
+        0x21 if fmt != FMT_D => cp1.write(FMT_D, fd, fs),
+        0x24 if float => cp1.write(FMT_W, fd, fs),
+        // Compare, whose condition has the bits less than, equal and unordered
+        0x30..=0x3f if float => {
+            let condition = i.fc & 0x7;
+            let unordered = fs.is_nan() || ft.is_nan();
+            let result = (condition & 0x4 != 0 && fs < ft)
+                || (condition & 0x2 != 0 && fs == ft)
+                || (condition & 0x1 != 0 && unordered);
+            cp1.set_condition(i.sh >> 2, result);
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Instructions of coprocessor 1, `lwc1` and `swc1`, returning the next pc
+pub fn coprocessor1(
+    register: &mut Register,
+    cp1: &mut Cp1,
+    memory: &mut Memory,
+    pc: Binary,
+    code: Binary,
+) -> Result<Option<Binary>, Exception> {
+    let ii = II::decode(code);
+    let address = register.get(ii.rs).wrapping_add(ii.im) as u32;
+    match opcode(code) {
+        // Load Word to Coprocessor 1
+        0x31 => {
+            cp1.fpr[ii.rt as usize] = memory.read_word(address)? as u32;
+            return Ok(Some(pc + 4));
+        }
+        // Store Word from Coprocessor 1
+        0x39 => {
+            memory.write_word(address, cp1.fpr[ii.rt as usize] as Binary)?;
+            return Ok(Some(pc + 4));
+        }
+        0x11 => {}
+        _ => return Ok(None),
+    }
+
+    let i = RI::decode(code);
+    match i.rs {
+        // Move From Coprocessor 1
+        0x0 => register.set(i.rt, cp1.fpr[i.rd as usize] as Binary),
+        // Move Control From Coprocessor 1
+        0x2 if i.rd == Cp1::FCSR => register.set(i.rt, cp1.fcsr as Binary),
+        // Move To Coprocessor 1
+        0x4 => cp1.fpr[i.rd as usize] = register.get(i.rt) as u32,
+        // Move Control To Coprocessor 1
+        0x6 if i.rd == Cp1::FCSR => cp1.fcsr = register.get(i.rt) as u32,
+        // Branch On FP False and Branch On FP True, by condition code `rt >> 2`
+        BC => {
+            if cp1.branch_taken(ii.rt) {
+                return Ok(Some(pc.wrapping_add(4 + (ii.im << 2))));
+            }
+        }
+        FMT_S | FMT_D | FMT_W if arithmetic(cp1, &i) => {}
+        _ => return Ok(None),
+    }
+    Ok(Some(pc + 4))
+}
+
+#[test]
+#[allow(overflowing_literals)]
+fn test_coprocessor1() {
+    use crate::EmulatorConfig;
+
+    let mut register = Register::new();
+    let mut cp1 = Cp1::new();
+    let mut memory = Memory::new(&EmulatorConfig::default());
+    let mut run = |register: &mut Register, cp1: &mut Cp1, code| {
+        coprocessor1(register, cp1, &mut memory, 0x00400000, code).unwrap()
+    };
+
+    // mtc1 $t0, $f0; cvt.s.w $f0, $f0
+    register.set(8, 3);
+    run(&mut register, &mut cp1, 0x44880000);
+    run(&mut register, &mut cp1, 0x46800020);
+    assert_eq!(cp1.single(0), 3.0);
+
+    // div.s $f2, $f0, $f0; cvt.d.s $f4, $f2; add.d $f4, $f4, $f4
+    run(&mut register, &mut cp1, 0x46000083);
+    run(&mut register, &mut cp1, 0x46001121);
+    run(&mut register, &mut cp1, 0x46242100);
+    assert_eq!(cp1.double(4), 2.0);
+
+    // c.lt.d $f2, $f4 compares as doubles; bc1t 4
+    cp1.set_double(2, 1.5);
+    run(&mut register, &mut cp1, 0x4624103c);
+    assert!(cp1.condition(0));
+    assert_eq!(run(&mut register, &mut cp1, 0x45010004), Some(0x00400014));
+    // c.eq.d $f2, $f4; bc1t 4
+    run(&mut register, &mut cp1, 0x46241032);
+    assert_eq!(run(&mut register, &mut cp1, 0x45010004), Some(0x00400004));
+
+    // cvt.w.d $f6, $f2 rounds to even, then toward zero; mfc1 $t1, $f6
+    cp1.set_double(2, 2.5);
+    run(&mut register, &mut cp1, 0x462011a4);
+    run(&mut register, &mut cp1, 0x44093000);
+    assert_eq!(register.get(9), 2);
+    cp1.fcsr = 1;
+    cp1.set_double(2, -2.7);
+    run(&mut register, &mut cp1, 0x462011a4);
+    assert_eq!(cp1.fpr[6] as i32, -2);
+
+    // neg.s $f8, $f0; swc1 $f8, 0($gp); lwc1 $f9, 0($gp)
+    register.set(28, 0x10010000);
+    run(&mut register, &mut cp1, 0x46000207);
+    run(&mut register, &mut cp1, 0xe7880000);
+    run(&mut register, &mut cp1, 0xc7890000);
+    assert_eq!(cp1.single(9), -3.0);
+
+    // mul.s with a word operand is not an instruction
+    assert_eq!(run(&mut register, &mut cp1, 0x46800002), None);
+}
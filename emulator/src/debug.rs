@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+/// Source position of an instruction
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceLine {
+    pub file: String,
+    /// Line number (1-origin)
+    pub line: usize,
+    /// Column the instruction starts at (1-origin)
+    pub column: usize,
+}
+
+/// Source positions and labels of a program, read from the side table written by
+/// `mma --debug-info`
+///
+/// The table has one entry per line:
+///
+/// ```text
+/// file <index> <path>
+/// line <address> <file index> <line> <column>
+/// label <address> <name>
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct DebugInfo {
+    /// Position of the instruction at each address
+    pub lines: BTreeMap<u32, SourceLine>,
+    /// Labels by address
+    pub labels: BTreeMap<u32, String>,
+}
+
+fn parse_address(text: &str) -> Result<u32, String> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u32::from_str_radix(digits, 16).map_err(|_| format!("invalid address `{}`", text))
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    text.parse()
+        .map_err(|_| format!("invalid number `{}`", text))
+}
+
+impl DebugInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut files = Vec::new();
+        let mut info = Self::new();
+        for (n, entry) in text.lines().enumerate() {
+            let error = |e: String| format!("debug info line {}: {}", n + 1, e);
+            let fields: Vec<&str> = entry.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {}
+                ["file", index, ..] => {
+                    if parse_number(index).map_err(error)? != files.len() {
+                        return Err(error(format!("file {} is out of order", index)));
+                    }
+                    // Paths may contain spaces
+                    let path =
+                        entry.trim_start()["file".len()..].trim_start()[index.len()..].trim_start();
+                    files.push(path.to_string());
+                }
+                ["line", address, file, line, column] => {
+                    let file = files
+                        .get(parse_number(file).map_err(error)?)
+                        .ok_or_else(|| error(format!("unknown file {}", file)))?;
+                    info.lines.insert(
+                        parse_address(address).map_err(error)?,
+                        SourceLine {
+                            file: file.clone(),
+                            line: parse_number(line).map_err(error)?,
+                            column: parse_number(column).map_err(error)?,
+                        },
+                    );
+                }
+                ["label", address, name] => {
+                    info.labels
+                        .insert(parse_address(address).map_err(error)?, name.to_string());
+                }
+                _ => return Err(error(format!("unexpected entry `{}`", entry))),
+            }
+        }
+        Ok(info)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text)
+    }
+
+    /// Address of the label `name`
+    pub fn address(&self, name: &str) -> Option<u32> {
+        self.labels
+            .iter()
+            .find(|(_, label)| label.as_str() == name)
+            .map(|(address, _)| *address)
+    }
+
+    /// Source position and label of `address`, such as `main.s:3:5 <L+4>`
+    pub fn describe(&self, address: u32) -> Option<String> {
+        let position = self
+            .lines
+            .get(&address)
+            .map(|p| format!("{}:{}:{}", p.file, p.line, p.column));
+        let label = symbolize(&self.labels, address).map(|label| format!("<{}>", label));
+        match (position, label) {
+            (Some(position), Some(label)) => Some(format!("{} {}", position, label)),
+            (position, label) => position.or(label),
+        }
+    }
+}
+
+/// Names `address` after the nearest label at or before it, e.g. `loop+8`.
+pub fn symbolize(labels: &BTreeMap<u32, String>, address: u32) -> Option<String> {
+    let (base, label) = labels.range(..=address).next_back()?;
+    Some(match address - base {
+        0 => label.clone(),
+        offset => format!("{}+{}", label, offset),
+    })
+}
+
+#[test]
+fn test_debug_info() {
+    let text = "file 0 main.s\n\
+                file 1 lib dir/util.s\n\
+                line 0x00400000 0 2 9\n\
+                line 0x00400004 1 7 5\n\
+                label 0x00400000 main\n\
+                label 0x10010000 A\n";
+    let info = DebugInfo::parse(text).unwrap();
+    assert_eq!(
+        info.lines[&0x00400004],
+        SourceLine {
+            file: "lib dir/util.s".to_string(),
+            line: 7,
+            column: 5
+        }
+    );
+    assert_eq!(info.address("A"), Some(0x10010000));
+    assert_eq!(info.address("B"), None);
+    assert_eq!(
+        info.describe(0x00400004),
+        Some("lib dir/util.s:7:5 <main+4>".to_string())
+    );
+    assert_eq!(info.describe(0x10010008), Some("<A+8>".to_string()));
+    assert_eq!(info.describe(0x003ffffc), None);
+
+    assert_eq!(
+        DebugInfo::parse("line 0x00400000 0 1 1"),
+        Err("debug info line 1: unknown file 0".to_string())
+    );
+    assert!(DebugInfo::parse("label main").is_err());
+}
@@ -1,4 +1,6 @@
+use crate::cp1::{BC, FMT_D, FMT_S, FMT_W};
 use crate::Binary;
+use crate::Register;
 
 pub struct Mask {}
 #[allow(dead_code, overflowing_literals)]
@@ -60,6 +62,195 @@ impl JI {
     }
 }
 
+/// Kind of instruction counted by the profiler
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum Class {
+    Alu,
+    /// Coprocessor 1 except its loads, stores and branches
+    Float,
+    Memory,
+    Branch,
+    Jump,
+    Syscall,
+    /// Coprocessor 0 and unknown instructions
+    Other,
+}
+
+impl Class {
+    pub fn of(code: Binary) -> Class {
+        match mnemonic(code) {
+            "lw" | "sw" | "lwc1" | "swc1" => Class::Memory,
+            "beq" | "bne" | "bc1f" | "bc1t" => Class::Branch,
+            "mfc1" | "mtc1" | "cfc1" | "ctc1" => Class::Float,
+            m if m.contains('.') => Class::Float,
+            "j" | "jal" | "jr" => Class::Jump,
+            "syscall" => Class::Syscall,
+            "mfc0" | "mtc0" | "eret" | "unknown" => Class::Other,
+            _ => Class::Alu,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Alu => "ALU",
+            Class::Float => "FPU",
+            Class::Memory => "memory",
+            Class::Branch => "branch",
+            Class::Jump => "jump",
+            Class::Syscall => "syscall",
+            Class::Other => "other",
+        }
+    }
+}
+
+/// Mnemonic of the instruction `code`, or `unknown` if the emulator does not support it
+pub fn mnemonic(code: Binary) -> &'static str {
+    let ri = RI::decode(code);
+    match (code as u32) >> 26 {
+        0x0 if code == 0 => "nop",
+        0x0 => match ri.fc {
+            0x0 => "sll",
+            0x2 => "srl",
+            0x8 => "jr",
+            0xc => "syscall",
+            0x10 => "mfhi",
+            0x12 => "mflo",
+            0x18 => "mult",
+            0x19 => "multu",
+            0x1a => "div",
+            0x1b => "divu",
+            0x20 => "add",
+            0x21 => "addu",
+            0x22 => "sub",
+            0x23 => "subu",
+            0x24 => "and",
+            0x25 => "or",
+            0x2a => "slt",
+            _ => "unknown",
+        },
+        0x2 => "j",
+        0x3 => "jal",
+        0x4 => "beq",
+        0x5 => "bne",
+        0x8 => "addi",
+        0x9 => "addiu",
+        0xd => "ori",
+        0xf => "lui",
+        0x10 => match ri.rs {
+            0x0 => "mfc0",
+            0x4 => "mtc0",
+            0x10 if ri.fc == 0x18 => "eret",
+            _ => "unknown",
+        },
+        0x11 => float_mnemonic(&ri),
+        0x23 => "lw",
+        0x2b => "sw",
+        0x31 => "lwc1",
+        0x39 => "swc1",
+        _ => "unknown",
+    }
+}
+
+/// Mnemonic of the coprocessor 1 instruction `ri`
+fn float_mnemonic(ri: &RI) -> &'static str {
+    match (ri.rs, ri.fc) {
+        (0x0, _) => "mfc1",
+        (0x2, _) => "cfc1",
+        (0x4, _) => "mtc1",
+        (0x6, _) => "ctc1",
+        (BC, _) if ri.rt & 1 == 0 => "bc1f",
+        (BC, _) => "bc1t",
+        (FMT_S, 0x0) => "add.s",
+        (FMT_D, 0x0) => "add.d",
+        (FMT_S, 0x1) => "sub.s",
+        (FMT_D, 0x1) => "sub.d",
+        (FMT_S, 0x2) => "mul.s",
+        (FMT_D, 0x2) => "mul.d",
+        (FMT_S, 0x3) => "div.s",
+        (FMT_D, 0x3) => "div.d",
+        (FMT_S, 0x5) => "abs.s",
+        (FMT_D, 0x5) => "abs.d",
+        (FMT_S, 0x6) => "mov.s",
+        (FMT_D, 0x6) => "mov.d",
+        (FMT_S, 0x7) => "neg.s",
+        (FMT_D, 0x7) => "neg.d",
+        (FMT_D, 0x20) => "cvt.s.d",
+        (FMT_W, 0x20) => "cvt.s.w",
+        (FMT_S, 0x21) => "cvt.d.s",
+        (FMT_W, 0x21) => "cvt.d.w",
+        (FMT_S, 0x24) => "cvt.w.s",
+        (FMT_D, 0x24) => "cvt.w.d",
+        (FMT_S, 0x32) => "c.eq.s",
+        (FMT_D, 0x32) => "c.eq.d",
+        (FMT_S, 0x3c) => "c.lt.s",
+        (FMT_D, 0x3c) => "c.lt.d",
+        (FMT_S, 0x3e) => "c.le.s",
+        (FMT_D, 0x3e) => "c.le.d",
+        _ => "unknown",
+    }
+}
+
+/// Register operand `$name` of the general-purpose register `idx`
+fn gpr(idx: Binary) -> String {
+    format!("${}", Register::name(idx))
+}
+
+/// Assembly of the instruction `code` at `pc`, with registers by their ABI names and branch and
+/// jump targets as addresses
+pub fn disassemble(pc: u32, code: Binary) -> String {
+    let mnemonic = mnemonic(code);
+    let ri = RI::decode(code);
+    let ii = II::decode(code);
+    let branch_target = pc.wrapping_add(4).wrapping_add((ii.im << 2) as u32);
+    let fpr = |idx: Binary| format!("$f{}", idx);
+    let operands = match mnemonic {
+        "nop" | "syscall" | "eret" => String::new(),
+        "sll" | "srl" => format!("{}, {}, {}", gpr(ri.rd), gpr(ri.rt), ri.sh),
+        "jr" => gpr(ri.rs),
+        "mfhi" | "mflo" => gpr(ri.rd),
+        "mult" | "multu" | "div" | "divu" => format!("{}, {}", gpr(ri.rs), gpr(ri.rt)),
+        "add" | "addu" | "sub" | "subu" | "and" | "or" | "slt" => {
+            format!("{}, {}, {}", gpr(ri.rd), gpr(ri.rs), gpr(ri.rt))
+        }
+        "j" | "jal" => format!(
+            "{:#010x}",
+            (pc.wrapping_add(4) & 0xf000_0000) | (JI::decode(code).ad << 2) as u32
+        ),
+        "beq" | "bne" => format!("{}, {}, {:#010x}", gpr(ii.rs), gpr(ii.rt), branch_target),
+        "addi" | "addiu" => format!("{}, {}, {}", gpr(ii.rt), gpr(ii.rs), ii.im),
+        "ori" => format!("{}, {}, {:#x}", gpr(ii.rt), gpr(ii.rs), ii.im & 0xffff),
+        "lui" => format!("{}, {:#x}", gpr(ii.rt), ii.im & 0xffff),
+        "lw" | "sw" => format!("{}, {}({})", gpr(ii.rt), ii.im, gpr(ii.rs)),
+        "lwc1" | "swc1" => format!("{}, {}({})", fpr(ii.rt), ii.im, gpr(ii.rs)),
+        "mfc0" | "mtc0" => format!("{}, ${}", gpr(ri.rt), ri.rd),
+        "mfc1" | "mtc1" => format!("{}, {}", gpr(ri.rt), fpr(ri.rd)),
+        "cfc1" | "ctc1" => format!("{}, ${}", gpr(ri.rt), ri.rd),
+        "bc1f" | "bc1t" => match ii.rt >> 2 {
+            0 => format!("{:#010x}", branch_target),
+            cc => format!("{}, {:#010x}", cc, branch_target),
+        },
+        "unknown" => return format!(".word {:#010x}", code),
+        m if m.starts_with("c.") => match ri.sh >> 2 {
+            0 => format!("{}, {}", fpr(ri.rd), fpr(ri.rt)),
+            cc => format!("{}, {}, {}", cc, fpr(ri.rd), fpr(ri.rt)),
+        },
+        m if m.starts_with("add.")
+            || m.starts_with("sub.")
+            || m.starts_with("mul.")
+            || m.starts_with("div.") =>
+        {
+            format!("{}, {}, {}", fpr(ri.sh), fpr(ri.rd), fpr(ri.rt))
+        }
+        // abs, mov, neg and cvt
+        _ => format!("{}, {}", fpr(ri.sh), fpr(ri.rd)),
+    };
+    if operands.is_empty() {
+        mnemonic.to_string()
+    } else {
+        format!("{} {}", mnemonic, operands)
+    }
+}
+
 #[test]
 #[allow(overflowing_literals)]
 fn test_ii_decode() {
@@ -104,3 +295,41 @@ fn test_ji_decode() {
     let ji = JI::decode(0b000000_00000_00000_0000000001111111);
     assert_eq!(ji, JI { ad: 127 });
 }
+
+#[test]
+#[allow(overflowing_literals)]
+fn test_mnemonic() {
+    assert_eq!(mnemonic(0x03e00008), "jr");
+    assert_eq!(mnemonic(0x0c100000), "jal");
+    assert_eq!(mnemonic(0x8d090000), "lw");
+    assert_eq!(mnemonic(0), "nop");
+    assert_eq!(mnemonic(0x42000018), "eret");
+    assert_eq!(mnemonic(0xfc000000), "unknown");
+
+    assert_eq!(Class::of(0x2129ffff), Class::Alu);
+    assert_eq!(Class::of(0x1409fffe), Class::Branch);
+    assert_eq!(Class::of(0x0000000c), Class::Syscall);
+
+    assert_eq!(mnemonic(0x4624103c), "c.lt.d");
+    assert_eq!(mnemonic(0x45010004), "bc1t");
+    assert_eq!(Class::of(0x46800020), Class::Float);
+    assert_eq!(Class::of(0xc7890000), Class::Memory);
+}
+
+#[test]
+#[allow(overflowing_literals)]
+fn test_disassemble() {
+    let pc = 0x00400000;
+    assert_eq!(disassemble(pc, 0x03e00008), "jr $ra");
+    assert_eq!(disassemble(pc, 0x0319c020), "add $t8, $t8, $t9");
+    assert_eq!(disassemble(pc, 0x2108ffff), "addi $t0, $t0, -1");
+    assert_eq!(disassemble(pc, 0x3c081001), "lui $t0, 0x1001");
+    assert_eq!(disassemble(pc, 0x8fde0004), "lw $fp, 4($fp)");
+    assert_eq!(disassemble(pc, 0x1500fffe), "bne $t0, $zero, 0x003ffffc");
+    assert_eq!(disassemble(pc, 0x0c100003), "jal 0x0040000c");
+    assert_eq!(disassemble(pc, 0x46000083), "div.s $f2, $f0, $f0");
+    assert_eq!(disassemble(pc, 0x4624103c), "c.lt.d $f2, $f4");
+    assert_eq!(disassemble(pc, 0x44093000), "mfc1 $t1, $f6");
+    assert_eq!(disassemble(pc, 0x0000000c), "syscall");
+    assert_eq!(disassemble(pc, 0xfc000000), ".word 0xfc000000");
+}
@@ -0,0 +1,39 @@
+use crate::Binary;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Device mapped into the address space of the emulator
+///
+/// Offsets are relative to the address the device is mapped at and always word aligned.
+pub trait Device {
+    fn read(&mut self, offset: u32) -> Binary;
+
+    fn write(&mut self, offset: u32, value: Binary);
+
+    /// Advances the device by one instruction.
+    fn tick(&mut self) {}
+
+    /// Hardware interrupt lines raised by the device, bit 0 being IP2 of the Cause register.
+    fn interrupts(&self) -> u32 {
+        0
+    }
+}
+
+/// Lets the caller keep a handle to a device after mapping it.
+impl<D: Device> Device for Rc<RefCell<D>> {
+    fn read(&mut self, offset: u32) -> Binary {
+        self.borrow_mut().read(offset)
+    }
+
+    fn write(&mut self, offset: u32, value: Binary) {
+        self.borrow_mut().write(offset, value)
+    }
+
+    fn tick(&mut self) {
+        self.borrow_mut().tick()
+    }
+
+    fn interrupts(&self) -> u32 {
+        self.borrow().interrupts()
+    }
+}
@@ -0,0 +1,309 @@
+use crate::Binary;
+use crate::Emulator;
+use crate::Endian;
+use crate::Exception;
+use crate::Memory;
+use crate::Register;
+use std::fmt::Write;
+
+/// Bytes on each line of a memory dump
+const BYTES_PER_LINE: usize = 16;
+
+/// Size of the units a memory range is shown in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Unit {
+    Byte,
+    Half,
+    Word,
+}
+
+impl Unit {
+    pub fn size(self) -> usize {
+        match self {
+            Unit::Byte => 1,
+            Unit::Half => 2,
+            Unit::Word => 4,
+        }
+    }
+}
+
+/// How the value of a register or memory unit is shown
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Interpretation {
+    Hex,
+    Signed,
+    Unsigned,
+}
+
+impl Interpretation {
+    /// `value` of `size` bytes, padded to the widest value of that size
+    fn format(self, value: u32, size: usize) -> String {
+        let bits = 8 * size as u32;
+        match self {
+            Interpretation::Hex => format!("{:0width$x}", value, width = 2 * size),
+            Interpretation::Unsigned => {
+                let width = [3, 5, 10][size / 2];
+                format!("{:>width$}", value, width = width)
+            }
+            Interpretation::Signed => {
+                // Sign extends the lower `bits` bits
+                let value = ((value << (32 - bits)) as i32) >> (32 - bits);
+                let width = [4, 6, 11][size / 2];
+                format!("{:>width$}", value, width = width)
+            }
+        }
+    }
+}
+
+/// Values of the registers at one point of a run
+#[derive(Debug, PartialEq, Clone)]
+pub struct RegisterSnapshot {
+    pub pc: Binary,
+    /// General-purpose registers by number
+    pub gpr: [Binary; 32],
+    pub hi: Binary,
+    pub lo: Binary,
+    /// Floating-point registers by number
+    pub fpr: [u32; 32],
+    pub fcsr: u32,
+}
+
+impl RegisterSnapshot {
+    /// Value of the general-purpose register, `hi`, `lo` or `pc` named `name`, with or without
+    /// `$`
+    pub fn get(&self, name: &str) -> Option<Binary> {
+        let name = name.strip_prefix('$').unwrap_or(name);
+        match name {
+            "pc" => Some(self.pc),
+            "hi" => Some(self.hi),
+            "lo" => Some(self.lo),
+            _ => {
+                let idx = Register::number(name).or_else(|| name.parse().ok())?;
+                self.gpr.get(idx as usize).copied()
+            }
+        }
+    }
+
+    /// One line for each general-purpose register, `hi`, `lo` and `pc`
+    ///
+    /// ```text
+    /// $zero ($0)  0x00000000           0
+    /// ```
+    pub fn format(&self, interpretation: Interpretation) -> String {
+        let mut output = String::new();
+        let mut line = |name: String, value: Binary| {
+            let value = match interpretation {
+                Interpretation::Hex => format!("{:#010x}  {:>11}", value, value),
+                _ => interpretation.format(value as u32, 4),
+            };
+            writeln!(output, "{:<11} {}", name, value).unwrap();
+        };
+        for (idx, value) in self.gpr.iter().enumerate() {
+            let name = Register::name(idx as Binary);
+            line(format!("${} (${})", name, idx), *value);
+        }
+        line("hi".to_string(), self.hi);
+        line("lo".to_string(), self.lo);
+        line("pc".to_string(), self.pc);
+        output
+    }
+
+    /// One line for each floating-point register with its value as a single, and as a double
+    /// for the even registers, followed by FCSR
+    pub fn format_fpu(&self) -> String {
+        let mut output = String::new();
+        for (idx, bits) in self.fpr.iter().enumerate() {
+            write!(
+                output,
+                "{:<5} {:#010x}  {:<14?}",
+                format!("$f{}", idx),
+                bits,
+                f32::from_bits(*bits)
+            )
+            .unwrap();
+            if idx % 2 == 0 {
+                let double = (self.fpr[idx + 1] as u64) << 32 | *bits as u64;
+                write!(output, "  {:?}", f64::from_bits(double)).unwrap();
+            }
+            output = output.trim_end().to_string();
+            output.push('\n');
+        }
+        writeln!(output, "fcsr  {:#010x}", self.fcsr).unwrap();
+        output
+    }
+}
+
+/// Copy of the bytes of memory from `address`
+#[derive(Debug, PartialEq, Clone)]
+pub struct MemoryRange {
+    pub address: u32,
+    pub bytes: Vec<u8>,
+    /// Byte order which halves and words are read in
+    pub endian: Endian,
+}
+
+impl MemoryRange {
+    /// Units of `unit` from the start of the range, leaving out a partial unit at the end
+    pub fn values(&self, unit: Unit) -> Vec<u32> {
+        self.bytes
+            .chunks_exact(unit.size())
+            .map(|chunk| {
+                let fold = |value: u32, byte: &u8| value << 8 | *byte as u32;
+                match self.endian {
+                    Endian::Big => chunk.iter().fold(0, fold),
+                    Endian::Little => chunk.iter().rev().fold(0, fold),
+                }
+            })
+            .collect()
+    }
+
+    /// Lines of 16 bytes in `unit`s shown as `interpretation`
+    ///
+    /// ```text
+    /// 0x10010000:  00000005 00000006 00000000 00000000
+    /// ```
+    pub fn format(&self, unit: Unit, interpretation: Interpretation) -> String {
+        let values = self.values(unit);
+        let mut output = String::new();
+        for (n, line) in values.chunks(BYTES_PER_LINE / unit.size()).enumerate() {
+            let address = self.address.wrapping_add((n * BYTES_PER_LINE) as u32);
+            let line: Vec<_> = line
+                .iter()
+                .map(|v| interpretation.format(*v, unit.size()))
+                .collect();
+            writeln!(output, "{:#010x}:  {}", address, line.join(" ")).unwrap();
+        }
+        output
+    }
+
+    /// Lines of 16 bytes in hex with their ASCII characters, as `hexdump -C` shows them
+    ///
+    /// ```text
+    /// 10010000  48 69 00 00 05 00 00 00  00 00 00 00 00 00 00 00  |Hi..............|
+    /// ```
+    pub fn hexdump(&self) -> String {
+        let mut output = String::new();
+        for (n, line) in self.bytes.chunks(BYTES_PER_LINE).enumerate() {
+            let address = self.address.wrapping_add((n * BYTES_PER_LINE) as u32);
+            write!(output, "{:08x} ", address).unwrap();
+            for i in 0..BYTES_PER_LINE {
+                if i % 8 == 0 {
+                    output.push(' ');
+                }
+                match line.get(i) {
+                    Some(byte) => write!(output, "{:02x} ", byte).unwrap(),
+                    None => output.push_str("   "),
+                }
+            }
+            let ascii: String = line
+                .iter()
+                .map(|b| match b {
+                    0x20..=0x7e => *b as char,
+                    _ => '.',
+                })
+                .collect();
+            writeln!(output, " |{}|", ascii).unwrap();
+        }
+        output
+    }
+}
+
+impl Memory {
+    /// Copies `size` bytes from `address` regardless of the permissions and without reading
+    /// devices, as a debugger shows memory.
+    pub fn range(&self, address: u32, size: u32) -> Result<MemoryRange, Exception> {
+        // The segments are checked first, so that nothing is reserved for an unmapped range
+        let mut offset = 0;
+        while offset < size {
+            let next = address.wrapping_add(offset);
+            let (segment, _) = self
+                .segments
+                .iter()
+                .find(|(s, _)| s.contains(next))
+                .ok_or(Exception::AddressErrorLoad(next))?;
+            offset = offset.saturating_add(segment.end().wrapping_sub(next));
+        }
+
+        let bytes = (0..size)
+            .map(|i| self.byte(address.wrapping_add(i)))
+            .collect();
+        Ok(MemoryRange {
+            address,
+            bytes,
+            endian: self.endian,
+        })
+    }
+}
+
+impl Emulator {
+    pub fn registers(&self) -> RegisterSnapshot {
+        let mut gpr = [0; 32];
+        for (idx, value) in gpr.iter_mut().enumerate() {
+            *value = self.register.get(idx as Binary);
+        }
+        RegisterSnapshot {
+            pc: self.pc,
+            gpr,
+            hi: self.register.get(Register::HI),
+            lo: self.register.get(Register::LO),
+            fpr: self.cp1.fpr,
+            fcsr: self.cp1.fcsr,
+        }
+    }
+}
+
+#[test]
+fn test_register_snapshot() {
+    let mut emu = Emulator::new();
+    emu.register.set(Register::T8, -2);
+    emu.cp1.set_double(2, 1.5);
+    let registers = emu.registers();
+    assert_eq!(registers.get("$t8"), Some(-2));
+    assert_eq!(registers.get("24"), Some(-2));
+    assert_eq!(registers.get("sp"), Some(0x7fffeffc));
+    assert_eq!(registers.get("$f0"), None);
+
+    let text = registers.format(Interpretation::Hex);
+    assert!(text.contains("$t8 ($24)   0xfffffffe           -2\n"));
+    assert!(registers
+        .format(Interpretation::Unsigned)
+        .contains("$t8 ($24)   4294967294\n"));
+    assert!(registers
+        .format_fpu()
+        .contains("$f2   0x00000000  0.0             1.5\n"));
+}
+
+#[test]
+fn test_memory_range() {
+    use crate::EmulatorConfig;
+
+    let mut memory = Memory::new(&EmulatorConfig::default());
+    memory.load(0x10010000, b"Hi\0\0").unwrap();
+    memory.load_word(0x10010004, -5).unwrap();
+    let range = memory.range(0x10010000, 20).unwrap();
+
+    assert_eq!(range.values(Unit::Half)[..2], [0x6948, 0]);
+    assert_eq!(
+        range.format(Unit::Word, Interpretation::Signed),
+        "0x10010000:        26952          -5           0           0\n\
+         0x10010010:            0\n"
+    );
+    assert_eq!(
+        range.format(Unit::Byte, Interpretation::Hex).lines().next(),
+        Some("0x10010000:  48 69 00 00 fb ff ff ff 00 00 00 00 00 00 00 00")
+    );
+    assert_eq!(
+        range.hexdump(),
+        "10010000  48 69 00 00 fb ff ff ff  00 00 00 00 00 00 00 00  |Hi..............|\n\
+         10010010  00 00 00 00                                       |....|\n"
+    );
+    assert_eq!(
+        memory.range(0x0000fffe, 4),
+        Err(Exception::AddressErrorLoad(0x0000fffe))
+    );
+    // Stops at the end of the data segment without reserving the size
+    assert_eq!(
+        memory.range(0x10010000, u32::MAX),
+        Err(Exception::AddressErrorLoad(0x10040000))
+    );
+}
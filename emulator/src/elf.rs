@@ -0,0 +1,197 @@
+use crate::as_i32_be;
+use crate::as_i32_le;
+use crate::Binary;
+use crate::Emulator;
+use crate::Endian;
+use crate::Register;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+const EM_MIPS: u16 = 8;
+const ET_EXEC: u16 = 2;
+const PT_LOAD: Binary = 1;
+const PF_W: Binary = 2;
+const SHT_SYMTAB: Binary = 2;
+
+/// Distance between `$gp` and the start of the data segment
+const GP_OFFSET: Binary = 0x8000;
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+pub fn is_elf(input: &[u8]) -> bool {
+    input.starts_with(b"\x7fELF")
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+    endian: Endian,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, n: usize) -> Result<&'a [u8], Error> {
+        offset
+            .checked_add(n)
+            .and_then(|end| self.input.get(offset..end))
+            .ok_or_else(|| invalid(format!("unexpected end of ELF file at {:#x}", offset)))
+    }
+
+    /// Offset of the entry `i` of `size` bytes in a table at `offset`
+    fn entry(offset: usize, i: usize, size: usize) -> Result<usize, Error> {
+        i.checked_mul(size)
+            .and_then(|n| n.checked_add(offset))
+            .ok_or_else(|| invalid(format!("table at {:#x} is too large", offset)))
+    }
+
+    fn half(&self, offset: usize) -> Result<u16, Error> {
+        let b = self.bytes(offset, 2)?;
+        Ok(match self.endian {
+            Endian::Little => u16::from_le_bytes([b[0], b[1]]),
+            Endian::Big => u16::from_be_bytes([b[0], b[1]]),
+        })
+    }
+
+    fn word(&self, offset: usize) -> Result<Binary, Error> {
+        let b = self.bytes(offset, 4)?;
+        Ok(match self.endian {
+            Endian::Little => as_i32_le(b),
+            Endian::Big => as_i32_be(b),
+        })
+    }
+
+    fn string(&self, offset: usize) -> Result<&'a str, Error> {
+        let rest = self.input.get(offset..).unwrap_or_default();
+        let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+        std::str::from_utf8(&rest[..end]).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Looks up the value of the symbol `name` in `.symtab`.
+    fn symbol(&self, name: &str) -> Result<Option<Binary>, Error> {
+        let shoff = self.word(32)? as usize;
+        let shentsize = self.half(46)? as usize;
+        let shnum = self.half(48)? as usize;
+
+        for i in 0..shnum {
+            let section = Self::entry(shoff, i, shentsize)?;
+            if self.word(section + 4)? != SHT_SYMTAB {
+                continue;
+            }
+
+            let offset = self.word(section + 16)? as usize;
+            let size = self.word(section + 20)? as usize;
+            let entsize = self.word(section + 36)? as usize;
+            let link = self.word(section + 24)? as usize;
+            let strtab = self.word(Self::entry(shoff, link, shentsize)? + 16)? as usize;
+            let end = Self::entry(offset, size, 1)?;
+
+            for entry in (offset..end).step_by(entsize.max(1)) {
+                let name_offset = Self::entry(strtab, self.word(entry)? as usize, 1)?;
+                if self.string(name_offset)? == name {
+                    return Ok(Some(self.word(entry + 4)?));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl Emulator {
+    pub fn load_elf<P: AsRef<Path>>(&mut self, input: P) -> Result<(), Error> {
+        let mut input = File::open(input)?;
+        let mut buffer = vec![];
+
+        input.read_to_end(&mut buffer)?;
+
+        self.load_elf_from_u8(&buffer)?;
+
+        Ok(())
+    }
+
+    /// Loads an ELF32 MIPS executable in either endian.
+    ///
+    /// Every PT_LOAD segment is copied to its virtual address, which must be mapped.
+    /// `$gp` is set to `_gp`, or 0x8000 past the first writable segment, and `$sp` to the
+    /// initial stack pointer.
+    pub fn load_elf_from_u8(&mut self, input: &[u8]) -> Result<(), Error> {
+        if !is_elf(input) {
+            return Err(invalid("not an ELF file".into()));
+        }
+        if input.get(4) != Some(&1) {
+            return Err(invalid("not a 32bit ELF file".into()));
+        }
+        let endian = match input.get(5) {
+            Some(1) => Endian::Little,
+            Some(2) => Endian::Big,
+            _ => return Err(invalid("unknown ELF data encoding".into())),
+        };
+        let reader = Reader { input, endian };
+
+        if reader.half(16)? != ET_EXEC {
+            return Err(invalid("not an executable ELF file".into()));
+        }
+        if reader.half(18)? != EM_MIPS {
+            return Err(invalid("not a MIPS ELF file".into()));
+        }
+
+        let entry = reader.word(24)?;
+        let phoff = reader.word(28)? as usize;
+        let phentsize = reader.half(42)? as usize;
+        let phnum = reader.half(44)? as usize;
+
+        let mut data = None;
+        for i in 0..phnum {
+            let header = Reader::entry(phoff, i, phentsize)?;
+            if reader.word(header)? != PT_LOAD {
+                continue;
+            }
+
+            let offset = reader.word(header + 4)? as usize;
+            let vaddr = reader.word(header + 8)?;
+            let filesz = reader.word(header + 16)? as usize;
+            let memsz = reader.word(header + 20)? as usize;
+            let flags = reader.word(header + 24)?;
+
+            // The segment must fit in the part of the memory map it is loaded to
+            let size = memsz.max(filesz);
+            let fits = self.config.segments().iter().any(|(segment, _)| {
+                let end = segment.base as u64 + segment.size as u64;
+                segment.contains(vaddr as u32) && size as u64 <= end - vaddr as u32 as u64
+            });
+            if !fits {
+                return Err(invalid(format!(
+                    "segment at {:#010x} of {:#x} bytes is outside of the memory map",
+                    vaddr, size
+                )));
+            }
+            let mut contents = reader.bytes(offset, filesz)?.to_vec();
+            contents.resize(size, 0);
+            self.memory.load(vaddr as u32, &contents).map_err(|_| {
+                invalid(format!(
+                    "segment at {:#010x} is outside of the memory map",
+                    vaddr
+                ))
+            })?;
+
+            if flags & PF_W != 0 && data.is_none() {
+                data = Some(vaddr);
+            }
+        }
+
+        let gp = match reader.symbol("_gp")? {
+            Some(gp) => gp,
+            None => data.unwrap_or(0).wrapping_add(GP_OFFSET),
+        };
+
+        self.memory.endian = reader.endian;
+        self.pc = entry;
+        self.register.set(Register::GP, gp);
+        self.register
+            .set(Register::SP, self.config.stack_pointer as Binary);
+
+        Ok(())
+    }
+}
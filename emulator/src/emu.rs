@@ -1,25 +1,63 @@
 use crate::as_i32_be;
 use crate::as_i32_le;
+use crate::cp1::{coprocessor1, BC};
 use crate::decode::JI;
 use crate::decode::{II, RI};
+use crate::dump::Interpretation;
+use crate::hazard::{destination_registers, source_registers};
+use crate::predictor::{is_conditional_branch, Branch};
+use crate::random::Random;
+use crate::Access;
 use crate::Binary;
+use crate::Cp0;
+use crate::Cp1;
 use crate::EBinary;
 use crate::Emulator;
+use crate::EmulatorConfig;
 use crate::Endian;
+use crate::Exception;
+use crate::Hazard;
+use crate::Load;
+use crate::Memory;
 use crate::Register;
-use crate::MEMORY_SIZE;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::{Error, ErrorKind};
 use std::path::Path;
+use std::str::FromStr;
 
 impl Emulator {
     pub fn new() -> Self {
-        Self {
+        Self::with_config(EmulatorConfig::default())
+    }
+
+    pub fn with_config(config: EmulatorConfig) -> Self {
+        let mut emu = Self {
             register: Register::new(),
-            memory: vec![0; MEMORY_SIZE],
+            cp0: Cp0::new(),
+            cp1: Cp1::new(),
+            memory: Memory::new(&config),
             stdout_history: String::new(),
+            input: None,
             pc: 0,
-        }
+            delayed: None,
+            load: None,
+            hazards: Vec::new(),
+            brk: config.heap.base,
+            heap_errors: Vec::new(),
+            caches: None,
+            predictors: Vec::new(),
+            profiler: None,
+            coverage: None,
+            debug_info: None,
+            instructions: 0,
+            executed: None,
+            randoms: HashMap::new(),
+            config,
+        };
+        emu.clear_register();
+        emu
     }
 
     pub fn load_program<P: AsRef<Path>>(
@@ -37,106 +75,385 @@ impl Emulator {
         Ok(())
     }
 
+    /// Loads a program in the file header format.
+    ///
+    /// The text section is placed at the start of the text segment, the data section at the
+    /// start of the data segment and the kernel text section at the exception vector.
     pub fn load_from_u8(&mut self, input: &Vec<u8>, endian: Endian) -> Result<(), std::io::Error> {
-        for (idx, buf) in input.chunks(4).enumerate() {
-            match endian {
-                Endian::Little => self.memory[idx] = as_i32_le(buf),
-                Endian::Big => self.memory[idx] = as_i32_be(buf),
+        let words: Vec<Binary> = input
+            .chunks(4)
+            .map(|buf| match endian {
+                Endian::Little => as_i32_le(buf),
+                Endian::Big => as_i32_be(buf),
+            })
+            .collect();
+
+        let header = |idx: usize| {
+            words
+                .get(idx)
+                .map(|w| *w as usize)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing file header"))
+        };
+        let (entry_point, start_text, start_data) = (header(0)?, header(1)?, header(2)?);
+        // The header ends where the text starts, and one of three words has no kernel text
+        let start_ktext = if start_text <= 3 {
+            words.len()
+        } else {
+            header(3)?
+        };
+        if start_text > start_data || start_data > start_ktext || start_ktext > words.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid file header"));
+        }
+
+        self.memory.endian = endian;
+        let sections = [
+            (&words[start_text..start_data], self.config.text.base),
+            (&words[start_data..start_ktext], self.config.data.base),
+            (&words[start_ktext..], self.config.exception_vector),
+        ];
+        for (section, base) in sections {
+            for (idx, word) in section.iter().enumerate() {
+                self.memory
+                    .load_word(base + 4 * idx as u32, *word)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
             }
         }
 
-        // Entry point
-        self.pc = self.memory[0];
+        // Entry point, which is in the text section unless it is empty
+        if !(start_text..start_data).contains(&entry_point) && entry_point != start_text {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid entry point"));
+        }
+        let offset = ((entry_point - start_text) as u32).wrapping_mul(4);
+        self.pc = self.config.text.base.wrapping_add(offset) as Binary;
 
         Ok(())
     }
 
     pub fn clear_memory(&mut self) {
-        self.memory.iter_mut().for_each(|m| *m = 0);
+        self.memory.clear();
+        self.brk = self.config.heap.base;
+        self.map_heap();
     }
 
     pub fn clear_register(&mut self) {
         self.register.reset();
+        self.register
+            .set(Register::SP, self.config.stack_pointer as Binary);
+        self.register
+            .set(Register::GP, self.config.global_pointer as Binary);
     }
 
-    pub fn syscall(&mut self) -> bool {
+    pub fn syscall(&mut self) -> Result<bool, Exception> {
         let v0 = self.register.get(Register::V0);
-        if v0 == 1 {
-            let a0 = self.register.get(Register::A0);
-
-            print!("{}", a0);
-            std::io::stdout().flush().unwrap();
-            self.stdout_history.push_str(&format!("{}", a0));
-            return true;
+        match v0 {
+            // Print Integer
+            1 => {
+                let a0 = self.register.get(Register::A0);
+                self.print(&format!("{}", a0));
+            }
+            // Print Float
+            2 => self.print(&format!("{:?}", self.cp1.single(12))),
+            // Print Double
+            3 => self.print(&format!("{:?}", self.cp1.double(12))),
+            // Read Float
+            6 => {
+                let value = self.read_number(v0)?;
+                self.cp1.set_single(0, value);
+            }
+            // Read Double
+            7 => {
+                let value = self.read_number(v0)?;
+                self.cp1.set_double(0, value);
+            }
+            // Sbrk, which gives -1 if the heap cannot grow
+            9 => {
+                let size = self.register.get(Register::A0);
+                let address = self.sbrk(size).map_or(-1, |address| address as Binary);
+                self.register.set(Register::V0, address);
+            }
+            // Time
+            30 => {
+                let time = self.time();
+                self.register.set(Register::A0, time as Binary);
+                self.register.set(Register::A1, (time >> 32) as Binary);
+            }
+            // Set Seed
+            40 => {
+                let id = self.register.get(Register::A0);
+                let seed = self.register.get(Register::A1) as u32;
+                self.randoms.insert(id, Random::new(seed as u64));
+            }
+            // Random Int
+            41 => {
+                let value = self.random(self.register.get(Register::A0)).next_u32();
+                self.register.set(Register::A0, value as Binary);
+            }
+            // Random Int Range, which is 0 for an upper bound below 1
+            42 => {
+                let bound = self.register.get(Register::A1);
+                let random = self.random(self.register.get(Register::A0));
+                let value = if bound > 0 {
+                    random.below(bound as u32)
+                } else {
+                    0
+                };
+                self.register.set(Register::A0, value as Binary);
+            }
+            // Random Float
+            43 => {
+                let value = self.random(self.register.get(Register::A0)).float();
+                self.cp1.set_single(0, value);
+            }
+            _ => return Ok(false),
         }
+        Ok(true)
+    }
 
-        false
+    fn print(&mut self, text: &str) {
+        print!("{}", text);
+        std::io::stdout().flush().unwrap();
+        self.stdout_history.push_str(text);
     }
 
-    pub fn step(&mut self) {
-        let code = self.memory[self.pc as usize];
+    /// Line of `input`, or of standard input, which is empty at the end of the input
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        let _ = match self.input.as_mut() {
+            Some(input) => input.read_line(&mut line),
+            None => std::io::stdin().read_line(&mut line),
+        };
+        line
+    }
 
-        println!("[pc] = {}, [code] = {:032b}", self.pc, code);
+    /// Number on a line of the input for the read syscall `syscall`
+    fn read_number<T: FromStr>(&mut self, syscall: Binary) -> Result<T, Exception> {
+        self.read_line()
+            .trim()
+            .parse()
+            .map_err(|_| Exception::InvalidInput(syscall))
+    }
 
-        if let Some(jd) = branch_instruction(&mut self.register, code) {
-            match jd {
-                JumpDest::Spec(pc) => self.pc = pc,
-                JumpDest::Next => self.pc += 1,
+    /// Takes a pending interrupt raised by a device before the next instruction.
+    fn interrupt(&mut self) {
+        self.cp0.set_interrupts(self.memory.interrupts());
+        if self.cp0.interrupt_pending() {
+            self.complete_load();
+            self.cp0.enter(0, self.pc);
+            self.pc = self.config.exception_vector as Binary;
+        }
+    }
+
+    pub fn step(&mut self) -> Result<(), Exception> {
+        // Interrupts wait until the delay slot is executed
+        if self.delayed.is_none() {
+            self.interrupt();
+        }
+
+        let pc = self.pc;
+        let code = self.memory.fetch(pc as u32)?;
+        self.access_cache(Access::Execute, pc as u32);
+
+        if self.config.trace {
+            match self.debug_info.as_ref().and_then(|d| d.describe(pc as u32)) {
+                Some(source) => println!(
+                    "[pc] = {:#010x}, [code] = {:032b}, [source] = {}",
+                    pc, code, source
+                ),
+                None => println!("[pc] = {:#010x}, [code] = {:032b}", pc, code),
             }
-            return;
         }
 
-        if let Some(pc) = jump_instruction(&mut self.register, code) {
+        let delayed = self.delayed.take();
+        self.check_load_hazard(pc, code);
+        let load = self.load.take();
+        // Destination of `lw` and its value before the load
+        let loaded = (opcode(code) == 0x23).then(|| {
+            let rt = II::decode(code).rt;
+            (rt, self.register.get(rt))
+        });
+
+        let data = data_access(&self.register, code);
+        let sp = self.register.get(Register::SP) as u32;
+
+        let taken = self.execute(code)?;
+        self.instructions += 1;
+        self.executed = Some((pc, code));
+        self.check_heap(pc, sp, data);
+
+        if let Some((access, address)) = data {
+            self.access_cache(access, address);
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(pc as u32, code, &self.register);
+        }
+        let branch = taken.map(|taken| Branch {
+            pc: pc as u32,
+            target: pc.wrapping_add(4 + (II::decode(code).im << 2)) as u32,
+            taken,
+        });
+        if let Some(branch) = branch.as_ref() {
+            for predictor in self.predictors.iter_mut() {
+                predictor.branch(branch);
+            }
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(pc as u32, branch.as_ref());
+        }
+
+        let next = loaded.map(|(register, before)| {
+            let value = self.register.get(register);
+            if self.config.load_delay {
+                self.register.set(register, before);
+            }
+            Load {
+                pc,
+                register,
+                value,
+            }
+        });
+        // A write in the delay slot is not overwritten by the load
+        self.load = load.filter(|load| !destination_registers(code).contains(&load.register));
+        self.complete_load();
+        self.load = next;
+
+        if let Some(target) = delayed {
+            self.pc = target;
+        } else if self.config.delay_slot && has_delay_slot(code) {
+            // A branch which is not taken continues after its delay slot
+            self.delayed = Some(match taken {
+                Some(false) => pc + 8,
+                _ => self.pc,
+            });
+            self.pc = pc + 4;
+        }
+        self.memory.tick();
+
+        Ok(())
+    }
+
+    /// Passes an access to the caches, unless it is to a device.
+    fn access_cache(&mut self, access: Access, address: u32) {
+        if let Some(caches) = self.caches.as_mut() {
+            if !self.memory.is_device(address) {
+                caches.access(access, address);
+            }
+        }
+    }
+
+    /// Writes the destination of the previous `lw` in load delay mode.
+    fn complete_load(&mut self) {
+        if let Some(load) = self.load.take() {
+            if self.config.load_delay {
+                self.register.set(load.register, load.value);
+            }
+        }
+    }
+
+    /// Records a `Hazard` if `code` at `pc` reads the destination of the previous `lw`.
+    fn check_load_hazard(&mut self, pc: Binary, code: Binary) {
+        let load = match self.load {
+            Some(load) if self.config.check_load_hazards => load,
+            _ => return,
+        };
+
+        if load.register != 0 && source_registers(code).contains(&load.register) {
+            let hazard = Hazard {
+                pc,
+                load: load.pc,
+                register: load.register,
+            };
+            self.hazards.push(hazard);
+        }
+    }
+
+    /// Executes `code` and moves the pc, giving whether it is a conditional branch which is
+    /// taken or `None` if it is not a conditional branch.
+    fn execute(&mut self, code: Binary) -> Result<Option<bool>, Exception> {
+        if let Some(jd) = branch_instruction(&mut self.register, code) {
+            return Ok(Some(match jd {
+                JumpDest::Spec(offset) => {
+                    self.pc = self.pc.wrapping_add(4 + (offset << 2));
+                    true
+                }
+                JumpDest::Next => {
+                    self.pc += 4;
+                    false
+                }
+            }));
+        }
+
+        if let Some(pc) = jump_instruction(&mut self.register, self.pc, code) {
+            // Jump And Link returns after the delay slot
+            if opcode(code) == 0x3 {
+                let link = if self.config.delay_slot { 8 } else { 4 };
+                self.register.set(Register::RA, self.pc + link);
+            }
             self.pc = pc;
-            return;
+            return Ok(None);
         }
 
-        if funct(code) == 0xc && self.syscall() {
-            self.pc += 1;
-            return;
+        if opcode(code) == 0x0 && funct(code) == 0xc && self.syscall()? {
+            self.pc += 4;
+            return Ok(None);
         }
 
         if arithmetic_with_register(&mut self.register, code) {
-            self.pc += 1;
-            return;
+            self.pc += 4;
+            return Ok(None);
         }
 
         if arithmetic_with_immediate(&mut self.register, code) {
-            self.pc += 1;
-            return;
+            self.pc += 4;
+            return Ok(None);
         }
 
-        if memory_instruction(&mut self.register, &mut self.memory, code) {
-            self.pc += 1;
-            return;
+        if memory_instruction(&mut self.register, &mut self.memory, code)? {
+            self.pc += 4;
+            return Ok(None);
         }
 
         if shirt_instruction(&mut self.register, code) {
-            self.pc += 1;
-            return;
+            self.pc += 4;
+            return Ok(None);
         }
 
         if move_from(&mut self.register, code) {
-            self.pc += 1;
-            return;
+            self.pc += 4;
+            return Ok(None);
         }
 
-        panic!("failed to decode a instruction [PC = {}]", self.pc);
+        if let Some(pc) = coprocessor0(&mut self.register, &mut self.cp0, self.pc, code) {
+            self.pc = pc;
+            return Ok(None);
+        }
+
+        let taken = is_conditional_branch(code).then(|| self.cp1.branch_taken(II::decode(code).rt));
+        if let Some(pc) = coprocessor1(
+            &mut self.register,
+            &mut self.cp1,
+            &mut self.memory,
+            self.pc,
+            code,
+        )? {
+            self.pc = pc;
+            return Ok(taken);
+        }
+
+        panic!("failed to decode a instruction [PC = {:#010x}]", self.pc);
     }
 
-    pub fn run(&mut self) {
+    /// Runs until the program returns to address 0.
+    pub fn run(&mut self) -> Result<(), Exception> {
         loop {
-            self.step();
+            self.step()?;
             if self.pc == 0 {
-                return;
+                return Ok(());
             }
         }
     }
 
     pub fn info_register(&self) {
-        for (i, r) in self.register.storage.iter().enumerate() {
-            println!("${}: {:#x} | {}", i, r, r);
-        }
+        print!("{}", self.registers().format(Interpretation::Hex));
     }
 }
 
@@ -148,12 +465,33 @@ pub fn funct(code: Binary) -> Binary {
     code & 0b000000_00000_00000_00000_00000_111111
 }
 
+/// Branches and jumps, which are followed by a delay slot
+pub fn has_delay_slot(code: Binary) -> bool {
+    match opcode(code) {
+        0x0 => matches!(funct(code), 0x8 | 0x9),
+        0x2..=0x5 => true,
+        0x11 => RI::decode(code).rs == BC,
+        _ => false,
+    }
+}
+
+/// Address loaded or stored by `code`
+fn data_access(register: &Register, code: Binary) -> Option<(Access, u32)> {
+    let access = match opcode(code) {
+        0x23 | 0x31 => Access::Read,
+        0x2b | 0x39 => Access::Write,
+        _ => return None,
+    };
+    let ii = II::decode(code);
+    Some((access, register.get(ii.rs).wrapping_add(ii.im) as u32))
+}
+
 pub enum JumpDest {
     Next,
     Spec(Binary),
 }
 
-pub fn jump_instruction(register: &mut Register, code: Binary) -> Option<Binary> {
+pub fn jump_instruction(register: &mut Register, pc: Binary, code: Binary) -> Option<Binary> {
     let opcode = opcode(code);
     // Jump Register
     if opcode == 0x0 {
@@ -163,10 +501,10 @@ pub fn jump_instruction(register: &mut Register, code: Binary) -> Option<Binary>
             let pc = register.get(ri.rs);
             return Some(pc);
         }
-    // Jump
-    } else if opcode == 0x2 {
+    // Jump, Jump And Link
+    } else if opcode == 0x2 || opcode == 0x3 {
         let ji = JI::decode(code);
-        return Some(ji.ad);
+        return Some((pc.wrapping_add(4) & !0x0fff_ffff) | (ji.ad << 2));
     }
     None
 }
@@ -179,13 +517,13 @@ fn shirt_instruction(register: &mut Register, code: Binary) -> bool {
     let i = RI::decode(code);
     match i.fc {
         0x0 => {
-            let rs = register.get(i.rs);
-            register.set(i.rt, rs << i.sh);
+            let rt = register.get(i.rt);
+            register.set(i.rd, rt << i.sh);
             true
         }
         0x2 => {
-            let rs = register.get(i.rs);
-            register.set(i.rt, rs >> i.sh);
+            let rt = register.get(i.rt);
+            register.set(i.rd, rt >> i.sh);
             true
         }
         _ => false,
@@ -213,6 +551,30 @@ fn move_from(register: &mut Register, code: Binary) -> bool {
     }
 }
 
+/// `mfc0`, `mtc0` and `eret`, returning the next pc
+fn coprocessor0(
+    register: &mut Register,
+    cp0: &mut Cp0,
+    pc: Binary,
+    code: Binary,
+) -> Option<Binary> {
+    if opcode(code) != 0x10 {
+        return None;
+    }
+
+    let i = RI::decode(code);
+    match i.rs {
+        // Move From Coprocessor 0
+        0x0 => register.set(i.rt, cp0.get(i.rd)),
+        // Move To Coprocessor 0
+        0x4 => cp0.set(i.rd, register.get(i.rt)),
+        // Exception Return
+        0x10 if i.fc == 0x18 => return Some(cp0.eret()),
+        _ => return None,
+    }
+    Some(pc + 4)
+}
+
 fn branch_instruction(register: &mut Register, code: Binary) -> Option<JumpDest> {
     match opcode(code) {
         // Branch On Equal
@@ -397,27 +759,29 @@ pub fn arithmetic_with_immediate(register: &mut Register, code: Binary) -> bool
     }
 }
 
-pub fn memory_instruction(register: &mut Register, memory: &mut [Binary], code: Binary) -> bool {
+pub fn memory_instruction(
+    register: &mut Register,
+    memory: &mut Memory,
+    code: Binary,
+) -> Result<bool, Exception> {
     match opcode(code) {
         // Load Word
         0x23 => {
             let ii = II::decode(code);
-            let rs = register.get(ii.rs);
-            let im = ii.im / 4;
-            let s = memory[(rs + im) as usize];
+            let address = register.get(ii.rs).wrapping_add(ii.im);
+            let s = memory.read_word(address as u32)?;
 
             register.set(ii.rt, s);
-            true
+            Ok(true)
         }
         // Store Word
         0x2b => {
             let ii = II::decode(code);
-            let rs = register.get(ii.rs) / 4;
-            let im = ii.im / 4;
-            memory[(rs + im) as usize] = register.get(ii.rt);
+            let address = register.get(ii.rs).wrapping_add(ii.im);
+            memory.write_word(address as u32, register.get(ii.rt))?;
 
-            true
+            Ok(true)
         }
-        _ => false,
+        _ => Ok(false),
     }
 }
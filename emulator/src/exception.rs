@@ -0,0 +1,32 @@
+use crate::Access;
+use crate::Exception;
+use std::fmt;
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Exception::AddressErrorLoad(address) => {
+                write!(f, "address error on load at {:#010x}", address)
+            }
+            Exception::AddressErrorStore(address) => {
+                write!(f, "address error on store at {:#010x}", address)
+            }
+            Exception::ProtectionFault(access, address) => {
+                write!(f, "protection fault on {} at {:#010x}", access, address)
+            }
+            Exception::InvalidInput(syscall) => write!(f, "invalid input to syscall {}", syscall),
+        }
+    }
+}
+
+impl std::error::Error for Exception {}
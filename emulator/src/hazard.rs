@@ -0,0 +1,107 @@
+use crate::decode::RI;
+use crate::emu::{funct, opcode};
+use crate::Binary;
+use crate::Hazard;
+use crate::Register;
+use std::fmt;
+
+/// Registers an instruction reads
+pub fn source_registers(code: Binary) -> Vec<Binary> {
+    let i = RI::decode(code);
+    let registers = match opcode(code) {
+        0x0 => match funct(code) {
+            // Shifts
+            0x0 | 0x2 => vec![i.rt],
+            // Jump Register
+            0x8 | 0x9 => vec![i.rs],
+            // Move from Hi and Lo
+            0x10 => vec![Register::HI],
+            0x12 => vec![Register::LO],
+            // System Call
+            0xc => vec![Register::V0, Register::A0],
+            _ => vec![i.rs, i.rt],
+        },
+        // Jump and Load Upper Immediate
+        0x2 | 0x3 | 0xf => vec![],
+        // Branches and Store Word
+        0x4 | 0x5 | 0x2b => vec![i.rs, i.rt],
+        // Move to Coprocessor 0
+        0x10 if i.rs == 0x4 => vec![i.rt],
+        0x10 => vec![],
+        // Move and Move Control to Coprocessor 1
+        0x11 if i.rs == 0x4 || i.rs == 0x6 => vec![i.rt],
+        0x11 => vec![],
+        _ => vec![i.rs],
+    };
+
+    registers.into_iter().filter(|r| *r != 0).collect()
+}
+
+/// Registers an instruction writes
+pub fn destination_registers(code: Binary) -> Vec<Binary> {
+    let i = RI::decode(code);
+    let registers = match opcode(code) {
+        0x0 => match funct(code) {
+            // Jump Register and System Call
+            0x8 | 0xc => vec![],
+            // Multiply and Divide
+            0x18..=0x1b => vec![Register::HI, Register::LO],
+            _ => vec![i.rd],
+        },
+        // Jump and Link
+        0x3 => vec![Register::RA],
+        // Jump, Branches and Store Word
+        0x2 | 0x4 | 0x5 | 0x2b => vec![],
+        // Move from Coprocessor 0
+        0x10 if i.rs == 0x0 => vec![i.rt],
+        0x10 => vec![],
+        // Move and Move Control from Coprocessor 1
+        0x11 if i.rs == 0x0 || i.rs == 0x2 => vec![i.rt],
+        // Coprocessor 1, whose registers are not tracked, and Load and Store Word to it
+        0x11 | 0x31 | 0x39 => vec![],
+        _ => vec![i.rt],
+    };
+
+    registers.into_iter().filter(|r| *r != 0).collect()
+}
+
+impl fmt::Display for Hazard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "${} is read at {:#010x} in the load delay slot of lw at {:#010x}",
+            self.register, self.pc, self.load
+        )
+    }
+}
+
+#[test]
+fn test_source_registers() {
+    // add $t0, $t1, $t2
+    assert_eq!(source_registers(0x012a4020), vec![9, 10]);
+    // sll $t0, $t1, 2
+    assert_eq!(source_registers(0x00094080), vec![9]);
+    // lw $t0, 4($sp)
+    assert_eq!(source_registers(0x8fa80004u32 as Binary), vec![29]);
+    // sw $t0, 4($sp)
+    assert_eq!(source_registers(0xafa80004u32 as Binary), vec![29, 8]);
+    // lui $t0, 1
+    assert_eq!(source_registers(0x3c080001), vec![]);
+    // addi $t0, $zero, 1
+    assert_eq!(source_registers(0x20080001), vec![]);
+}
+
+#[test]
+fn test_destination_registers() {
+    // add $t0, $t1, $t2
+    assert_eq!(destination_registers(0x012a4020), vec![8]);
+    // lw $t0, 4($sp)
+    assert_eq!(destination_registers(0x8fa80004u32 as Binary), vec![8]);
+    // sw $t0, 4($sp)
+    assert_eq!(destination_registers(0xafa80004u32 as Binary), vec![]);
+    // mult $t1, $t2
+    assert_eq!(
+        destination_registers(0x012a0018),
+        vec![Register::HI, Register::LO]
+    );
+}
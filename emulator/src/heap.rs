@@ -0,0 +1,178 @@
+use crate::memory::PAGE_SIZE;
+use crate::Access;
+use crate::Binary;
+use crate::Emulator;
+use crate::HeapError;
+use crate::Register;
+use std::fmt;
+
+impl Emulator {
+    /// Moves the break by `size` bytes, rounded up to a word, and returns the old break, or
+    /// `None` if the break would leave the heap or pass `$sp`.
+    pub fn sbrk(&mut self, size: Binary) -> Option<u32> {
+        let heap = self.config.heap;
+        let sp = self.register.get(Register::SP) as u32 as i64;
+        let brk = self.brk as i64 + ((size as i64 + 3) & !3);
+        if brk < heap.base as i64 || brk > heap.base as i64 + heap.size as i64 || brk > sp {
+            self.flag(HeapError::Exhausted {
+                pc: self.pc,
+                brk: brk as u32,
+            });
+            return None;
+        }
+        let old = self.brk;
+        self.brk = brk as u32;
+        self.map_heap();
+        Some(old)
+    }
+
+    /// Maps the pages of the heap up to the break.
+    pub(crate) fn map_heap(&mut self) {
+        let heap = self.config.heap;
+        let size = (self.brk - heap.base).div_ceil(PAGE_SIZE) as u64 * PAGE_SIZE as u64;
+        self.memory
+            .resize(heap.base, size.min(heap.size as u64) as u32);
+    }
+
+    /// Records the `HeapError`s of the instruction at `pc`, before which `$sp` was `sp`, and
+    /// which made the load or store `data`.
+    pub(crate) fn check_heap(&mut self, pc: Binary, sp: u32, data: Option<(Access, u32)>) {
+        if !self.config.check_heap {
+            return;
+        }
+
+        let brk = self.brk;
+        if let Some((access, address)) = data {
+            if self.config.heap.contains(address) && address >= brk {
+                self.flag(HeapError::BeyondBreak {
+                    pc,
+                    access,
+                    address,
+                    brk,
+                });
+            }
+        }
+        // The stack is reported when it first grows into the heap
+        let new_sp = self.register.get(Register::SP) as u32;
+        if new_sp < brk && sp >= brk {
+            self.flag(HeapError::Collision {
+                pc,
+                sp: new_sp,
+                brk,
+            });
+        }
+    }
+
+    fn flag(&mut self, error: HeapError) {
+        if self.config.check_heap {
+            self.heap_errors.push(error);
+        }
+    }
+}
+
+impl fmt::Display for HeapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeapError::Exhausted { pc, brk } => write!(
+                f,
+                "sbrk at {:#010x} cannot move the break to {:#010x}",
+                pc, brk
+            ),
+            HeapError::Collision { pc, sp, brk } => write!(
+                f,
+                "$sp is moved to {:#010x} at {:#010x}, below the break {:#010x}",
+                sp, pc, brk
+            ),
+            HeapError::BeyondBreak {
+                pc,
+                access,
+                address,
+                brk,
+            } => write!(
+                f,
+                "{} of {:#010x} at {:#010x} is beyond the break {:#010x}",
+                access, address, pc, brk
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_sbrk() {
+    use crate::EmulatorConfig;
+    use crate::Segment;
+
+    let mut emu = Emulator::with_config(EmulatorConfig {
+        heap: Segment {
+            base: 0x10040000,
+            size: 0x100,
+        },
+        check_heap: true,
+        ..Default::default()
+    });
+    emu.pc = 0x00400000;
+    assert!(!emu.memory.is_mapped(0x10040000));
+    assert_eq!(emu.sbrk(5), Some(0x10040000));
+    assert!(emu.memory.is_mapped(0x100400fc));
+    assert_eq!(emu.sbrk(0), Some(0x10040008));
+    assert_eq!(emu.sbrk(-8), Some(0x10040008));
+    assert_eq!(emu.brk, 0x10040000);
+    assert!(!emu.memory.is_mapped(0x10040000));
+    assert_eq!(emu.sbrk(-4), None);
+    assert_eq!(emu.sbrk(0x104), None);
+    assert_eq!(
+        emu.heap_errors,
+        [
+            HeapError::Exhausted {
+                pc: 0x00400000,
+                brk: 0x1003fffc
+            },
+            HeapError::Exhausted {
+                pc: 0x00400000,
+                brk: 0x10040104
+            }
+        ]
+    );
+
+    // The break cannot pass the stack
+    emu.register.set(Register::SP, 0x10040080);
+    assert_eq!(emu.sbrk(0x84), None);
+    assert_eq!(emu.sbrk(0x80), Some(0x10040000));
+}
+
+#[test]
+fn test_check_heap() {
+    use crate::EmulatorConfig;
+
+    let mut emu = Emulator::with_config(EmulatorConfig {
+        check_heap: true,
+        ..Default::default()
+    });
+    emu.sbrk(8);
+    emu.check_heap(0x00400000, 0x7fffeffc, Some((Access::Write, 0x10040004)));
+    emu.check_heap(0x00400004, 0x7fffeffc, Some((Access::Read, 0x10040008)));
+    emu.check_heap(0x00400008, 0x7fffeffc, Some((Access::Read, 0x10010000)));
+    emu.register.set(Register::SP, 0x10040004);
+    emu.check_heap(0x0040000c, 0x7fffeffc, None);
+    emu.check_heap(0x00400010, 0x10040004, None);
+    assert_eq!(
+        emu.heap_errors,
+        [
+            HeapError::BeyondBreak {
+                pc: 0x00400004,
+                access: Access::Read,
+                address: 0x10040008,
+                brk: 0x10040008
+            },
+            HeapError::Collision {
+                pc: 0x0040000c,
+                sp: 0x10040004,
+                brk: 0x10040008
+            }
+        ]
+    );
+    assert_eq!(
+        emu.heap_errors[0].to_string(),
+        "read of 0x10040008 at 0x00400004 is beyond the break 0x10040008"
+    );
+}
@@ -0,0 +1,229 @@
+use crate::device::Device;
+use crate::timer::Timer;
+use crate::Binary;
+use crate::Emulator;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::io::IsTerminal;
+use std::rc::Rc;
+
+const READY: Binary = 0x1;
+const INTERRUPT_ENABLE: Binary = 0x2;
+
+/// Input shared by the keyboard and the read syscalls, so that each byte is read by only one of
+/// them
+#[derive(Clone)]
+pub struct SharedInput(Rc<RefCell<Box<dyn BufRead>>>);
+
+impl SharedInput {
+    pub fn new<R: BufRead + 'static>(input: R) -> Self {
+        Self(Rc::new(RefCell::new(Box::new(input))))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.0.borrow_mut().fill_buf().ok()?.first().copied()
+    }
+
+    fn next(&self) -> Option<u8> {
+        let c = self.peek()?;
+        self.0.borrow_mut().consume(1);
+        Some(c)
+    }
+
+    /// Reader for `Emulator::input`, which reads a line without taking the bytes after it
+    pub fn reader(&self) -> Box<dyn BufRead> {
+        Box::new(BufReader::with_capacity(1, self.clone()))
+    }
+}
+
+impl Read for SharedInput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+impl fmt::Debug for SharedInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedInput")
+    }
+}
+
+/// Keyboard and display of MARS
+///
+/// | Offset | Register             |
+/// |--------|----------------------|
+/// | 0x0    | Receiver control     |
+/// | 0x4    | Receiver data        |
+/// | 0x8    | Transmitter control  |
+/// | 0xc    | Transmitter data     |
+///
+/// Keys are scripted with `push_input`, or taken from `source` after the scripted ones, and each
+/// one is ready once the previous one is read.
+/// A ready receiver raises the interrupt line IP2 and a ready transmitter IP3 when bit 1 of
+/// their control register is set.
+#[derive(Debug, Default)]
+pub struct KeyboardDisplay {
+    input: VecDeque<u8>,
+    /// Keys after the scripted ones
+    pub source: Option<SharedInput>,
+    received: u8,
+    receiver_control: Binary,
+    transmitter_control: Binary,
+    /// Characters written to the display
+    pub output: String,
+    /// Also print the characters written to the display
+    pub echo: bool,
+    /// Instructions the display takes to show a character
+    pub delay: u32,
+    busy: u32,
+}
+
+impl KeyboardDisplay {
+    pub const BASE: u32 = 0xffff_0000;
+    pub const SIZE: u32 = 0x10;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_input(&mut self, input: &str) {
+        self.input.extend(input.bytes());
+    }
+
+    pub fn receiver_ready(&self) -> bool {
+        !self.input.is_empty() || self.source.as_ref().is_some_and(|s| s.peek().is_some())
+    }
+
+    pub fn transmitter_ready(&self) -> bool {
+        self.busy == 0
+    }
+}
+
+impl Device for KeyboardDisplay {
+    fn read(&mut self, offset: u32) -> Binary {
+        match offset {
+            0x0 if self.receiver_ready() => self.receiver_control | READY,
+            0x0 => self.receiver_control,
+            0x4 => {
+                let source = self.source.as_ref();
+                if let Some(c) = self.input.pop_front().or_else(|| source?.next()) {
+                    self.received = c;
+                }
+                self.received as Binary
+            }
+            0x8 if self.transmitter_ready() => self.transmitter_control | READY,
+            0x8 => self.transmitter_control,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, value: Binary) {
+        match offset {
+            0x0 => self.receiver_control = value & INTERRUPT_ENABLE,
+            0x8 => self.transmitter_control = value & INTERRUPT_ENABLE,
+            0xc if self.transmitter_ready() => {
+                let c = value as u8 as char;
+                self.output.push(c);
+                if self.echo {
+                    print!("{}", c);
+                    std::io::stdout().flush().unwrap();
+                }
+                self.busy = self.delay;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        self.busy = self.busy.saturating_sub(1);
+    }
+
+    fn interrupts(&self) -> u32 {
+        let receiver = self.receiver_control & INTERRUPT_ENABLE != 0 && self.receiver_ready();
+        let transmitter =
+            self.transmitter_control & INTERRUPT_ENABLE != 0 && self.transmitter_ready();
+        receiver as u32 | (transmitter as u32) << 1
+    }
+}
+
+impl Emulator {
+    /// Maps the keyboard and display and the timer, as `mme` and `mips` do. Keys and the read
+    /// syscalls share standard input, which gives keys only when it is a pipe since polling a
+    /// terminal would wait for a line.
+    pub fn map_devices(&mut self) {
+        let input = SharedInput::new(std::io::stdin().lock());
+        self.input = Some(input.reader());
+        let keyboard = KeyboardDisplay {
+            source: (!std::io::stdin().is_terminal()).then_some(input),
+            echo: true,
+            ..KeyboardDisplay::new()
+        };
+        self.memory
+            .map_device(KeyboardDisplay::BASE, KeyboardDisplay::SIZE, keyboard);
+        self.memory
+            .map_device(Timer::BASE, Timer::SIZE, Timer::new());
+    }
+}
+
+#[test]
+fn test_keyboard_display() {
+    let mut device = KeyboardDisplay {
+        delay: 2,
+        ..KeyboardDisplay::new()
+    };
+
+    assert_eq!(device.read(0x0) & READY, 0);
+    device.push_input("ab");
+    assert_eq!(device.read(0x0) & READY, READY);
+    assert_eq!(device.read(0x4), 'a' as Binary);
+    assert_eq!(device.read(0x4), 'b' as Binary);
+    assert_eq!(device.read(0x0) & READY, 0);
+    // The last key is kept
+    assert_eq!(device.read(0x4), 'b' as Binary);
+
+    device.write(0x0, INTERRUPT_ENABLE);
+    assert_eq!(device.read(0x0), INTERRUPT_ENABLE);
+    assert_eq!(device.interrupts(), 0);
+    device.push_input("c");
+    assert_eq!(device.interrupts(), 0x1);
+    device.read(0x4);
+
+    assert_eq!(device.read(0x8), READY);
+    device.write(0xc, 'x' as Binary);
+    assert_eq!(device.read(0x8), 0);
+    // Ignored while the display is busy
+    device.write(0xc, 'y' as Binary);
+    device.tick();
+    device.tick();
+    assert_eq!(device.read(0x8), READY);
+    device.write(0xc, 'z' as Binary);
+    assert_eq!(device.output, "xz");
+}
+
+#[test]
+fn test_shared_input() {
+    let input = SharedInput::new("ab\n12\nc".as_bytes());
+    let mut reader = input.reader();
+    let mut device = KeyboardDisplay {
+        source: Some(input),
+        ..KeyboardDisplay::new()
+    };
+    device.push_input("x");
+
+    // The keyboard and the reader take turns
+    assert_eq!(device.read(0x4), 'x' as Binary);
+    assert_eq!(device.read(0x4), 'a' as Binary);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "b\n");
+    assert_eq!(device.read(0x4), '1' as Binary);
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "2\n");
+    assert_eq!(device.read(0x0) & READY, READY);
+    assert_eq!(device.read(0x4), 'c' as Binary);
+    assert_eq!(device.read(0x0) & READY, 0);
+}
@@ -1,15 +1,45 @@
+pub mod arguments;
+pub mod cache;
+pub mod config;
+pub mod coverage;
+pub mod cp0;
+pub mod cp1;
+pub mod debug;
 pub mod decode;
+pub mod device;
+pub mod dump;
+pub mod elf;
 pub mod emu;
+pub mod exception;
+pub mod hazard;
+pub mod heap;
+pub mod keyboard;
+pub mod memory;
+pub mod pipeline;
+pub mod predictor;
+pub mod profile;
+pub mod random;
 pub mod register;
+pub mod timer;
+
+use cache::Caches;
+use coverage::Coverage;
+use debug::DebugInfo;
+use device::Device;
+use predictor::BranchPredictor;
+use profile::Profiler;
+use random::Random;
+use std::collections::HashMap;
+use std::io::BufRead;
 
 pub type Binary = i32;
 pub type EBinary = i64;
-const MEMORY_SIZE: usize = 8192;
 
 pub struct Register {
     storage: [Binary; 34],
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Endian {
     Little,
     Big,
@@ -29,9 +59,175 @@ pub fn as_i32_le(array: &[u8]) -> i32 {
         + ((array[3] as i32) << 24)
 }
 
+/// Range of addresses `base..base + size`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Segment {
+    pub base: u32,
+    pub size: u32,
+}
+
+/// Memory map of the emulator, by default the one of SPIM and MARS
+#[derive(Debug, PartialEq, Clone)]
+pub struct EmulatorConfig {
+    pub text: Segment,
+    /// Area below `data` around `$gp`, which SPIM keeps for small globals declared by `.extern`
+    pub small_data: Segment,
+    pub data: Segment,
+    /// Grows up from the end of the data segment
+    pub heap: Segment,
+    /// Grows down from `stack_pointer`
+    pub stack: Segment,
+    pub ktext: Segment,
+    pub kdata: Segment,
+    /// Initial `$sp`
+    pub stack_pointer: u32,
+    /// Initial `$gp`
+    pub global_pointer: u32,
+    /// Address jumped to on an interrupt, where the kernel text section is loaded
+    pub exception_vector: u32,
+    /// Execute the instruction after a branch or jump before it is taken, as MIPS does
+    pub delay_slot: bool,
+    /// Write the destination of `lw` after the next instruction, as MIPS I does
+    pub load_delay: bool,
+    /// Report registers read in the load delay slot as `Hazard`s
+    pub check_load_hazards: bool,
+    /// Report collisions of the heap and the stack and accesses beyond the break as `HeapError`s
+    pub check_heap: bool,
+    /// Seed of the generators of the random syscalls until the program sets one
+    pub random_seed: u64,
+    /// Milliseconds since the Unix epoch at which the clock of the time syscall starts
+    pub clock_start: u64,
+    /// Instructions executed in a millisecond of the clock of the time syscall
+    pub instructions_per_ms: u64,
+    /// Print the address and code of every instruction executed
+    pub trace: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Permission {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Byte addressed memory made of the segments of an `EmulatorConfig`
+///
+/// Pages are allocated when they are first written, so untouched memory costs nothing.
+pub struct Memory {
+    pub endian: Endian,
+    segments: Vec<(Segment, Permission)>,
+    pages: HashMap<u32, Box<[u8]>>,
+    /// Permissions of pages changed by `Memory::protect`
+    permissions: HashMap<u32, Permission>,
+    devices: Vec<(Segment, Box<dyn Device>)>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Exception {
+    /// Instruction fetch or load from an unmapped or unaligned address
+    AddressErrorLoad(u32),
+    /// Store to an unmapped or unaligned address
+    AddressErrorStore(u32),
+    /// Access to a mapped page which does not permit it
+    ProtectionFault(Access, u32),
+    /// Line read by the syscall in `$v0` which is not a number, or the end of the input
+    InvalidInput(Binary),
+}
+
+/// Registers of coprocessor 0 which handle interrupts
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Cp0 {
+    /// `$12`: interrupt mask in bits 8-15, EXL in bit 1 and IE in bit 0
+    pub status: Binary,
+    /// `$13`: pending interrupts in bits 8-15 and the exception code in bits 2-6
+    pub cause: Binary,
+    /// `$14`: address `eret` returns to
+    pub epc: Binary,
+}
+
+/// Registers of coprocessor 1, the floating-point unit
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Cp1 {
+    /// `$f0`-`$f31`, a double being held by an even register (low word) and the next one
+    pub fpr: [u32; 32],
+    /// Rounding mode in bits 0-1, condition code 0 in bit 23 and condition codes 1-7 in bits
+    /// 25-31
+    pub fcsr: u32,
+}
+
+/// `lw` whose destination is written after the next instruction in load delay mode
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Load {
+    pub pc: Binary,
+    pub register: Binary,
+    pub value: Binary,
+}
+
+/// Register read in the load delay slot of the `lw` at `load`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Hazard {
+    pub pc: Binary,
+    pub load: Binary,
+    pub register: Binary,
+}
+
+/// Misuse of the heap by the instruction at `pc`, found with `EmulatorConfig::check_heap`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HeapError {
+    /// `sbrk` cannot move the break to `brk`, which is outside the heap or above `$sp`
+    Exhausted { pc: Binary, brk: u32 },
+    /// `$sp` is moved to `sp`, below the break `brk`
+    Collision { pc: Binary, sp: u32, brk: u32 },
+    /// Load or store of `address` in the heap at or above the break `brk`
+    BeyondBreak {
+        pc: Binary,
+        access: Access,
+        address: u32,
+        brk: u32,
+    },
+}
+
 pub struct Emulator {
+    pub config: EmulatorConfig,
     pub register: Register,
-    pub memory: Vec<Binary>,
+    pub cp0: Cp0,
+    pub cp1: Cp1,
+    pub memory: Memory,
     pub pc: Binary,
     pub stdout_history: String,
+    /// Read by the read syscalls, or standard input if `None`
+    pub input: Option<Box<dyn BufRead>>,
+    /// Target of the branch or jump whose delay slot executes next
+    delayed: Option<Binary>,
+    /// `lw` executed by the previous instruction
+    load: Option<Load>,
+    /// Load delay hazards found with `EmulatorConfig::check_load_hazards`
+    pub hazards: Vec<Hazard>,
+    /// End of the heap allocated by `sbrk`
+    pub brk: u32,
+    /// Misuses of the heap found with `EmulatorConfig::check_heap`
+    pub heap_errors: Vec<HeapError>,
+    /// Caches which instruction fetches, loads and stores go through
+    pub caches: Option<Caches>,
+    /// Predictors which every conditional branch is run through
+    pub predictors: Vec<BranchPredictor>,
+    /// Counts the instructions executed
+    pub profiler: Option<Profiler>,
+    /// Records the instructions executed and the directions of branches
+    pub coverage: Option<Coverage>,
+    /// Source positions and labels shown in the trace
+    pub debug_info: Option<DebugInfo>,
+    /// Instructions executed, which drive the clock of the time syscall
+    pub instructions: u64,
+    /// Address and code of the instruction executed by the last `step`
+    pub executed: Option<(Binary, Binary)>,
+    /// Generators of the random syscalls by id
+    pub randoms: HashMap<Binary, Random>,
 }
@@ -0,0 +1,350 @@
+use crate::as_i32_be;
+use crate::as_i32_le;
+use crate::device::Device;
+use crate::Access;
+use crate::Binary;
+use crate::EmulatorConfig;
+use crate::Endian;
+use crate::Exception;
+use crate::Memory;
+use crate::Permission;
+use crate::Segment;
+use std::collections::HashMap;
+
+pub const PAGE_SIZE: u32 = 4096;
+
+impl Permission {
+    pub const NONE: Permission = Permission {
+        read: false,
+        write: false,
+        execute: false,
+    };
+    pub const RW: Permission = Permission {
+        read: true,
+        write: true,
+        execute: false,
+    };
+    pub const RX: Permission = Permission {
+        read: true,
+        write: false,
+        execute: true,
+    };
+
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+}
+
+impl Access {
+    fn address_error(self, address: u32) -> Exception {
+        match self {
+            Access::Read | Access::Execute => Exception::AddressErrorLoad(address),
+            Access::Write => Exception::AddressErrorStore(address),
+        }
+    }
+}
+
+impl Memory {
+    pub fn new(config: &EmulatorConfig) -> Self {
+        // The heap is mapped up to the break as `sbrk` moves it
+        let segments = config
+            .segments()
+            .map(|(segment, permission)| match segment {
+                s if s == config.heap => (Segment { size: 0, ..s }, permission),
+                s => (s, permission),
+            });
+        Self {
+            endian: Endian::Little,
+            segments: segments.to_vec(),
+            pages: HashMap::new(),
+            permissions: HashMap::new(),
+            devices: Vec::new(),
+        }
+    }
+
+    /// Maps `device` to `base..base + size`, in front of the memory there.
+    pub fn map_device<D: Device + 'static>(&mut self, base: u32, size: u32, device: D) {
+        self.devices
+            .push((Segment { base, size }, Box::new(device)));
+    }
+
+    pub fn is_device(&self, address: u32) -> bool {
+        self.devices.iter().any(|(s, _)| s.contains(address))
+    }
+
+    fn device(&mut self, address: u32) -> Option<(&mut Box<dyn Device>, u32)> {
+        self.devices
+            .iter_mut()
+            .find(|(s, _)| s.contains(address))
+            .map(|(s, d)| (d, address - s.base))
+    }
+
+    /// Changes the size of the segment starting at `base`.
+    pub fn resize(&mut self, base: u32, size: u32) {
+        if let Some((segment, _)) = self.segments.iter_mut().find(|(s, _)| s.base == base) {
+            segment.size = size;
+        }
+    }
+
+    /// Advances every device by one instruction.
+    pub fn tick(&mut self) {
+        for (_, device) in self.devices.iter_mut() {
+            device.tick();
+        }
+    }
+
+    /// Hardware interrupt lines raised by any device
+    pub fn interrupts(&self) -> u32 {
+        self.devices
+            .iter()
+            .fold(0, |lines, (_, device)| lines | device.interrupts())
+    }
+
+    /// Permission of the page containing `address`, or `None` if it is not mapped
+    pub fn permission(&self, address: u32) -> Option<Permission> {
+        let (_, permission) = self.segments.iter().find(|(s, _)| s.contains(address))?;
+        Some(
+            self.permissions
+                .get(&(address / PAGE_SIZE))
+                .copied()
+                .unwrap_or(*permission),
+        )
+    }
+
+    pub fn is_mapped(&self, address: u32) -> bool {
+        self.permission(address).is_some()
+    }
+
+    /// Changes the permission of every mapped page overlapping `address..address + size`.
+    pub fn protect(&mut self, address: u32, size: u32, permission: Permission) {
+        if size == 0 {
+            return;
+        }
+        let last = address.saturating_add(size - 1) / PAGE_SIZE;
+        for page in address / PAGE_SIZE..=last {
+            if self.is_mapped(page * PAGE_SIZE) {
+                self.permissions.insert(page, permission);
+            }
+        }
+    }
+
+    /// Number of pages which have been allocated
+    pub fn resident_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    fn check(&self, address: u32, access: Access) -> Result<(), Exception> {
+        match self.permission(address) {
+            None => Err(access.address_error(address)),
+            Some(p) if !p.allows(access) => Err(Exception::ProtectionFault(access, address)),
+            Some(_) => Ok(()),
+        }
+    }
+
+    pub(crate) fn byte(&self, address: u32) -> u8 {
+        self.pages
+            .get(&(address / PAGE_SIZE))
+            .map(|page| page[(address % PAGE_SIZE) as usize])
+            .unwrap_or(0)
+    }
+
+    fn byte_mut(&mut self, address: u32) -> &mut u8 {
+        let page = self
+            .pages
+            .entry(address / PAGE_SIZE)
+            .or_insert_with(|| vec![0; PAGE_SIZE as usize].into_boxed_slice());
+        &mut page[(address % PAGE_SIZE) as usize]
+    }
+
+    fn word(&self, address: u32) -> Binary {
+        let bytes = [0, 1, 2, 3].map(|i| self.byte(address + i));
+        match self.endian {
+            Endian::Little => as_i32_le(&bytes),
+            Endian::Big => as_i32_be(&bytes),
+        }
+    }
+
+    /// Copies `bytes` to `address` regardless of the permissions, as a loader does.
+    pub fn load(&mut self, address: u32, bytes: &[u8]) -> Result<(), Exception> {
+        for (i, byte) in bytes.iter().enumerate() {
+            let address = address.wrapping_add(i as u32);
+            if !self.is_mapped(address) {
+                return Err(Exception::AddressErrorStore(address));
+            }
+            *self.byte_mut(address) = *byte;
+        }
+        Ok(())
+    }
+
+    /// Loads the word at `address` regardless of the permissions.
+    pub fn load_word(&mut self, address: u32, value: Binary) -> Result<(), Exception> {
+        let bytes = match self.endian {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        };
+        self.load(address, &bytes)
+    }
+
+    pub fn read_byte(&self, address: u32) -> Result<u8, Exception> {
+        self.check(address, Access::Read)?;
+        Ok(self.byte(address))
+    }
+
+    pub fn write_byte(&mut self, address: u32, value: u8) -> Result<(), Exception> {
+        self.check(address, Access::Write)?;
+        *self.byte_mut(address) = value;
+        Ok(())
+    }
+
+    /// Reads the instruction at `address`.
+    pub fn fetch(&self, address: u32) -> Result<Binary, Exception> {
+        if !address.is_multiple_of(4) {
+            return Err(Exception::AddressErrorLoad(address));
+        }
+        self.check(address, Access::Execute)?;
+        Ok(self.word(address))
+    }
+
+    pub fn read_word(&mut self, address: u32) -> Result<Binary, Exception> {
+        if !address.is_multiple_of(4) {
+            return Err(Exception::AddressErrorLoad(address));
+        }
+        if let Some((device, offset)) = self.device(address) {
+            return Ok(device.read(offset));
+        }
+        self.check(address, Access::Read)?;
+        Ok(self.word(address))
+    }
+
+    pub fn write_word(&mut self, address: u32, value: Binary) -> Result<(), Exception> {
+        if !address.is_multiple_of(4) {
+            return Err(Exception::AddressErrorStore(address));
+        }
+        if let Some((device, offset)) = self.device(address) {
+            device.write(offset, value);
+            return Ok(());
+        }
+        self.check(address, Access::Write)?;
+        let bytes = match self.endian {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        };
+        for (i, byte) in bytes.iter().enumerate() {
+            *self.byte_mut(address + i as u32) = *byte;
+        }
+
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.pages.clear();
+        self.permissions.clear();
+    }
+}
+
+#[test]
+fn test_memory() {
+    let config = EmulatorConfig::default();
+    let mut memory = Memory::new(&config);
+
+    memory.write_word(0x10010000, 0x12345678).unwrap();
+    assert_eq!(memory.read_word(0x10010000), Ok(0x12345678));
+    assert_eq!(memory.read_byte(0x10010000), Ok(0x78));
+
+    memory.endian = Endian::Big;
+    assert_eq!(memory.read_word(0x10010000), Ok(0x78563412));
+
+    // Top of the stack
+    memory.write_word(0x7fffeffc, 1).unwrap();
+    assert_eq!(memory.read_word(0x7fffeffc), Ok(1));
+
+    assert_eq!(memory.read_word(0), Err(Exception::AddressErrorLoad(0)));
+    assert_eq!(
+        memory.read_word(0x10010002),
+        Err(Exception::AddressErrorLoad(0x10010002))
+    );
+    assert_eq!(
+        memory.write_word(0x0000_1000, 1),
+        Err(Exception::AddressErrorStore(0x0000_1000))
+    );
+
+    // The heap is not mapped until it is resized
+    assert!(!memory.is_mapped(0x10040000));
+    memory.resize(0x10040000, PAGE_SIZE);
+    assert!(memory.is_mapped(0x10040ffc));
+    assert!(!memory.is_mapped(0x10041000));
+
+    memory.protect(0x10010000, 4, Permission::NONE);
+    memory.clear();
+    assert_eq!(memory.permission(0x10010000), Some(Permission::RW));
+    assert_eq!(memory.read_word(0x10010000), Ok(0));
+}
+
+#[test]
+fn test_sparse_pages() {
+    let mut memory = Memory::new(&EmulatorConfig::default());
+
+    // Reading untouched memory allocates nothing
+    assert_eq!(memory.read_word(0x7fff0000), Ok(0));
+    assert_eq!(memory.resident_pages(), 0);
+
+    memory.write_word(0x7fffeffc, 1).unwrap();
+    memory.write_word(0x7fffeff8, 2).unwrap();
+    memory.write_word(0x9000_0000, 3).unwrap();
+    assert_eq!(memory.resident_pages(), 2);
+
+    memory.clear();
+    assert_eq!(memory.read_word(0x7fffeffc), Ok(0));
+    assert_eq!(memory.resident_pages(), 0);
+}
+
+#[test]
+fn test_permissions() {
+    let mut memory = Memory::new(&EmulatorConfig::default());
+
+    // Text is not writable and data is not executable
+    memory.load_word(0x00400000, 0xc).unwrap();
+    assert_eq!(memory.fetch(0x00400000), Ok(0xc));
+    assert_eq!(
+        memory.write_word(0x00400000, 1),
+        Err(Exception::ProtectionFault(Access::Write, 0x00400000))
+    );
+    assert_eq!(
+        memory.fetch(0x10010000),
+        Err(Exception::ProtectionFault(Access::Execute, 0x10010000))
+    );
+
+    // Guard page below the stack
+    memory.protect(0x7ffee000, PAGE_SIZE, Permission::NONE);
+    assert_eq!(
+        memory.read_word(0x7ffeeffc),
+        Err(Exception::ProtectionFault(Access::Read, 0x7ffeeffc))
+    );
+    assert_eq!(memory.read_word(0x7ffef000), Ok(0));
+}
+
+#[test]
+fn test_map_device() {
+    use crate::keyboard::KeyboardDisplay;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut memory = Memory::new(&EmulatorConfig::default());
+    let device = Rc::new(RefCell::new(KeyboardDisplay::new()));
+    memory.map_device(KeyboardDisplay::BASE, KeyboardDisplay::SIZE, device.clone());
+
+    device.borrow_mut().push_input("k");
+    assert_eq!(memory.read_word(0xffff0000), Ok(1));
+    assert_eq!(memory.read_word(0xffff0004), Ok('k' as Binary));
+    memory.write_word(0xffff000c, 'd' as Binary).unwrap();
+    assert_eq!(device.borrow().output, "d");
+
+    assert_eq!(
+        memory.read_word(0xffff0010),
+        Err(Exception::AddressErrorLoad(0xffff0010))
+    );
+}
@@ -0,0 +1,494 @@
+use crate::emu::{has_delay_slot, opcode};
+use crate::hazard::{destination_registers, source_registers};
+use crate::Binary;
+use crate::Emulator;
+use crate::Exception;
+use crate::Memory;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Stage {
+    Fetch,
+    Decode,
+    Execute,
+    Memory,
+    WriteBack,
+}
+
+pub const STAGES: [Stage; 5] = [
+    Stage::Fetch,
+    Stage::Decode,
+    Stage::Execute,
+    Stage::Memory,
+    Stage::WriteBack,
+];
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Fetch => "IF",
+            Stage::Decode => "ID",
+            Stage::Execute => "EX",
+            Stage::Memory => "MEM",
+            Stage::WriteBack => "WB",
+        }
+    }
+}
+
+/// How the pipeline handles branches and jumps until they are resolved
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BranchPolicy {
+    /// Stop fetching until the target is known
+    Stall,
+    /// Keep fetching the next instructions and flush them if the branch is taken
+    PredictNotTaken,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PipelineConfig {
+    /// Forward results from EX/MEM and MEM/WB to EX instead of waiting for write back
+    pub forwarding: bool,
+    pub branches: BranchPolicy,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            forwarding: true,
+            branches: BranchPolicy::PredictNotTaken,
+        }
+    }
+}
+
+/// Instruction as it goes through the pipeline
+#[derive(Debug, PartialEq, Clone)]
+pub struct PipelineEntry {
+    pub pc: Binary,
+    pub code: Binary,
+    /// Cycle each stage is entered, counted from 1, up to the stage it is flushed in
+    pub cycles: Vec<u64>,
+    /// Cycle at the end of which the instruction is flushed
+    pub flushed: Option<u64>,
+}
+
+/// Five-stage pipeline model of a program run by the emulator
+///
+/// Branches and `jr` are resolved in EX and `j` in ID. Registers are written in the first half
+/// of WB and read in the second half of ID.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Pipeline {
+    pub config: PipelineConfig,
+    /// Instructions in the order they are fetched, including the flushed ones
+    pub entries: Vec<PipelineEntry>,
+    /// Cycles spent waiting in ID for an operand
+    pub data_stalls: u64,
+    /// Cycles fetch waited for a branch with `BranchPolicy::Stall`
+    pub control_stalls: u64,
+    /// Instructions flushed after a taken branch
+    pub flushes: u64,
+}
+
+/// Stage cycles of the last instruction which was not flushed
+#[derive(Clone, Copy)]
+struct Previous {
+    fetch: u64,
+    decode: u64,
+    execute: u64,
+}
+
+/// Fetch which has to wait for the branch resolved at `resolve`
+struct Redirect {
+    index: usize,
+    resolve: u64,
+    /// First instruction on the wrong path, if it is fetched
+    wrong_path: Option<Binary>,
+}
+
+impl PipelineEntry {
+    /// Cycle and stage of every cycle the instruction is in the pipeline
+    pub fn stages(&self) -> impl Iterator<Item = (u64, Stage)> + '_ {
+        let end = self.flushed.or(self.cycles.last().copied()).unwrap_or(0);
+        self.cycles.iter().enumerate().flat_map(move |(s, start)| {
+            let next = self.cycles.get(s + 1).copied().unwrap_or(end + 1);
+            (*start..next).map(move |cycle| (cycle, STAGES[s]))
+        })
+    }
+
+    /// Stage the instruction is in at `cycle`
+    pub fn stage_at(&self, cycle: u64) -> Option<Stage> {
+        let last = *self.cycles.last()?;
+        let end = self.flushed.unwrap_or(last);
+        if cycle > end {
+            return None;
+        }
+
+        self.cycles
+            .iter()
+            .rposition(|c| *c <= cycle)
+            .map(|s| STAGES[s])
+    }
+}
+
+impl Pipeline {
+    pub fn new(config: PipelineConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Runs `emu` until the program ends and schedules the executed instructions.
+    pub fn run(&mut self, emu: &mut Emulator) -> Result<(), Exception> {
+        let mut trace = Vec::new();
+        loop {
+            // The instruction executed, which is not the one at the pc after an interrupt
+            emu.step()?;
+            trace.extend(emu.executed);
+            if emu.pc == 0 {
+                break;
+            }
+        }
+
+        self.schedule(&trace, &emu.memory, emu.config.delay_slot);
+        Ok(())
+    }
+
+    fn schedule(&mut self, trace: &[(Binary, Binary)], memory: &Memory, delay_slot: bool) {
+        let slot = delay_slot as usize;
+        let mut previous: Option<Previous> = None;
+        let mut ready: HashMap<Binary, u64> = HashMap::new();
+        let mut redirect: Option<Redirect> = None;
+
+        for (index, (pc, code)) in trace.iter().copied().enumerate() {
+            let mut fetch = previous.map_or(1, |p| (p.fetch + 1).max(p.decode));
+
+            if let Some(r) = redirect.take_if(|r| r.index == index) {
+                if let (Some(wrong_path), Some(p)) = (r.wrong_path, previous) {
+                    self.flush(wrong_path, p, r.resolve, memory);
+                } else {
+                    self.control_stalls += (r.resolve + 1).saturating_sub(fetch);
+                }
+                fetch = fetch.max(r.resolve + 1);
+            }
+
+            let decode = previous.map_or(fetch + 1, |p| (fetch + 1).max(p.execute));
+            let issue = previous.map_or(decode + 1, |p| (decode + 1).max(p.execute + 1));
+            let execute = source_registers(code)
+                .iter()
+                .filter_map(|r| ready.get(r))
+                .fold(issue, |e, r| e.max(*r));
+            self.data_stalls += execute - issue;
+            let memory_stage = execute + 1;
+            let write_back = memory_stage + 1;
+
+            for r in destination_registers(code) {
+                let available = match (self.config.forwarding, opcode(code)) {
+                    // Load Word
+                    (true, 0x23) => memory_stage + 1,
+                    (true, _) => execute + 1,
+                    (false, _) => write_back + 1,
+                };
+                ready.insert(r, available);
+            }
+
+            if has_delay_slot(code) {
+                let next = index + 1 + slot;
+                let fall_through = pc + 4 * (1 + slot as Binary);
+                let taken = trace.get(next).is_some_and(|(pc, _)| *pc != fall_through);
+                let resolve = match opcode(code) {
+                    // Jump and Jump and Link
+                    0x2 | 0x3 => decode,
+                    _ => execute,
+                };
+
+                redirect = match self.config.branches {
+                    BranchPolicy::Stall => Some(Redirect {
+                        index: next,
+                        resolve,
+                        wrong_path: None,
+                    }),
+                    BranchPolicy::PredictNotTaken if taken => Some(Redirect {
+                        index: next,
+                        resolve,
+                        wrong_path: Some(fall_through),
+                    }),
+                    BranchPolicy::PredictNotTaken => None,
+                };
+            }
+
+            self.entries.push(PipelineEntry {
+                pc,
+                code,
+                cycles: vec![fetch, decode, execute, memory_stage, write_back],
+                flushed: None,
+            });
+            previous = Some(Previous {
+                fetch,
+                decode,
+                execute,
+            });
+        }
+    }
+
+    /// Adds the instructions fetched from `pc` after `previous` until the branch resolves.
+    fn flush(&mut self, mut pc: Binary, mut previous: Previous, resolve: u64, memory: &Memory) {
+        loop {
+            let fetch = (previous.fetch + 1).max(previous.decode);
+            if fetch > resolve {
+                return;
+            }
+            let decode = (fetch + 1).max(previous.execute);
+
+            let mut cycles = vec![fetch];
+            if decode <= resolve {
+                cycles.push(decode);
+            }
+            self.entries.push(PipelineEntry {
+                pc,
+                code: memory.fetch(pc as u32).unwrap_or(0),
+                cycles,
+                flushed: Some(resolve),
+            });
+            self.flushes += 1;
+
+            previous = Previous {
+                fetch,
+                decode,
+                execute: decode + 1,
+            };
+            pc += 4;
+        }
+    }
+
+    /// Instructions which completed
+    pub fn retired(&self) -> usize {
+        self.entries.iter().filter(|e| e.flushed.is_none()).count()
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.entries
+            .iter()
+            .filter_map(|e| e.cycles.last())
+            .max()
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Cycles per instruction
+    pub fn cpi(&self) -> f64 {
+        self.cycles() as f64 / self.retired().max(1) as f64
+    }
+
+    /// Instruction in each stage at every cycle from 1, in pipeline order
+    pub fn timeline(&self) -> Vec<[Option<&PipelineEntry>; 5]> {
+        let mut timeline = vec![[None; 5]; self.cycles() as usize];
+        for entry in self.entries.iter() {
+            for (cycle, stage) in entry.stages() {
+                timeline[cycle as usize - 1][stage as usize].get_or_insert(entry);
+            }
+        }
+        timeline
+    }
+
+    /// Instruction in each stage at `cycle`, in pipeline order
+    ///
+    /// Only the instructions fetched by `cycle` are looked at, from the last one back to the
+    /// first one which has retired before `cycle`. Instructions retire in order, and a flushed
+    /// instruction leaves before the next one which is not flushed.
+    pub fn occupants(&self, cycle: u64) -> [Option<&PipelineEntry>; 5] {
+        let mut occupants = [None; 5];
+        let fetched = self
+            .entries
+            .partition_point(|e| e.cycles.first().is_some_and(|fetch| *fetch <= cycle));
+        for entry in self.entries[..fetched].iter().rev() {
+            if let Some(stage) = entry.stage_at(cycle) {
+                occupants[stage as usize] = Some(entry);
+            }
+            if entry.flushed.is_none() && entry.cycles.last().is_some_and(|wb| *wb < cycle) {
+                break;
+            }
+        }
+        occupants
+    }
+
+    /// Pipeline diagram with a row per instruction and a column per cycle
+    ///
+    /// A stage is named in the cycle it is entered and `--` marks a stall.
+    pub fn diagram(&self) -> String {
+        let cycles = self.cycles();
+        let mut output = String::new();
+
+        write!(output, "{:<20}", "address    code").unwrap();
+        for cycle in 1..=cycles {
+            write!(output, "{:<4}", cycle).unwrap();
+        }
+        output = output.trim_end().to_string();
+        output.push('\n');
+
+        for entry in self.entries.iter() {
+            let mut row = format!("{:#010x} {:08x} ", entry.pc, entry.code);
+            let mut column = 1;
+            for (cycle, stage) in entry.stages() {
+                row.push_str(&" ".repeat(4 * (cycle - column) as usize));
+                let cell = if entry.cycles.contains(&cycle) {
+                    stage.name()
+                } else {
+                    "--"
+                };
+                write!(row, "{:<4}", cell).unwrap();
+                column = cycle + 1;
+            }
+            if entry.flushed.is_some() {
+                row.push_str(&" ".repeat(4 * (cycles + 1).saturating_sub(column) as usize));
+                row.push_str("flushed");
+            }
+            output.push_str(row.trim_end());
+            output.push('\n');
+        }
+
+        writeln!(
+            output,
+            "{} instructions, {} cycles, CPI {:.2}, {} data stalls, {} control stalls, {} flushed",
+            self.retired(),
+            cycles,
+            self.cpi(),
+            self.data_stalls,
+            self.control_stalls,
+            self.flushes
+        )
+        .unwrap();
+
+        output
+    }
+
+    /// Stage cycles of every instruction and the occupants of every stage per cycle as JSON
+    pub fn to_json(&self) -> String {
+        let instructions: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                let stages: Vec<String> = e
+                    .cycles
+                    .iter()
+                    .zip(STAGES)
+                    .map(|(c, s)| format!("\"{}\": {}", s.name(), c))
+                    .collect();
+                let flushed = match e.flushed {
+                    Some(c) => c.to_string(),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "{{\"pc\": \"{:#010x}\", \"code\": \"{:#010x}\", \"flushed\": {}, {}}}",
+                    e.pc,
+                    e.code,
+                    flushed,
+                    stages.join(", ")
+                )
+            })
+            .collect();
+
+        let cycles: Vec<String> = (1..)
+            .zip(self.timeline())
+            .map(|(cycle, occupants)| {
+                let stages: Vec<String> = occupants
+                    .iter()
+                    .zip(STAGES)
+                    .map(|(e, s)| match e {
+                        Some(e) => format!("\"{}\": \"{:#010x}\"", s.name(), e.pc),
+                        None => format!("\"{}\": null", s.name()),
+                    })
+                    .collect();
+                format!("{{\"cycle\": {}, {}}}", cycle, stages.join(", "))
+            })
+            .collect();
+
+        format!(
+            "{{\n  \"cycles\": {},\n  \"instructions\": {},\n  \"data_stalls\": {},\n  \"control_stalls\": {},\n  \"flushes\": {},\n  \"pipeline\": [\n    {}\n  ],\n  \"diagram\": [\n    {}\n  ]\n}}\n",
+            self.cycles(),
+            self.retired(),
+            self.data_stalls,
+            self.control_stalls,
+            self.flushes,
+            instructions.join(",\n    "),
+            cycles.join(",\n    ")
+        )
+    }
+}
+
+#[cfg(test)]
+fn run_program(codes: &[Binary], config: PipelineConfig) -> Pipeline {
+    let mut emu = Emulator::new();
+    for (i, code) in codes.iter().enumerate() {
+        emu.memory
+            .load_word(emu.config.text.base + 4 * i as u32, *code)
+            .unwrap();
+    }
+    emu.pc = emu.config.text.base as Binary;
+
+    let mut pipeline = Pipeline::new(config);
+    pipeline.run(&mut emu).unwrap();
+    pipeline
+}
+
+#[test]
+fn test_load_use() {
+    // lw $t1, 0($sp); add $t2, $t1, $t1; jr $ra
+    let codes = [0x8fa90000u32 as Binary, 0x01295020, 0x03e00008];
+
+    let pipeline = run_program(&codes, PipelineConfig::default());
+    assert_eq!(pipeline.entries[0].cycles, vec![1, 2, 3, 4, 5]);
+    // One stall with forwarding from MEM/WB
+    assert_eq!(pipeline.entries[1].cycles, vec![2, 3, 5, 6, 7]);
+    assert_eq!(pipeline.entries[2].cycles, vec![3, 5, 6, 7, 8]);
+    assert_eq!(pipeline.data_stalls, 1);
+    assert_eq!(pipeline.cycles(), 8);
+
+    let pipeline = run_program(
+        &codes,
+        PipelineConfig {
+            forwarding: false,
+            ..Default::default()
+        },
+    );
+    // Waits until the register file is written in WB
+    assert_eq!(pipeline.entries[1].cycles, vec![2, 3, 6, 7, 8]);
+    assert_eq!(pipeline.data_stalls, 2);
+}
+
+#[test]
+fn test_taken_branch() {
+    // beq $zero, $zero, 8; addi $t0, $zero, 1; addi $t0, $zero, 2; jr $ra
+    let codes = [0x10000002, 0x20080001, 0x20080002, 0x03e00008];
+
+    let pipeline = run_program(&codes, PipelineConfig::default());
+    let flushed: Vec<(Binary, &[u64])> = pipeline
+        .entries
+        .iter()
+        .filter(|e| e.flushed.is_some())
+        .map(|e| (e.pc, e.cycles.as_slice()))
+        .collect();
+    assert_eq!(
+        flushed,
+        vec![(0x00400004, &[2, 3][..]), (0x00400008, &[3][..])]
+    );
+    assert_eq!(pipeline.entries[3].cycles, vec![4, 5, 6, 7, 8]);
+    assert_eq!(pipeline.flushes, 2);
+    assert_eq!(pipeline.occupants(3)[0].map(|e| e.pc), Some(0x00400008));
+    for (cycle, occupants) in (1..).zip(pipeline.timeline()) {
+        assert_eq!(pipeline.occupants(cycle), occupants);
+    }
+    assert_eq!(pipeline.occupants(0), [None; 5]);
+    assert_eq!(pipeline.occupants(pipeline.cycles() + 1), [None; 5]);
+
+    let pipeline = run_program(
+        &codes,
+        PipelineConfig {
+            branches: BranchPolicy::Stall,
+            ..Default::default()
+        },
+    );
+    assert_eq!(pipeline.entries.len(), 2);
+    assert_eq!(pipeline.entries[1].cycles, vec![4, 5, 6, 7, 8]);
+    assert_eq!(pipeline.control_stalls, 2);
+}
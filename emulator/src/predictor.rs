@@ -0,0 +1,380 @@
+use crate::cp1::BC;
+use crate::debug::symbolize;
+use crate::decode::RI;
+use crate::emu::opcode;
+use std::collections::BTreeMap;
+
+/// Outcome of a conditional branch executed by the emulator
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Branch {
+    pub pc: u32,
+    /// Address the branch goes to when it is taken
+    pub target: u32,
+    pub taken: bool,
+}
+
+/// `beq`, `bne`, `blez`, `bgtz`, the REGIMM branches, `bc1f` and `bc1t`
+pub fn is_conditional_branch(code: i32) -> bool {
+    match opcode(code) {
+        0x1 | 0x4..=0x7 => true,
+        0x11 => RI::decode(code).rs == BC,
+        _ => false,
+    }
+}
+
+/// Branch predictor which is told the outcome of every branch after predicting it
+pub trait Predictor {
+    fn name(&self) -> String;
+
+    /// Predicted target of the branch at `pc`, or `None` if it is predicted not taken.
+    ///
+    /// `target` is the target encoded in the branch, which is known once it is decoded.
+    fn predict(&mut self, pc: u32, target: u32) -> Option<u32>;
+
+    fn update(&mut self, branch: &Branch);
+}
+
+/// Index of `pc` in a table of `size` entries
+fn index(pc: u32, size: usize) -> usize {
+    (pc >> 2) as usize % size
+}
+
+/// Table of `size` entries, which must not be empty
+fn table<T: Clone>(size: usize, value: T) -> Result<Vec<T>, String> {
+    if size == 0 {
+        return Err("predictor table of 0 entries".to_string());
+    }
+    Ok(vec![value; size])
+}
+
+/// Predicts every branch taken or every branch not taken.
+pub struct Static {
+    pub taken: bool,
+}
+
+impl Predictor for Static {
+    fn name(&self) -> String {
+        if self.taken {
+            "static taken".to_string()
+        } else {
+            "static not taken".to_string()
+        }
+    }
+
+    fn predict(&mut self, _pc: u32, target: u32) -> Option<u32> {
+        self.taken.then_some(target)
+    }
+
+    fn update(&mut self, _branch: &Branch) {}
+}
+
+/// Predicts the last outcome of each branch.
+pub struct OneBit {
+    table: Vec<bool>,
+}
+
+impl OneBit {
+    pub fn new(size: usize) -> Result<Self, String> {
+        Ok(Self {
+            table: table(size, false)?,
+        })
+    }
+}
+
+impl Predictor for OneBit {
+    fn name(&self) -> String {
+        format!("1-bit ({} entries)", self.table.len())
+    }
+
+    fn predict(&mut self, pc: u32, target: u32) -> Option<u32> {
+        self.table[index(pc, self.table.len())].then_some(target)
+    }
+
+    fn update(&mut self, branch: &Branch) {
+        let idx = index(branch.pc, self.table.len());
+        self.table[idx] = branch.taken;
+    }
+}
+
+/// 2-bit saturating counter, predicting taken from 2 upwards
+fn count(counter: &mut u8, taken: bool) {
+    *counter = if taken {
+        (*counter + 1).min(3)
+    } else {
+        counter.saturating_sub(1)
+    };
+}
+
+/// Predicts with a 2-bit saturating counter for each branch, starting weakly not taken.
+pub struct TwoBit {
+    counters: Vec<u8>,
+}
+
+impl TwoBit {
+    pub fn new(size: usize) -> Result<Self, String> {
+        Ok(Self {
+            counters: table(size, 1)?,
+        })
+    }
+}
+
+impl Predictor for TwoBit {
+    fn name(&self) -> String {
+        format!("2-bit ({} entries)", self.counters.len())
+    }
+
+    fn predict(&mut self, pc: u32, target: u32) -> Option<u32> {
+        (self.counters[index(pc, self.counters.len())] >= 2).then_some(target)
+    }
+
+    fn update(&mut self, branch: &Branch) {
+        let idx = index(branch.pc, self.counters.len());
+        count(&mut self.counters[idx], branch.taken);
+    }
+}
+
+/// Predicts with 2-bit counters indexed by the pc xor the outcomes of the last branches.
+pub struct Gshare {
+    /// Number of outcomes kept in `history`
+    pub history_bits: u32,
+    history: u32,
+    counters: Vec<u8>,
+}
+
+impl Gshare {
+    /// Most outcomes kept in the history, giving a table of a million counters
+    pub const MAX_HISTORY_BITS: u32 = 20;
+
+    pub fn new(history_bits: u32) -> Result<Self, String> {
+        if !(1..=Self::MAX_HISTORY_BITS).contains(&history_bits) {
+            return Err(format!(
+                "gshare history of {} bits is not in 1..={}",
+                history_bits,
+                Self::MAX_HISTORY_BITS
+            ));
+        }
+        Ok(Self {
+            history_bits,
+            history: 0,
+            counters: table(1 << history_bits, 1)?,
+        })
+    }
+
+    fn index(&self, pc: u32) -> usize {
+        (((pc >> 2) ^ self.history) & ((1 << self.history_bits) - 1)) as usize
+    }
+}
+
+impl Predictor for Gshare {
+    fn name(&self) -> String {
+        format!("gshare ({} bits of history)", self.history_bits)
+    }
+
+    fn predict(&mut self, pc: u32, target: u32) -> Option<u32> {
+        (self.counters[self.index(pc)] >= 2).then_some(target)
+    }
+
+    fn update(&mut self, branch: &Branch) {
+        let idx = self.index(branch.pc);
+        count(&mut self.counters[idx], branch.taken);
+        self.history = ((self.history << 1) | branch.taken as u32) & ((1 << self.history_bits) - 1);
+    }
+}
+
+/// Branch target buffer which predicts the stored target for branches taken the last time and
+/// not taken for the others.
+///
+/// Unlike the other predictors it is used before the branch is decoded, so a prediction is only
+/// correct if the target is as well.
+pub struct Btb {
+    /// Tag and target of each entry
+    entries: Vec<Option<(u32, u32)>>,
+}
+
+impl Btb {
+    pub fn new(size: usize) -> Result<Self, String> {
+        Ok(Self {
+            entries: table(size, None)?,
+        })
+    }
+}
+
+impl Predictor for Btb {
+    fn name(&self) -> String {
+        format!("BTB ({} entries)", self.entries.len())
+    }
+
+    fn predict(&mut self, pc: u32, _target: u32) -> Option<u32> {
+        match self.entries[index(pc, self.entries.len())] {
+            Some((tag, target)) if tag == pc => Some(target),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, branch: &Branch) {
+        let idx = index(branch.pc, self.entries.len());
+        if branch.taken {
+            self.entries[idx] = Some((branch.pc, branch.target));
+        } else if matches!(self.entries[idx], Some((tag, _)) if tag == branch.pc) {
+            self.entries[idx] = None;
+        }
+    }
+}
+
+/// Statistics of a branch site
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct SiteStats {
+    pub executed: u64,
+    pub taken: u64,
+    pub correct: u64,
+}
+
+impl SiteStats {
+    pub fn accuracy(&self) -> f64 {
+        match self.executed {
+            0 => 0.0,
+            n => self.correct as f64 / n as f64,
+        }
+    }
+}
+
+/// Predictor run on the branches of the emulator, with statistics per branch site
+pub struct BranchPredictor {
+    pub predictor: Box<dyn Predictor>,
+    pub sites: BTreeMap<u32, SiteStats>,
+}
+
+impl BranchPredictor {
+    pub fn new<P: Predictor + 'static>(predictor: P) -> Self {
+        Self {
+            predictor: Box::new(predictor),
+            sites: BTreeMap::new(),
+        }
+    }
+
+    /// Predicts `branch` and tells the predictor its outcome, returning whether the prediction
+    /// was correct.
+    pub fn branch(&mut self, branch: &Branch) -> bool {
+        let prediction = self.predictor.predict(branch.pc, branch.target);
+        let correct = prediction == branch.taken.then_some(branch.target);
+        self.predictor.update(branch);
+
+        let site = self.sites.entry(branch.pc).or_default();
+        site.executed += 1;
+        site.taken += branch.taken as u64;
+        site.correct += correct as u64;
+        correct
+    }
+
+    /// Statistics of every site together
+    pub fn total(&self) -> SiteStats {
+        self.sites
+            .values()
+            .fold(SiteStats::default(), |total, site| SiteStats {
+                executed: total.executed + site.executed,
+                taken: total.taken + site.taken,
+                correct: total.correct + site.correct,
+            })
+    }
+
+    /// Accuracy of the predictor and of each site, which is named after the nearest label in
+    /// `labels` at or before it.
+    pub fn report(&self, labels: &BTreeMap<u32, String>) -> String {
+        let total = self.total();
+        let mut report = format!(
+            "{}: {}/{} correct ({:.2}%)\n",
+            self.predictor.name(),
+            total.correct,
+            total.executed,
+            100.0 * total.accuracy()
+        );
+        for (pc, site) in self.sites.iter() {
+            let label = symbolize(labels, *pc)
+                .map(|l| format!(" <{}>", l))
+                .unwrap_or_default();
+            report.push_str(&format!(
+                "  {:#010x}{}: {}/{} taken, {}/{} correct ({:.2}%)\n",
+                pc,
+                label,
+                site.taken,
+                site.executed,
+                site.correct,
+                site.executed,
+                100.0 * site.accuracy()
+            ));
+        }
+        report
+    }
+}
+
+#[test]
+fn test_predictors() {
+    // A loop branch taken three times and then not taken, run twice
+    let outcomes = [true, true, true, false, true, true, true, false];
+    let run = |predictor: &mut BranchPredictor| {
+        for taken in outcomes {
+            predictor.branch(&Branch {
+                pc: 0x00400010,
+                target: 0x00400000,
+                taken,
+            });
+        }
+        predictor.total().correct
+    };
+
+    assert_eq!(run(&mut BranchPredictor::new(Static { taken: true })), 6);
+    assert_eq!(run(&mut BranchPredictor::new(Static { taken: false })), 2);
+    // Mispredicts the first iteration and the exit of each loop
+    assert_eq!(run(&mut BranchPredictor::new(OneBit::new(16).unwrap())), 4);
+    // Stays taken after the exit of the first loop
+    assert_eq!(run(&mut BranchPredictor::new(TwoBit::new(16).unwrap())), 5);
+    assert_eq!(run(&mut BranchPredictor::new(Btb::new(16).unwrap())), 4);
+}
+
+#[test]
+fn test_gshare() {
+    // Alternating outcomes are learned from the history
+    let mut gshare = BranchPredictor::new(Gshare::new(4).unwrap());
+    let mut two_bit = BranchPredictor::new(TwoBit::new(16).unwrap());
+    for i in 0..64 {
+        let branch = Branch {
+            pc: 0x00400010,
+            target: 0x00400000,
+            taken: i % 2 == 0,
+        };
+        gshare.branch(&branch);
+        two_bit.branch(&branch);
+    }
+    assert!(gshare.total().correct > 56);
+    assert!(two_bit.total().correct <= 32);
+}
+
+#[test]
+fn test_report() {
+    let mut predictor = BranchPredictor::new(Static { taken: true });
+    predictor.branch(&Branch {
+        pc: 0x00400008,
+        target: 0x00400000,
+        taken: true,
+    });
+
+    let labels = BTreeMap::from([(0x00400000, "loop".to_string())]);
+    assert_eq!(
+        predictor.report(&labels),
+        "static taken: 1/1 correct (100.00%)\n  0x00400008 <loop+8>: 1/1 taken, 1/1 correct (100.00%)\n"
+    );
+    assert_eq!(symbolize(&labels, 0x003ffffc), None);
+}
+
+#[test]
+fn test_predictor_sizes() {
+    assert!(OneBit::new(0).is_err());
+    assert!(TwoBit::new(0).is_err());
+    assert!(Btb::new(0).is_err());
+    assert!(Gshare::new(0).is_err());
+    assert_eq!(
+        Gshare::new(40).err(),
+        Some("gshare history of 40 bits is not in 1..=20".to_string())
+    );
+    assert!(Gshare::new(20).is_ok());
+}
@@ -0,0 +1,277 @@
+use crate::debug::symbolize;
+use crate::decode::{mnemonic, Class, JI, RI};
+use crate::Binary;
+use crate::Register;
+use std::collections::BTreeMap;
+
+/// Function on the call stack of the profiler
+struct Frame {
+    function: u32,
+    return_address: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct FunctionStats {
+    /// Instructions executed in the function itself
+    pub self_count: u64,
+    /// Instructions executed in the function and the functions it calls
+    pub total: u64,
+    /// Times the function was called by `jal`
+    pub calls: u64,
+}
+
+/// Counts executed instructions, and the instructions of each function by following `jal` and
+/// `jr $ra`
+///
+/// The function the program starts in is named after its first instruction, and every other
+/// function after the target of the `jal` calling it.
+pub struct Profiler {
+    /// Executions of each instruction by address
+    pub counts: BTreeMap<u32, u64>,
+    pub mnemonics: BTreeMap<&'static str, u64>,
+    pub classes: BTreeMap<Class, u64>,
+    pub functions: BTreeMap<u32, FunctionStats>,
+    /// Calls by caller and callee
+    pub calls: BTreeMap<(u32, u32), u64>,
+    stack: Vec<Frame>,
+    /// Frames of each function on `stack`
+    active: BTreeMap<u32, u32>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            counts: BTreeMap::new(),
+            mnemonics: BTreeMap::new(),
+            classes: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            stack: Vec::new(),
+            active: BTreeMap::new(),
+        }
+    }
+
+    fn push(&mut self, frame: Frame) {
+        *self.active.entry(frame.function).or_default() += 1;
+        self.stack.push(frame);
+    }
+
+    /// Pops the frames above `depth`.
+    fn truncate(&mut self, depth: usize) {
+        for frame in self.stack.drain(depth..) {
+            if let Some(frames) = self.active.get_mut(&frame.function) {
+                *frames -= 1;
+                if *frames == 0 {
+                    self.active.remove(&frame.function);
+                }
+            }
+        }
+    }
+
+    /// Counts `code` executed at `pc`, where `register` holds the registers after it.
+    pub fn record(&mut self, pc: u32, code: Binary, register: &Register) {
+        if self.stack.is_empty() {
+            self.push(Frame {
+                function: pc,
+                return_address: 0,
+            });
+        }
+
+        *self.counts.entry(pc).or_default() += 1;
+        let mnemonic = mnemonic(code);
+        *self.mnemonics.entry(mnemonic).or_default() += 1;
+        *self.classes.entry(Class::of(code)).or_default() += 1;
+
+        let current = self.stack.last().unwrap().function;
+        self.functions.entry(current).or_default().self_count += 1;
+        // A recursive function is counted once in the total
+        for function in self.active.keys() {
+            self.functions.entry(*function).or_default().total += 1;
+        }
+
+        match mnemonic {
+            "jal" => {
+                let target = (pc.wrapping_add(4) & 0xf000_0000) | (JI::decode(code).ad << 2) as u32;
+                *self.calls.entry((current, target)).or_default() += 1;
+                self.functions.entry(target).or_default().calls += 1;
+                self.push(Frame {
+                    function: target,
+                    return_address: register.get(Register::RA) as u32,
+                });
+            }
+            "jr" if RI::decode(code).rs == Register::RA => {
+                let ra = register.get(Register::RA) as u32;
+                // Returns to the innermost caller expecting it, skipping frames left by
+                // functions which did not return with `jr $ra`
+                match self.stack.iter().rposition(|f| f.return_address == ra) {
+                    Some(depth) if depth > 0 => self.truncate(depth),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Number of instructions executed
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Flat profile, call graph and counts by mnemonic, class and address, with functions and
+    /// addresses named after the labels in `labels`
+    pub fn report(&self, labels: &BTreeMap<u32, String>) -> String {
+        let name = |address: u32| {
+            symbolize(labels, address).unwrap_or_else(|| format!("{:#010x}", address))
+        };
+        let total = self.total();
+        let percent = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+        let mut report = format!("{} instructions executed\n", total);
+
+        report.push_str("\nFlat profile\n    self        %   total   calls  function\n");
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by_key(|(address, f)| (std::cmp::Reverse(f.self_count), **address));
+        for (address, f) in functions.iter() {
+            report.push_str(&format!(
+                "{:>8} {:>7.2}% {:>7} {:>7}  {}\n",
+                f.self_count,
+                percent(f.self_count),
+                f.total,
+                f.calls,
+                name(**address)
+            ));
+        }
+
+        report.push_str("\nCall graph\n");
+        for (address, f) in functions.iter() {
+            report.push_str(&format!(
+                "{}: {} instructions, {} calls\n",
+                name(**address),
+                f.total,
+                f.calls
+            ));
+            for ((caller, _), count) in self.calls.iter().filter(|((_, c), _)| c == *address) {
+                report.push_str(&format!("  called by {} {} times\n", name(*caller), count));
+            }
+            for ((_, callee), count) in self.calls.iter().filter(|((c, _), _)| c == *address) {
+                report.push_str(&format!("  calls {} {} times\n", name(*callee), count));
+            }
+        }
+
+        report.push_str("\nInstructions\n");
+        let mut mnemonics: Vec<_> = self.mnemonics.iter().collect();
+        mnemonics.sort_by_key(|(mnemonic, count)| (std::cmp::Reverse(**count), **mnemonic));
+        for (mnemonic, count) in mnemonics {
+            report.push_str(&format!(
+                "{:<8} {:>8} {:>7.2}%\n",
+                mnemonic,
+                count,
+                percent(*count)
+            ));
+        }
+
+        report.push_str("\nClasses\n");
+        for (class, count) in self.classes.iter() {
+            report.push_str(&format!(
+                "{:<8} {:>8} {:>7.2}%\n",
+                class.name(),
+                count,
+                percent(*count)
+            ));
+        }
+
+        report.push_str("\nExecution counts\n");
+        for (pc, count) in self.counts.iter() {
+            report.push_str(&format!("{:#010x} {:>8}  {}\n", pc, count, name(*pc)));
+        }
+        report
+    }
+}
+
+#[test]
+#[allow(overflowing_literals)]
+fn test_profiler() {
+    let mut profiler = Profiler::new();
+    let mut register = Register::new();
+
+    // main: jal f; f: jal g; g: jr $ra; f: jr $ra; main: jr $ra
+    let trace = [
+        (0x00400000, 0x0c100003, 0x00400004),
+        (0x0040000c, 0x0c100006, 0x00400010),
+        (0x00400018, 0x03e00008, 0x00400010),
+        (0x00400010, 0x03e00008, 0x00400004),
+        (0x00400004, 0x03e00008, 0),
+    ];
+    for (pc, code, ra) in trace {
+        register.set(Register::RA, ra);
+        profiler.record(pc, code, &register);
+    }
+
+    assert_eq!(profiler.total(), 5);
+    assert_eq!(profiler.mnemonics["jal"], 2);
+    assert_eq!(profiler.classes[&Class::Jump], 5);
+    assert_eq!(
+        profiler.functions[&0x00400000],
+        FunctionStats {
+            self_count: 2,
+            total: 5,
+            calls: 0
+        }
+    );
+    assert_eq!(
+        profiler.functions[&0x0040000c],
+        FunctionStats {
+            self_count: 2,
+            total: 3,
+            calls: 1
+        }
+    );
+    assert_eq!(profiler.functions[&0x00400018].total, 1);
+    assert_eq!(profiler.calls[&(0x0040000c, 0x00400018)], 1);
+
+    let labels = BTreeMap::from([
+        (0x00400000, "main".to_string()),
+        (0x0040000c, "f".to_string()),
+        (0x00400018, "g".to_string()),
+    ]);
+    let report = profiler.report(&labels);
+    assert!(report
+        .contains("f: 3 instructions, 1 calls\n  called by main 1 times\n  calls g 1 times\n"));
+    assert!(report.contains("0x00400010        1  f+4\n"));
+}
+
+#[test]
+#[allow(overflowing_literals)]
+fn test_profiler_recursion() {
+    let mut profiler = Profiler::new();
+    let mut register = Register::new();
+
+    // main: jal f; f: jal f; f: jr $ra; f: jr $ra; main: jr $ra
+    let trace = [
+        (0x00400000, 0x0c100003, 0x00400004),
+        (0x0040000c, 0x0c100003, 0x00400010),
+        (0x00400010, 0x03e00008, 0x00400010),
+        (0x00400010, 0x03e00008, 0x00400004),
+        (0x00400004, 0x03e00008, 0),
+    ];
+    for (pc, code, ra) in trace {
+        register.set(Register::RA, ra);
+        profiler.record(pc, code, &register);
+    }
+
+    // Each instruction is counted once in the total of f, however deep the recursion is
+    assert_eq!(
+        profiler.functions[&0x0040000c],
+        FunctionStats {
+            self_count: 3,
+            total: 3,
+            calls: 2
+        }
+    );
+    assert_eq!(profiler.functions[&0x00400000].total, 5);
+}
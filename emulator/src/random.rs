@@ -0,0 +1,83 @@
+use crate::Binary;
+use crate::Emulator;
+
+/// SplitMix64 generator of the random syscalls, which gives the same numbers for a seed on every
+/// machine
+#[derive(Debug, PartialEq, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Number in `0..bound`, which must not be 0
+    pub fn below(&mut self, bound: u32) -> u32 {
+        ((self.next_u32() as u64 * bound as u64) >> 32) as u32
+    }
+
+    /// Number in `0.0..1.0`
+    pub fn float(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+impl Emulator {
+    /// Generator `id` of the random syscalls, seeded with `EmulatorConfig::random_seed` until the
+    /// program sets its seed
+    pub fn random(&mut self, id: Binary) -> &mut Random {
+        let seed = self.config.random_seed;
+        self.randoms.entry(id).or_insert_with(|| Random::new(seed))
+    }
+
+    /// Milliseconds since the Unix epoch on the virtual clock of the time syscall, which runs at
+    /// `EmulatorConfig::instructions_per_ms` from `EmulatorConfig::clock_start`
+    pub fn time(&self) -> u64 {
+        self.config.clock_start + self.instructions / self.config.instructions_per_ms.max(1)
+    }
+}
+
+#[test]
+fn test_random() {
+    // The first number of SplitMix64 seeded with 0
+    assert_eq!(Random::new(0).next_u64(), 0xe220a8397b1dcdaf);
+
+    let mut random = Random::new(42);
+    let first = random.next_u32();
+    assert_eq!(Random::new(42).next_u32(), first);
+    assert_ne!(Random::new(43).next_u32(), first);
+
+    for _ in 0..1000 {
+        assert!(random.below(6) < 6);
+        let float = random.float();
+        assert!((0.0..1.0).contains(&float));
+    }
+    assert_eq!(random.below(1), 0);
+}
+
+#[test]
+fn test_time() {
+    let mut emu = Emulator::new();
+    emu.config.clock_start = 1_700_000_000_000;
+    emu.instructions = 2500;
+    assert_eq!(emu.time(), 1_700_000_000_002);
+
+    let seed = emu.config.random_seed;
+    emu.random(1).next_u32();
+    assert_ne!(emu.random(1), &Random::new(seed));
+    assert_eq!(emu.random(2), &Random::new(seed));
+}
@@ -1,12 +1,10 @@
 use crate::Binary;
 use crate::Register;
-use crate::MEMORY_SIZE;
+use mips_abi::{register_number, REGISTER_NAMES};
 
 impl Register {
     pub fn new() -> Self {
-        let mut storage = [0; 34];
-        storage[Register::SP as usize] = MEMORY_SIZE as i32 - 1;
-        Self { storage }
+        Self { storage: [0; 34] }
     }
 
     pub fn get(&self, idx: Binary) -> Binary {
@@ -28,6 +26,21 @@ impl Register {
     pub fn reset(&mut self) {
         self.storage = [0; 34];
     }
+
+    /// ABI name of the register `idx`, without `$`
+    pub fn name(idx: Binary) -> &'static str {
+        match idx {
+            Register::HI => "hi",
+            Register::LO => "lo",
+            _ => REGISTER_NAMES[idx as usize],
+        }
+    }
+
+    /// Number of the general-purpose register named `name` in the ABI, without `$`, where
+    /// `s8` is `fp`
+    pub fn number(name: &str) -> Option<Binary> {
+        register_number(name)
+    }
 }
 
 #[allow(dead_code)]
@@ -40,10 +53,43 @@ impl Register {
     pub const A1: Binary = 5;
     pub const A2: Binary = 6;
     pub const A3: Binary = 7;
+    pub const T0: Binary = 8;
+    pub const T1: Binary = 9;
+    pub const T2: Binary = 10;
+    pub const T3: Binary = 11;
+    pub const T4: Binary = 12;
+    pub const T5: Binary = 13;
+    pub const T6: Binary = 14;
+    pub const T7: Binary = 15;
+    pub const S0: Binary = 16;
+    pub const S1: Binary = 17;
+    pub const S2: Binary = 18;
+    pub const S3: Binary = 19;
+    pub const S4: Binary = 20;
+    pub const S5: Binary = 21;
+    pub const S6: Binary = 22;
+    pub const S7: Binary = 23;
+    pub const T8: Binary = 24;
+    pub const T9: Binary = 25;
+    pub const K0: Binary = 26;
+    pub const K1: Binary = 27;
     pub const GP: Binary = 28;
     pub const SP: Binary = 29;
     pub const FP: Binary = 30;
+    /// Alias of `FP`
+    pub const S8: Binary = 30;
     pub const RA: Binary = 31;
     pub const HI: Binary = 32;
     pub const LO: Binary = 33;
 }
+
+#[test]
+fn test_names() {
+    assert_eq!(Register::number("t8"), Some(Register::T8));
+    assert_eq!(Register::number("t9"), Some(25));
+    assert_eq!(Register::number("s8"), Some(Register::FP));
+    assert_eq!(Register::number("fp"), Some(30));
+    assert_eq!(Register::number("s9"), None);
+    assert_eq!(Register::name(24), "t8");
+    assert_eq!(Register::name(Register::LO), "lo");
+}
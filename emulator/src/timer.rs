@@ -0,0 +1,110 @@
+use crate::device::Device;
+use crate::Binary;
+
+const ENABLE: Binary = 0x1;
+const INTERRUPT_ENABLE: Binary = 0x2;
+const PENDING: Binary = 0x4;
+
+/// Timer counting executed instructions
+///
+/// | Offset | Register |
+/// |--------|----------|
+/// | 0x0    | Count    |
+/// | 0x4    | Compare  |
+/// | 0x8    | Control  |
+///
+/// While bit 0 of Control is set, Count is incremented after every instruction, and when it
+/// reaches Compare bit 2 of Control becomes pending. A pending timer raises the interrupt line
+/// IP7 if bit 1 of Control is set. Writing Compare acknowledges the interrupt.
+#[derive(Debug, Default)]
+pub struct Timer {
+    pub count: Binary,
+    pub compare: Binary,
+    control: Binary,
+}
+
+impl Timer {
+    pub const BASE: u32 = 0xffff_0010;
+    pub const SIZE: u32 = 0x10;
+    /// Interrupt line of the timer, IP7
+    pub const LINE: u32 = 5;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pending(&self) -> bool {
+        self.control & PENDING != 0
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, offset: u32) -> Binary {
+        match offset {
+            0x0 => self.count,
+            0x4 => self.compare,
+            0x8 => self.control,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, value: Binary) {
+        match offset {
+            0x0 => self.count = value,
+            0x4 => {
+                self.compare = value;
+                self.control &= !PENDING;
+            }
+            0x8 => self.control = (self.control & PENDING) | (value & (ENABLE | INTERRUPT_ENABLE)),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.control & ENABLE == 0 {
+            return;
+        }
+        self.count = self.count.wrapping_add(1);
+        if self.count == self.compare {
+            self.control |= PENDING;
+        }
+    }
+
+    fn interrupts(&self) -> u32 {
+        if self.pending() && self.control & INTERRUPT_ENABLE != 0 {
+            1 << Timer::LINE
+        } else {
+            0
+        }
+    }
+}
+
+#[test]
+fn test_timer() {
+    let mut timer = Timer::new();
+    timer.write(0x4, 3);
+
+    // Stopped
+    timer.tick();
+    assert_eq!(timer.read(0x0), 0);
+
+    timer.write(0x8, ENABLE);
+    timer.tick();
+    timer.tick();
+    assert!(!timer.pending());
+    timer.tick();
+    assert_eq!(timer.read(0x0), 3);
+    assert_eq!(timer.read(0x8), ENABLE | PENDING);
+    // Interrupts are disabled
+    assert_eq!(timer.interrupts(), 0);
+
+    timer.write(0x8, ENABLE | INTERRUPT_ENABLE);
+    assert_eq!(timer.interrupts(), 1 << Timer::LINE);
+
+    // Acknowledge and re-arm
+    timer.write(0x4, 5);
+    assert_eq!(timer.interrupts(), 0);
+    timer.tick();
+    timer.tick();
+    assert_eq!(timer.interrupts(), 1 << Timer::LINE);
+}
@@ -1,6 +1,52 @@
-use clap::Parser;
-use mips_assembler::assemble_to_u8;
-use mips_emulator::Emulator;
+use clap::{Parser, ValueEnum};
+use mips_assembler::source::Source;
+use mips_assembler::{assemble_source_to_program_with, AssembleOptions};
+use mips_emulator::cache::{CacheConfig, Caches, Replacement, WritePolicy};
+use mips_emulator::coverage::Coverage;
+use mips_emulator::debug::DebugInfo;
+use mips_emulator::dump::{Interpretation, Unit};
+use mips_emulator::pipeline::{BranchPolicy, Pipeline, PipelineConfig};
+use mips_emulator::predictor::{BranchPredictor, Btb, Gshare, OneBit, Static, TwoBit};
+use mips_emulator::profile::Profiler;
+use mips_emulator::{Emulator, EmulatorConfig};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ViewArg {
+    Hexdump,
+    Byte,
+    Half,
+    Word,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum InterpretationArg {
+    Hex,
+    Signed,
+    Unsigned,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ReplacementArg {
+    Lru,
+    Fifo,
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PredictorArg {
+    Taken,
+    NotTaken,
+    OneBit,
+    TwoBit,
+    Gshare,
+    Btb,
+}
 
 #[derive(Debug, Parser)]
 #[clap(name = "mips", version = "v1.0.0", about = "Minimum mips emulator")]
@@ -8,15 +54,325 @@ struct Args {
     /// Read assembly from <Input File>
     #[arg(value_name = "Input File")]
     input: String,
+
+    /// Pass <Arguments> to the program in argv after <Input File>, which is argv[0].
+    #[arg(
+        value_name = "Arguments",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    arguments: Vec<String>,
+
+    /// Pass <Name=Value> to the program in envp. Can be given more than once.
+    #[arg(long = "env", value_name = "Name=Value")]
+    env: Vec<String>,
+
+    /// Execute the instruction after a branch or jump, filling unfilled slots with a nop.
+    #[arg(long = "delay-slots", default_value_t = false)]
+    delay_slots: bool,
+
+    /// Write the destination of lw after the next instruction, as MIPS I does.
+    #[arg(long = "load-delay", default_value_t = false)]
+    load_delay: bool,
+
+    /// Warn when a register is read in the load delay slot of lw.
+    #[arg(long = "check-hazards", default_value_t = false)]
+    check_hazards: bool,
+
+    /// Warn of collisions of the heap and the stack and of accesses beyond the break.
+    #[arg(long = "check-heap", default_value_t = false)]
+    check_heap: bool,
+
+    /// Print a five-stage pipeline diagram of the run.
+    #[arg(long = "pipeline", value_name = "Format")]
+    pipeline: Option<Format>,
+
+    /// Wait for write back instead of forwarding results in the pipeline diagram.
+    #[arg(long = "no-forwarding", default_value_t = false)]
+    no_forwarding: bool,
+
+    /// Stall fetch until branches are resolved instead of predicting not taken.
+    #[arg(long = "branch-stall", default_value_t = false)]
+    branch_stall: bool,
+
+    /// Simulate a cache and print its hit and miss statistics.
+    #[arg(long = "cache", default_value_t = false)]
+    cache: bool,
+
+    /// Size of the cache in bytes.
+    #[arg(long = "cache-size", value_name = "Bytes", default_value_t = 1024)]
+    cache_size: u32,
+
+    /// Size of a cache block in bytes.
+    #[arg(long = "block-size", value_name = "Bytes", default_value_t = 16)]
+    block_size: u32,
+
+    /// Blocks in a cache set.
+    #[arg(long = "associativity", value_name = "Ways", default_value_t = 1)]
+    associativity: u32,
+
+    /// Block replaced when a cache set is full.
+    #[arg(long = "replacement", value_name = "Policy", default_value = "lru")]
+    replacement: ReplacementArg,
+
+    /// Write stores through to memory instead of writing back dirty blocks.
+    #[arg(long = "write-through", default_value_t = false)]
+    write_through: bool,
+
+    /// Use separate instruction and data caches.
+    #[arg(long = "split-cache", default_value_t = false)]
+    split_cache: bool,
+
+    /// Print every cache access.
+    #[arg(long = "cache-trace", default_value_t = false)]
+    cache_trace: bool,
+
+    /// Run conditional branches through a branch predictor and print its accuracy. Can be given
+    /// more than once.
+    #[arg(long = "predictor", value_name = "Predictor")]
+    predictors: Vec<PredictorArg>,
+
+    /// Entries in the table of a branch predictor.
+    #[arg(
+        long = "predictor-size",
+        value_name = "Entries",
+        default_value_t = 1024,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    predictor_size: usize,
+
+    /// Branch outcomes in the global history of gshare, from 1 to 20.
+    #[arg(
+        long = "history-bits",
+        value_name = "Bits",
+        default_value_t = 8,
+        value_parser = clap::value_parser!(u32).range(1..=20)
+    )]
+    history_bits: u32,
+
+    /// Print a flat profile, a call graph and the instruction counts of the run.
+    #[arg(long = "profile", default_value_t = false)]
+    profile: bool,
+
+    /// Write the lines and branches executed by the run to <File> in the lcov format.
+    #[arg(long = "coverage", value_name = "File")]
+    coverage: Option<String>,
+
+    /// Seed the generators of the random syscalls with <Seed> until the program sets one.
+    #[arg(long = "seed", value_name = "Seed", default_value_t = 0)]
+    seed: u64,
+
+    /// Start the clock of the time syscall at <Milliseconds> since the Unix epoch. The clock
+    /// advances a millisecond every 1000 instructions.
+    #[arg(long = "clock-start", value_name = "Milliseconds", default_value_t = 0)]
+    clock_start: u64,
+
+    /// Print the registers when the run ends.
+    #[arg(long = "dump-registers", default_value_t = false)]
+    dump_registers: bool,
+
+    /// Print <Size> bytes of memory from <Address>, a number or a label, when the run ends. Can
+    /// be given more than once.
+    #[arg(long = "dump-memory", value_name = "Address:Size")]
+    dump_memory: Vec<String>,
+
+    /// Show dumped memory as a hexdump with ASCII or as bytes, halves or words.
+    #[arg(long = "dump-view", value_name = "View", default_value = "hexdump")]
+    dump_view: ViewArg,
+
+    /// Show dumped registers and bytes, halves or words in hex or in signed or unsigned decimal.
+    #[arg(long = "dump-as", value_name = "Interpretation", default_value = "hex")]
+    dump_as: InterpretationArg,
+}
+
+/// Address of a number in decimal or hex, or of a label
+fn parse_address(text: &str, debug_info: &DebugInfo) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok().or_else(|| debug_info.address(text)),
+    }
+}
+
+/// Prints the registers and memory asked for by `--dump-registers` and `--dump-memory`
+fn dump(emu: &Emulator, args: &Args, debug_info: &DebugInfo) {
+    let interpretation = match args.dump_as {
+        InterpretationArg::Hex => Interpretation::Hex,
+        InterpretationArg::Signed => Interpretation::Signed,
+        InterpretationArg::Unsigned => Interpretation::Unsigned,
+    };
+    if args.dump_registers {
+        print!("{}", emu.registers().format(interpretation));
+    }
+    for range in args.dump_memory.iter() {
+        let parsed = range.split_once(':').and_then(|(address, size)| {
+            Some((
+                parse_address(address, debug_info)?,
+                parse_address(size, debug_info)?,
+            ))
+        });
+        let Some((address, size)) = parsed else {
+            eprintln!("{}: expected <Address>:<Size>", range);
+            std::process::exit(1);
+        };
+        let range = match emu.memory.range(address, size) {
+            Ok(range) => range,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        match args.dump_view {
+            ViewArg::Hexdump => print!("{}", range.hexdump()),
+            ViewArg::Byte => print!("{}", range.format(Unit::Byte, interpretation)),
+            ViewArg::Half => print!("{}", range.format(Unit::Half, interpretation)),
+            ViewArg::Word => print!("{}", range.format(Unit::Word, interpretation)),
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
-    let bin = assemble_to_u8(mips_assembler::Endian::Little, &args.input).unwrap();
-    let mut emu = Emulator::new();
+    let source = Source::load::<_, &str>(&[&args.input], &[]).unwrap();
+    let options = AssembleOptions {
+        delay_slots: args.delay_slots,
+    };
+    let program = assemble_source_to_program_with(&source, &options).unwrap();
+    let mut bin = Vec::new();
+    program
+        .write_code(mips_assembler::Endian::Little, &mut bin)
+        .unwrap();
+    // Reports are printed alone so that they can be read by other programs
+    let report = args.pipeline.is_some()
+        || args.profile
+        || args.cache
+        || !args.predictors.is_empty()
+        || args.coverage.is_some()
+        || args.dump_registers
+        || !args.dump_memory.is_empty();
+    let mut emu = Emulator::with_config(EmulatorConfig {
+        delay_slot: args.delay_slots,
+        load_delay: args.load_delay,
+        check_load_hazards: args.check_hazards,
+        check_heap: args.check_heap,
+        random_seed: args.seed,
+        clock_start: args.clock_start,
+        trace: !report,
+        ..Default::default()
+    });
     emu.clear_memory();
     emu.clear_register();
     emu.load_from_u8(&bin, mips_emulator::Endian::Little)
         .unwrap();
-    emu.run();
+    let argv: Vec<_> = std::iter::once(&args.input)
+        .chain(args.arguments.iter())
+        .collect();
+    if let Err(e) = emu.set_arguments(&argv, &args.env.iter().collect::<Vec<_>>()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    emu.map_devices();
+    let debug_info = DebugInfo::parse(&program.debug_info()).unwrap();
+    emu.debug_info = Some(debug_info.clone());
+    if args.cache {
+        let config = CacheConfig {
+            size: args.cache_size,
+            block_size: args.block_size,
+            associativity: args.associativity,
+            replacement: match args.replacement {
+                ReplacementArg::Lru => Replacement::Lru,
+                ReplacementArg::Fifo => Replacement::Fifo,
+                ReplacementArg::Random => Replacement::Random,
+            },
+            write_policy: if args.write_through {
+                WritePolicy::WriteThrough
+            } else {
+                WritePolicy::WriteBack
+            },
+            trace: args.cache_trace,
+        };
+        match Caches::new(config, args.split_cache) {
+            Ok(caches) => emu.caches = Some(caches),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    for predictor in args.predictors.iter() {
+        let size = args.predictor_size;
+        let predictor = match predictor {
+            PredictorArg::Taken => Ok(BranchPredictor::new(Static { taken: true })),
+            PredictorArg::NotTaken => Ok(BranchPredictor::new(Static { taken: false })),
+            PredictorArg::OneBit => OneBit::new(size).map(BranchPredictor::new),
+            PredictorArg::TwoBit => TwoBit::new(size).map(BranchPredictor::new),
+            PredictorArg::Gshare => Gshare::new(args.history_bits).map(BranchPredictor::new),
+            PredictorArg::Btb => Btb::new(size).map(BranchPredictor::new),
+        };
+        match predictor {
+            Ok(predictor) => emu.predictors.push(predictor),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if args.profile {
+        emu.profiler = Some(Profiler::new());
+    }
+    if args.coverage.is_some() {
+        emu.coverage = Some(Coverage::new());
+    }
+    let result = match args.pipeline {
+        Some(format) => {
+            let mut pipeline = Pipeline::new(PipelineConfig {
+                forwarding: !args.no_forwarding,
+                branches: if args.branch_stall {
+                    BranchPolicy::Stall
+                } else {
+                    BranchPolicy::PredictNotTaken
+                },
+            });
+            pipeline.run(&mut emu).map(|_| match format {
+                Format::Text => print!("{}", pipeline.diagram()),
+                Format::Json => print!("{}", pipeline.to_json()),
+            })
+        }
+        None => emu.run(),
+    };
+    for hazard in emu.hazards.iter() {
+        eprintln!("warning: {}", hazard);
+    }
+    for error in emu.heap_errors.iter() {
+        eprintln!("warning: {}", error);
+    }
+    if let Err(e) = result {
+        match debug_info.describe(emu.pc as u32) {
+            Some(source) => eprintln!("{} [PC = {:#010x}] at {}", e, emu.pc, source),
+            None => eprintln!("{} [PC = {:#010x}]", e, emu.pc),
+        }
+        dump(&emu, &args, &debug_info);
+        std::process::exit(1);
+    }
+    dump(&emu, &args, &debug_info);
+    if let Some(caches) = &emu.caches {
+        for (name, cache) in caches.caches() {
+            for access in cache.trace.iter() {
+                println!("[{}] {}", name, access);
+            }
+        }
+        print!("{}", caches.report());
+    }
+    let labels = &debug_info.labels;
+    for predictor in emu.predictors.iter() {
+        print!("{}", predictor.report(labels));
+    }
+    if let Some(profiler) = &emu.profiler {
+        print!("{}", profiler.report(labels));
+    }
+    if let (Some(coverage), Some(path)) = (&emu.coverage, &args.coverage) {
+        if let Err(e) = std::fs::write(path, coverage.lcov(&emu.memory, &debug_info.lines)) {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
 }
@@ -1,4 +1,4 @@
-addi $t0, $0, L
+la $t0, L
 
 # Load word
 # dest: $a0 offset: 0, address: L 
@@ -3,8 +3,8 @@
 main:
 addi $v0, $0, 1
 
-addi $t0, $0, A
-addi $t1, $0, B
+la $t0, A
+la $t1, B
 
 lw $t2, 0($t0)
 sw $t2, 0($t1)
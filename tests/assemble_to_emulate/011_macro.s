@@ -0,0 +1,19 @@
+.macro print_int(%x)
+  add $a0, $zero, %x
+  addi $v0, $zero, 1
+  syscall
+.end_macro
+
+# Each expansion gets its own copy of the label "loop".
+.macro countdown(%from)
+  addi $t0, $zero, %from
+loop:
+  print_int($t0)
+  addi $t0, $t0, -1
+  bne $t0, $zero, loop
+.end_macro
+
+countdown(3)
+countdown(2)
+
+jr $ra
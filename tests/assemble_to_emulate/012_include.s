@@ -0,0 +1,6 @@
+.include "include/print.s"
+
+addi $t0, $zero, 12
+print_int($t0)
+
+jr $ra
@@ -0,0 +1,19 @@
+# Echo keys to the display until a newline by polling the keyboard and display
+lui $t0, 65535
+addi $t3, $zero, 10
+addi $t4, $zero, 1
+
+wait_key:
+lw $t1, 0($t0)
+and $t1, $t1, $t4
+beq $t1, $zero, wait_key
+lw $t2, 4($t0)
+
+wait_display:
+lw $t1, 8($t0)
+and $t1, $t1, $t4
+beq $t1, $zero, wait_display
+sw $t2, 12($t0)
+
+bne $t2, $t3, wait_key
+jr $ra
@@ -0,0 +1,34 @@
+# Count three timer interrupts, raised every 10 instructions
+.text
+lui $t0, 65535
+addi $t1, $zero, 10
+sw $t1, 20($t0)
+addi $t1, $zero, 3
+sw $t1, 24($t0)
+
+# Unmask IP7 and enable interrupts
+addi $t1, $zero, 1
+sll $t1, $t1, 15
+addi $t1, $t1, 1
+mtc0 $t1, $12
+
+addi $t2, $zero, 3
+wait:
+bne $s0, $t2, wait
+
+mtc0 $zero, $12
+addi $v0, $zero, 1
+add $a0, $s0, $zero
+syscall
+lw $a0, 16($t0)
+syscall
+jr $ra
+
+.ktext
+handler:
+lui $k0, 65535
+lw $k1, 20($k0)
+addi $k1, $k1, 10
+sw $k1, 20($k0)
+addi $s0, $s0, 1
+eret
@@ -0,0 +1,17 @@
+# The instruction after a branch or jump is executed before it is taken
+addi $v0, $zero, 1
+addi $t1, $zero, 3
+
+.set noreorder
+loop:
+addi $t0, $t0, 1
+bne $t0, $t1, loop
+addi $a0, $a0, 10
+.set reorder
+
+syscall
+j end
+addi $a0, $zero, 7
+end:
+syscall
+jr $ra
@@ -0,0 +1,12 @@
+# $t1 is read in the load delay slot of lw
+.data
+A: .word 5
+.text
+la $t0, A
+lw $t1, 0($t0)
+add $a0, $t1, $zero
+addi $v0, $zero, 1
+syscall
+add $a0, $t1, $zero
+syscall
+jr $ra
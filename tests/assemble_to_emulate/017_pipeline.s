@@ -0,0 +1,10 @@
+# A load-use stall and a taken branch
+.data
+A: .word 2
+.text
+la $t0, A
+lw $t1, 0($t0)
+loop:
+addi $t1, $t1, -1
+bne $t1, $zero, loop
+jr $ra
@@ -0,0 +1,23 @@
+# Adds up A and B, which are 64 bytes apart and map to the same set of a small cache
+la $t0, A
+la $t1, B
+addi $t3, $t0, 16
+addi $a0, $zero, 0
+
+L:
+lw $t2, 0($t0)
+add $a0, $a0, $t2
+lw $t2, 0($t1)
+add $a0, $a0, $t2
+addi $t0, $t0, 4
+addi $t1, $t1, 4
+bne $t0, $t3, L
+
+addi $v0, $zero, 1
+syscall
+jr $ra
+
+.data
+A: .word 1, 2, 3, 4
+.space 48
+B: .word 10, 20, 30, 40
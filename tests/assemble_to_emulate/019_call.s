@@ -0,0 +1,30 @@
+# Prints square(1) + square(2) + square(3) with nested calls
+main:
+add $s0, $ra, $zero
+addi $a0, $zero, 3
+jal sum_squares
+add $a0, $v0, $zero
+addi $v0, $zero, 1
+syscall
+add $ra, $s0, $zero
+jr $ra
+
+# Sum of the squares of 1..$a0
+sum_squares:
+add $s1, $ra, $zero
+add $s2, $a0, $zero
+addi $s3, $zero, 0
+L:
+add $a0, $s2, $zero
+jal square
+add $s3, $s3, $v0
+addi $s2, $s2, -1
+bne $s2, $zero, L
+add $v0, $s3, $zero
+add $ra, $s1, $zero
+jr $ra
+
+square:
+mult $a0, $a0
+mflo $v0
+jr $ra
@@ -0,0 +1,28 @@
+# Prints the area of a circle with radius 2 in single and double precision, and rounded
+.data
+PI: .float 3.14159, 2.0
+HALF: .double 0.5
+.text
+main:
+    la $t0, PI
+    lwc1 $f0, 0($t0)
+    lwc1 $f1, 4($t0)
+    mul.s $f2, $f1, $f1
+    mul.s $f12, $f2, $f0
+    addi $v0, $zero, 2
+    syscall
+    cvt.d.s $f12, $f12
+    la $t1, HALF
+    lwc1 $f4, 0($t1)
+    lwc1 $f5, 4($t1)
+    add.d $f12, $f12, $f4
+    addi $v0, $zero, 3
+    syscall
+    cvt.w.d $f6, $f12
+    mfc1 $a0, $f6
+    c.lt.d $f4, $f12
+    bc1f L
+    addi $v0, $zero, 1
+    syscall
+L:
+    jr $ra
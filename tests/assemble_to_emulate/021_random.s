@@ -0,0 +1,27 @@
+# Rolls a die three times with seed 7, then prints a random int and the time
+main:
+    addi $a0, $zero, 0
+    addi $a1, $zero, 7
+    addi $v0, $zero, 40
+    syscall
+    addi $t0, $zero, 3
+L:
+    addi $a0, $zero, 0
+    addi $a1, $zero, 6
+    addi $v0, $zero, 42
+    syscall
+    addi $a0, $a0, 1
+    addi $v0, $zero, 1
+    syscall
+    addi $t0, $t0, -1
+    bne $t0, $zero, L
+    addi $a0, $zero, 1
+    addi $v0, $zero, 41
+    syscall
+    addi $v0, $zero, 1
+    syscall
+    addi $v0, $zero, 30
+    syscall
+    addi $v0, $zero, 1
+    syscall
+    jr $ra
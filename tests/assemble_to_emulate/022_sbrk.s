@@ -0,0 +1,22 @@
+# Builds the linked list 3 -> 2 -> 1 on the heap and prints it
+main:
+    addi $s0, $zero, 0
+    addi $s1, $zero, 1
+    addi $t0, $zero, 4
+L:
+    # Each node holds a value and the next node
+    addi $a0, $zero, 8
+    addi $v0, $zero, 9
+    syscall
+    sw $s1, 0($v0)
+    sw $s0, 4($v0)
+    add $s0, $v0, $zero
+    addi $s1, $s1, 1
+    bne $s1, $t0, L
+P:
+    lw $a0, 0($s0)
+    addi $v0, $zero, 1
+    syscall
+    lw $s0, 4($s0)
+    bne $s0, $zero, P
+    jr $ra
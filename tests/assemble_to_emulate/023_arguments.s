@@ -0,0 +1,17 @@
+# Prints argc and the first word of each argument after argv[0]
+main:
+    add $s0, $a0, $zero
+    add $s1, $a1, $zero
+    addi $v0, $zero, 1
+    syscall
+    addi $t0, $zero, 1
+L:
+    beq $t0, $s0, E
+    addi $s1, $s1, 4
+    lw $t1, 0($s1)
+    lw $a0, 0($t1)
+    syscall
+    addi $t0, $t0, 1
+    j L
+E:
+    jr $ra
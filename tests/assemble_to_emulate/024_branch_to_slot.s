@@ -0,0 +1,11 @@
+# A branch taken to its delay slot executes the slot again
+addi $v0, $zero, 1
+
+.set noreorder
+beq $zero, $zero, slot
+slot:
+addi $a0, $a0, 1
+.set reorder
+
+syscall
+jr $ra
@@ -0,0 +1,11 @@
+# $t0 is written in the load delay slot of lw, which keeps the value of the slot
+.data
+A: .word 5
+.text
+la $t1, A
+lw $t0, 0($t1)
+addi $t0, $zero, 1
+add $a0, $t0, $zero
+addi $v0, $zero, 1
+syscall
+jr $ra
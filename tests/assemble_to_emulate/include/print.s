@@ -0,0 +1,5 @@
+.macro print_int(%x)
+  add $a0, $zero, %x
+  addi $v0, $zero, 1
+  syscall
+.end_macro
@@ -1,5 +1,10 @@
-use mips_assembler::assemble_to_u8;
-use mips_emulator::Emulator;
+use mips_assembler::link::link;
+use mips_assembler::source::Source;
+use mips_assembler::{
+    assemble_files_to_object, assemble_files_to_u8, assemble_source_to_program, assemble_to_u8,
+    assemble_to_u8_from_string, Program,
+};
+use mips_emulator::{Emulator, Exception};
 
 fn assert(fname: &str, expect: &str) {
     println!("Start assemble");
@@ -11,11 +16,24 @@ fn assert(fname: &str, expect: &str) {
     emu.clear_register();
     emu.load_from_u8(&bin, mips_emulator::Endian::Little)
         .unwrap();
-    emu.run();
+    emu.run().unwrap();
     assert_eq!(emu.stdout_history, expect);
     println!("Finish emulate");
 }
 
+/// Assembles `source`, loads it into `emu` and runs it. The program is returned for its labels
+/// and debug info.
+fn run_source(emu: &mut Emulator, source: &Source) -> (Program, Result<(), Exception>) {
+    let program = assemble_source_to_program(source).unwrap();
+    let mut bin = Vec::new();
+    program
+        .write_code(mips_assembler::Endian::Little, &mut bin)
+        .unwrap();
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    (program, emu.run())
+}
+
 #[test]
 fn test() {
     assert("001_addi_addu.s", "5");
@@ -28,4 +46,636 @@ fn test() {
     assert("008_shift.s", "-32-2");
     assert("009_space.s", "15");
     assert("010_ori.s", "-10");
+    assert("011_macro.s", "32121");
+    assert("012_include.s", "12");
+    assert("020_float.s", "12.5663613.06636047363281313");
+    assert("021_random.s", "316-5011762630");
+    assert("022_sbrk.s", "321");
+}
+
+#[test]
+fn test_read_float() {
+    use mips_emulator::Exception;
+
+    let input = r#"addi $v0, $zero, 6
+syscall
+add.s $f12, $f0, $f0
+addi $v0, $zero, 2
+syscall
+jr $ra"#
+        .to_string();
+    let bin = assemble_to_u8_from_string(mips_assembler::Endian::Little, input).unwrap();
+    let run = |input: &'static str| {
+        let mut emu = Emulator::new();
+        emu.input = Some(Box::new(std::io::Cursor::new(input)));
+        emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+            .unwrap();
+        emu.run().map(|_| emu.stdout_history)
+    };
+    assert_eq!(run("1.25\n"), Ok("2.5".to_string()));
+
+    // Input which is not a number and the end of the input are errors
+    assert_eq!(run("abc\n"), Err(Exception::InvalidInput(6)));
+    assert_eq!(run(""), Err(Exception::InvalidInput(6)));
+}
+
+#[test]
+fn test_multiple_files() {
+    let inputs = [
+        "./tests/assemble_to_emulate/multiple/main.s",
+        "./tests/assemble_to_emulate/multiple/print.s",
+    ];
+    let bin =
+        assemble_files_to_u8::<_, &str>(mips_assembler::Endian::Little, &inputs, &[]).unwrap();
+    let mut emu = Emulator::new();
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    emu.run().unwrap();
+    assert_eq!(emu.stdout_history, "40");
+}
+
+#[test]
+fn test_link() {
+    let inputs = [
+        "./tests/assemble_to_emulate/multiple/main.s",
+        "./tests/assemble_to_emulate/multiple/print.s",
+    ];
+    let objects: Vec<_> = inputs
+        .iter()
+        .map(|i| (i, assemble_files_to_object::<_, &str>(&[i], &[]).unwrap()))
+        .collect();
+    let bin = link(mips_assembler::Endian::Little, &objects).unwrap();
+    let mut emu = Emulator::new();
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    emu.run().unwrap();
+    assert_eq!(emu.stdout_history, "40");
+}
+
+#[test]
+fn test_diagnostics() {
+    let err = assemble_to_u8(
+        mips_assembler::Endian::Little,
+        "./tests/assemble_to_emulate/include/cycle_a.s",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("include cycle"));
+
+    let inputs = [
+        "./tests/assemble_to_emulate/multiple/main.s",
+        "./tests/assemble_to_emulate/multiple/print.s",
+        "./tests/assemble_to_emulate/multiple/undefined.s",
+    ];
+    let err =
+        assemble_files_to_u8::<_, &str>(mips_assembler::Endian::Little, &inputs, &[]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "./tests/assemble_to_emulate/multiple/undefined.s:2: label nowhere is not defined"
+    );
+
+    // A data label does not fit in the immediate of addi
+    let input = "main: addi $t0, $zero, L\nlw $t1, 0($t0)\n.data\nL: .word 1".to_string();
+    let err = assemble_to_u8_from_string(mips_assembler::Endian::Little, input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "<input>:1: address 0x10010000 of label L does not fit in 16 bits, use la or %hi/%lo"
+    );
+}
+
+#[test]
+fn test_address_error() {
+    use mips_emulator::{EmulatorConfig, Exception, Segment};
+
+    let input = "addi $t0, $zero, 2\nlw $a0, 0($t0)\njr $ra".to_string();
+    let bin = assemble_to_u8_from_string(mips_assembler::Endian::Little, input).unwrap();
+    let mut emu = Emulator::new();
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    assert_eq!(emu.run(), Err(Exception::AddressErrorLoad(2)));
+    assert_eq!(emu.pc, 0x00400004);
+
+    // Stack moved below the default one
+    let config = EmulatorConfig {
+        stack: Segment {
+            base: 0x7000_0000,
+            size: 0x1000,
+        },
+        stack_pointer: 0x7000_0ffc,
+        ..EmulatorConfig::default()
+    };
+    let input = "addi $t0, $zero, 5\nsw $t0, 0($sp)\nlw $a0, 0($sp)\naddi $v0, $zero, 1\nsyscall\nsw $t0, 4($sp)".to_string();
+    let bin = assemble_to_u8_from_string(mips_assembler::Endian::Little, input).unwrap();
+    let mut emu = Emulator::with_config(config);
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    assert_eq!(emu.run(), Err(Exception::AddressErrorStore(0x7000_1000)));
+    assert_eq!(emu.stdout_history, "5");
+}
+
+#[test]
+fn test_global_pointer() {
+    let input = r#"addi $t0, $zero, 7
+sw $t0, -32768($gp)
+lw $a0, -32768($gp)
+sw $a0, 32764($gp)
+addi $v0, $zero, 1
+syscall
+jr $ra"#
+        .to_string();
+    let bin = assemble_to_u8_from_string(mips_assembler::Endian::Little, input).unwrap();
+    let mut emu = Emulator::new();
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    emu.run().unwrap();
+    assert_eq!(emu.stdout_history, "7");
+    assert_eq!(emu.memory.read_word(0x10000000), Ok(7));
+    assert_eq!(emu.memory.read_word(0x1000fffc), Ok(7));
+}
+
+#[test]
+fn test_invalid_entry_point() {
+    // Entry point, start of text, start of data and start of kernel text, then one instruction
+    let words: [u32; 5] = [0xffff_fff0, 4, 5, 5, 0x03e00008];
+    let bin: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    let mut emu = Emulator::new();
+    let err = emu
+        .load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "invalid entry point");
+}
+
+#[test]
+fn test_header_without_ktext() {
+    // Header of three words, then `jr $ra` and a word of data
+    let words: [u32; 5] = [3, 3, 4, 0x03e00008, 7];
+    let bin: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    let mut emu = Emulator::new();
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    assert_eq!(emu.pc, 0x00400000);
+    assert_eq!(emu.memory.read_word(0x10010000), Ok(7));
+    emu.run().unwrap();
+}
+
+#[test]
+fn test_mmio() {
+    use mips_emulator::keyboard::KeyboardDisplay;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let bin = assemble_to_u8(
+        mips_assembler::Endian::Little,
+        "./tests/assemble_to_emulate/013_mmio.s",
+    )
+    .unwrap();
+    let device = Rc::new(RefCell::new(KeyboardDisplay::new()));
+    device.borrow_mut().delay = 5;
+    device.borrow_mut().push_input("mips\nignored");
+
+    let mut emu = Emulator::new();
+    emu.memory
+        .map_device(KeyboardDisplay::BASE, KeyboardDisplay::SIZE, device.clone());
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    emu.run().unwrap();
+
+    assert_eq!(device.borrow().output, "mips\n");
+}
+
+#[test]
+fn test_interrupt() {
+    use mips_emulator::timer::Timer;
+
+    let bin = assemble_to_u8(
+        mips_assembler::Endian::Little,
+        "./tests/assemble_to_emulate/014_interrupt.s",
+    )
+    .unwrap();
+
+    let mut emu = Emulator::new();
+    emu.memory
+        .map_device(Timer::BASE, Timer::SIZE, Timer::new());
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    emu.run().unwrap();
+
+    // Three interrupts, and the count 41 instructions after the timer started
+    assert_eq!(emu.stdout_history, "341");
+}
+
+#[test]
+fn test_delay_slot() {
+    use mips_assembler::source::Source;
+    use mips_assembler::{assemble_source_to_program_with, AssembleOptions};
+    use mips_emulator::EmulatorConfig;
+
+    let run = |fname: &str, delay_slot: bool| {
+        let source = Source::load::<_, &str>(&[fname], &[]).unwrap();
+        let options = AssembleOptions {
+            delay_slots: delay_slot,
+        };
+        let mut bin = Vec::new();
+        assemble_source_to_program_with(&source, &options)
+            .unwrap()
+            .write_code(mips_assembler::Endian::Little, &mut bin)
+            .unwrap();
+
+        let mut emu = Emulator::with_config(EmulatorConfig {
+            delay_slot,
+            ..Default::default()
+        });
+        emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+            .unwrap();
+        emu.run().unwrap();
+        emu.stdout_history
+    };
+
+    // The slot filled by hand runs on every iteration, and the one after `j` holds a nop
+    assert_eq!(
+        run("./tests/assemble_to_emulate/015_delay_slot.s", true),
+        "3030"
+    );
+    assert_eq!(
+        run("./tests/assemble_to_emulate/015_delay_slot.s", false),
+        "1010"
+    );
+    assert_eq!(
+        run("./tests/assemble_to_emulate/003_loop.s", true),
+        "0123456789"
+    );
+    assert_eq!(
+        run("./tests/assemble_to_emulate/024_branch_to_slot.s", true),
+        "2"
+    );
+    assert_eq!(
+        run("./tests/assemble_to_emulate/024_branch_to_slot.s", false),
+        "1"
+    );
+}
+
+#[test]
+fn test_load_delay() {
+    use mips_emulator::{EmulatorConfig, Hazard};
+
+    let run = |fname: &str, load_delay: bool| {
+        let bin = assemble_to_u8(mips_assembler::Endian::Little, fname).unwrap();
+        let mut emu = Emulator::with_config(EmulatorConfig {
+            load_delay,
+            check_load_hazards: true,
+            ..Default::default()
+        });
+        emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+            .unwrap();
+        emu.run().unwrap();
+        emu
+    };
+
+    // The instruction after lw sees the old value
+    let emu = run("./tests/assemble_to_emulate/016_load_delay.s", true);
+    assert_eq!(emu.stdout_history, "05");
+    assert_eq!(
+        emu.hazards,
+        vec![Hazard {
+            pc: 0x0040000c,
+            load: 0x00400008,
+            register: 9,
+        }]
+    );
+
+    // Hazards are found without the load delay as well
+    let emu = run("./tests/assemble_to_emulate/016_load_delay.s", false);
+    assert_eq!(emu.stdout_history, "55");
+    assert_eq!(emu.hazards.len(), 1);
+
+    // A write in the load delay slot is not overwritten by the load
+    let emu = run("./tests/assemble_to_emulate/025_load_delay_write.s", true);
+    assert_eq!(emu.stdout_history, "1");
+    assert!(emu.hazards.is_empty());
+}
+
+#[test]
+fn test_pipeline() {
+    use mips_emulator::pipeline::{BranchPolicy, Pipeline, PipelineConfig};
+    use mips_emulator::timer::Timer;
+
+    let bin = assemble_to_u8(
+        mips_assembler::Endian::Little,
+        "./tests/assemble_to_emulate/017_pipeline.s",
+    )
+    .unwrap();
+
+    let run = |config: PipelineConfig| {
+        let mut emu = Emulator::new();
+        emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+            .unwrap();
+        let mut pipeline = Pipeline::new(config);
+        pipeline.run(&mut emu).unwrap();
+        pipeline
+    };
+
+    // addi waits one cycle for lw, and the taken bne flushes jr and the instruction after it
+    let pipeline = run(PipelineConfig::default());
+    assert_eq!(pipeline.retired(), 8);
+    assert_eq!(pipeline.cycles(), 15);
+    assert_eq!(pipeline.data_stalls, 1);
+    assert_eq!(pipeline.flushes, 2);
+    assert!(pipeline.diagram().ends_with(
+        "8 instructions, 15 cycles, CPI 1.88, 1 data stalls, 0 control stalls, 2 flushed\n"
+    ));
+
+    // Without forwarding every dependent instruction waits for the write back before it, and
+    // fetch waits for bne to be resolved
+    let pipeline = run(PipelineConfig {
+        forwarding: false,
+        branches: BranchPolicy::Stall,
+    });
+    assert_eq!(pipeline.entries.len(), 8);
+    assert_eq!(pipeline.cycles(), 26);
+    assert_eq!(pipeline.data_stalls, 10);
+    assert_eq!(pipeline.control_stalls, 8);
+    assert_eq!(pipeline.flushes, 0);
+
+    // The handler is scheduled where each of the three interrupts is taken
+    let bin = assemble_to_u8(
+        mips_assembler::Endian::Little,
+        "./tests/assemble_to_emulate/014_interrupt.s",
+    )
+    .unwrap();
+    let mut emu = Emulator::new();
+    emu.memory
+        .map_device(Timer::BASE, Timer::SIZE, Timer::new());
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    let mut pipeline = Pipeline::new(PipelineConfig::default());
+    pipeline.run(&mut emu).unwrap();
+    let handlers = pipeline
+        .entries
+        .iter()
+        .filter(|e| e.pc as u32 == 0x80000180);
+    assert_eq!(handlers.count(), 3);
+}
+
+#[test]
+fn test_cache() {
+    use mips_emulator::cache::{CacheConfig, Caches};
+
+    let bin = assemble_to_u8(
+        mips_assembler::Endian::Little,
+        "./tests/assemble_to_emulate/018_cache.s",
+    )
+    .unwrap();
+
+    let run = |config: CacheConfig| {
+        let mut emu = Emulator::new();
+        emu.caches = Some(Caches::new(config, true).unwrap());
+        emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+            .unwrap();
+        emu.run().unwrap();
+        assert_eq!(emu.stdout_history, "110");
+        match emu.caches.unwrap() {
+            Caches::Split { data, .. } => data,
+            Caches::Unified(_) => unreachable!(),
+        }
+    };
+
+    // A and B evict each other in a direct mapped cache
+    let data = run(CacheConfig {
+        size: 64,
+        ..Default::default()
+    });
+    assert_eq!(data.stats.reads, 8);
+    assert_eq!(data.stats.read_misses, 8);
+    assert_eq!(data.stats.evictions, 7);
+
+    // Both fit in a set of a 2-way cache
+    let data = run(CacheConfig {
+        size: 128,
+        associativity: 2,
+        trace: true,
+        ..Default::default()
+    });
+    assert_eq!(data.stats.read_misses, 2);
+    assert_eq!(data.stats.evictions, 0);
+    assert_eq!(data.trace[1].to_string(), "R 0x10010040 set 0 miss");
+    assert!(data.trace[2].hit);
+}
+
+#[test]
+fn test_branch_predictor() {
+    use mips_emulator::predictor::{BranchPredictor, OneBit, Static, TwoBit};
+
+    let source = Source::load::<_, &str>(&["./tests/assemble_to_emulate/005_slt.s"], &[]).unwrap();
+    let mut emu = Emulator::new();
+    emu.predictors = vec![
+        BranchPredictor::new(Static { taken: false }),
+        BranchPredictor::new(OneBit::new(64).unwrap()),
+        BranchPredictor::new(TwoBit::new(64).unwrap()),
+    ];
+    let (program, result) = run_source(&mut emu, &source);
+    result.unwrap();
+
+    // The loop branch is taken 9 times and falls through once
+    let correct: Vec<u64> = emu.predictors.iter().map(|p| p.total().correct).collect();
+    assert_eq!(correct, vec![1, 8, 8]);
+    assert_eq!(
+        emu.predictors[1].report(&program.labels()),
+        "1-bit (64 entries): 8/10 correct (80.00%)\n  0x00400018 <L+16>: 9/10 taken, 8/10 correct (80.00%)\n"
+    );
+}
+
+#[test]
+fn test_profile() {
+    use mips_emulator::decode::Class;
+    use mips_emulator::profile::Profiler;
+
+    let source = Source::load::<_, &str>(&["./tests/assemble_to_emulate/019_call.s"], &[]).unwrap();
+    let mut emu = Emulator::new();
+    emu.profiler = Some(Profiler::new());
+    let (program, result) = run_source(&mut emu, &source);
+    result.unwrap();
+
+    let profiler = emu.profiler.unwrap();
+    let labels = program.labels();
+    let function = |name: &str| {
+        let address = labels.iter().find(|(_, l)| *l == name).unwrap().0;
+        profiler.functions[address]
+    };
+    assert_eq!(profiler.total(), 38);
+    assert_eq!(profiler.mnemonics["jal"], 4);
+    assert_eq!(profiler.classes[&Class::Branch], 3);
+    assert_eq!(function("main").total, 38);
+    assert_eq!(function("sum_squares").total, 30);
+    assert_eq!(function("square").self_count, 9);
+    assert_eq!(function("square").calls, 3);
+
+    let report = profiler.report(&labels);
+    assert!(report.contains("square: 9 instructions, 3 calls\n  called by sum_squares 3 times\n"));
+    assert!(report.contains("0x0040002c        3  L\n"));
+}
+
+#[test]
+fn test_coverage() {
+    use mips_emulator::coverage::Coverage;
+    use mips_emulator::debug::DebugInfo;
+
+    let input = r#"addi $a0, $zero, 1
+beq $a0, $zero, L
+addi $v0, $zero, 1
+syscall
+L:
+jr $ra
+addi $a0, $zero, 2"#;
+    let mut emu = Emulator::new();
+    emu.coverage = Some(Coverage::new());
+    let (program, result) = run_source(&mut emu, &Source::from_string("main.s", input));
+    result.unwrap();
+
+    let debug_info = DebugInfo::parse(&program.debug_info()).unwrap();
+    let lcov = emu.coverage.unwrap().lcov(&emu.memory, &debug_info.lines);
+
+    // The branch is never taken and the last line never runs
+    assert!(lcov.contains("BRDA:2,0,0,0\nBRDA:2,0,1,1\nBRF:2\nBRH:1\n"));
+    assert!(lcov.contains("DA:6,1\nDA:7,0\nLF:6\nLH:5\n"));
+}
+
+#[test]
+fn test_debug_info() {
+    use mips_emulator::debug::{DebugInfo, SourceLine};
+
+    let input = r#"main: addi $t0, $zero, 1
+    jal f
+    jr $ra
+f:  lw $t1, 0($zero)"#;
+    let mut emu = Emulator::new();
+    let (program, result) = run_source(&mut emu, &Source::from_string("main.s", input));
+    assert!(result.is_err());
+
+    // The faulting load is found in the source
+    let debug_info = DebugInfo::parse(&program.debug_info()).unwrap();
+    assert_eq!(
+        debug_info.lines[&(emu.pc as u32)],
+        SourceLine {
+            file: "main.s".to_string(),
+            line: 4,
+            column: 5
+        }
+    );
+    assert_eq!(debug_info.address("f"), Some(0x0040000c));
+    assert_eq!(
+        debug_info.describe(0x00400004),
+        Some("main.s:2:5 <main+4>".to_string())
+    );
+}
+
+#[test]
+fn test_dump() {
+    use mips_emulator::dump::{Interpretation, Unit};
+
+    let input = r#"la $t0, A
+    lw $t1, 4($t0)
+    sw $t1, 8($t0)
+    jr $ra
+.data
+A: .word 1819043144, -2"#
+        .to_string();
+    let bin = assemble_to_u8_from_string(mips_assembler::Endian::Little, input).unwrap();
+    let mut emu = Emulator::new();
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    emu.run().unwrap();
+
+    let registers = emu.registers();
+    assert_eq!(registers.get("$t1"), Some(-2));
+    assert_eq!(registers.gpr[8], 0x10010000);
+
+    let range = emu.memory.range(0x10010000, 12).unwrap();
+    assert_eq!(
+        range.hexdump(),
+        "10010000  48 65 6c 6c fe ff ff ff  fe ff ff ff              |Hell........|\n"
+    );
+    assert_eq!(
+        range.format(Unit::Half, Interpretation::Unsigned),
+        "0x10010000:  25928 27756 65534 65535 65534 65535\n"
+    );
+}
+
+#[test]
+fn test_time() {
+    use mips_emulator::{EmulatorConfig, Register};
+
+    let input = r#"addi $v0, $zero, 30
+syscall
+jr $ra"#
+        .to_string();
+    let bin = assemble_to_u8_from_string(mips_assembler::Endian::Little, input).unwrap();
+    let mut emu = Emulator::with_config(EmulatorConfig {
+        clock_start: 0x1_0000_0010,
+        instructions_per_ms: 1,
+        ..Default::default()
+    });
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    emu.run().unwrap();
+    // The syscall is the second instruction
+    assert_eq!(emu.register.get(Register::A0), 0x11);
+    assert_eq!(emu.register.get(Register::A1), 1);
+    assert_eq!(emu.instructions, 3);
+}
+
+#[test]
+fn test_check_heap() {
+    use mips_emulator::{Access, EmulatorConfig, HeapError, Register};
+
+    // Allocates a node of two words but writes a third, then asks for too much
+    let input = r#"addi $a0, $zero, 8
+addi $v0, $zero, 9
+syscall
+sw $zero, 8($v0)
+lui $a0, 28672
+addi $v0, $zero, 9
+syscall
+jr $ra"#
+        .to_string();
+    let bin = assemble_to_u8_from_string(mips_assembler::Endian::Little, input).unwrap();
+    let mut emu = Emulator::with_config(EmulatorConfig {
+        check_heap: true,
+        ..Default::default()
+    });
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    emu.run().unwrap();
+    assert_eq!(emu.register.get(Register::V0), -1);
+    assert_eq!(emu.brk, 0x10040008);
+    assert_eq!(
+        emu.heap_errors,
+        [
+            HeapError::BeyondBreak {
+                pc: 0x0040000c,
+                access: Access::Write,
+                address: 0x10040008,
+                brk: 0x10040008
+            },
+            HeapError::Exhausted {
+                pc: 0x00400018,
+                brk: 0x80040008
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_arguments() {
+    let bin = assemble_to_u8(
+        mips_assembler::Endian::Little,
+        "./tests/assemble_to_emulate/023_arguments.s",
+    )
+    .unwrap();
+    let mut emu = Emulator::new();
+    emu.load_from_u8(&bin, mips_emulator::Endian::Little)
+        .unwrap();
+    emu.set_arguments(&["023_arguments.s", "1", "ABCD"], &["HOME=/"])
+        .unwrap();
+    emu.run().unwrap();
+    // "1\0" is 0x0031 and "ABCD" is 0x44434241
+    assert_eq!(emu.stdout_history, "3491145258561");
 }
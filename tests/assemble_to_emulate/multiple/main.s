@@ -0,0 +1,17 @@
+.text
+.globl main
+.globl back
+main:
+lui $t0, %hi(A)
+addi $t0, $t0, %lo(A)
+lw $a0, 0($t0)
+j print
+
+back:
+lui $t0, %hi(B)
+addi $t0, $t0, %lo(B)
+lw $a0, 0($t0)
+j print
+
+.data
+A: .word 4
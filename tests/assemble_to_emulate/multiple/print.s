@@ -0,0 +1,11 @@
+.globl print
+.globl B
+print:
+addi $v0, $zero, 1
+syscall
+
+bne $a0, $zero, back
+jr $ra
+
+.data
+B: .word 0
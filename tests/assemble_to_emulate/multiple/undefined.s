@@ -0,0 +1,2 @@
+.text
+j nowhere
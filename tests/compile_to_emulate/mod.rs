@@ -17,7 +17,7 @@ fn assert(fname: &str, expect: &str) {
     emu.clear_register();
     emu.load_from_u8(&bin, mips_emulator::Endian::Little)
         .unwrap();
-    emu.run();
+    emu.run().unwrap();
     println!("Finish emulate");
 
     assert_eq!(emu.stdout_history, expect);
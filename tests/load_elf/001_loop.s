@@ -0,0 +1,12 @@
+# mma --elf 001_loop.s -o 001_loop.elf
+addi $a0, $zero, 0
+addi $t0, $zero, 5
+
+L:
+addi $v0, $0, 1
+syscall
+
+addi $a0, $a0, 1
+bne $a0, $t0, L
+
+jr $ra
@@ -0,0 +1,16 @@
+# mma -c 002_data.s -o 002_data.o && mips-ld --elf -b 002_data.o -o 002_data.elf
+.globl main
+exit:
+jr $ra
+
+main:
+la $t0, A
+lw $a0, 0($t0)
+addi $v0, $0, 1
+syscall
+lw $a0, 4($t0)
+syscall
+j exit
+
+.data
+A: .word 7, -3
@@ -0,0 +1,17 @@
+# Assembled by llvm-mc, not by mma, and put in an executable by hand:
+#
+#   llvm-mc -triple=mips-unknown-linux -filetype=obj 003_external.s -o 003_external.o
+#   llvm-objcopy -O binary --only-section=.text 003_external.o text.bin
+#
+# 003_external.elf is big-endian with no section headers. .text is loaded at 0x00400000 and
+# .data at 0x10010000, where the 4 bytes in the file are followed by 12 zeroed bytes (memsz 16).
+    .text
+    .globl __start
+__start:
+    lui $t0, 0x1001
+    lw $a0, 0($t0)
+    addiu $v0, $zero, 1
+    syscall
+    lw $a0, 12($t0)
+    syscall
+    jr $ra
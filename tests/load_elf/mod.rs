@@ -0,0 +1,78 @@
+use mips_emulator::elf::is_elf;
+use mips_emulator::{Emulator, Register};
+
+fn load(fname: &str) -> Emulator {
+    let fname = format!("./tests/load_elf/{}", fname);
+    let mut emu = Emulator::new();
+    emu.load_elf(&fname).unwrap();
+    emu
+}
+
+#[test]
+fn test() {
+    let mut emu = load("001_loop.elf");
+    assert_eq!(emu.pc, 0x00400000);
+    emu.run().unwrap();
+    assert_eq!(emu.stdout_history, "01234");
+
+    let mut emu = load("002_data.elf");
+    // Entry point is `main`
+    assert_eq!(emu.pc, 0x00400004);
+    emu.run().unwrap();
+    assert_eq!(emu.stdout_history, "7-3");
+}
+
+#[test]
+fn test_registers() {
+    let emu = load("002_data.elf");
+    assert_eq!(emu.register.get(Register::GP), 0x10010000 + 0x8000);
+    assert_eq!(emu.register.get(Register::SP), 0x7fffeffc);
+}
+
+#[test]
+fn test_invalid() {
+    let bin = std::fs::read("./tests/load_elf/001_loop.s").unwrap();
+    assert!(!is_elf(&bin));
+
+    let mut emu = Emulator::new();
+    let err = emu.load_elf_from_u8(&bin).unwrap_err();
+    assert_eq!(err.to_string(), "not an ELF file");
+
+    let mut bin = std::fs::read("./tests/load_elf/001_loop.elf").unwrap();
+    bin.truncate(60);
+    assert!(emu.load_elf_from_u8(&bin).is_err());
+}
+
+#[test]
+fn test_external() {
+    // Big-endian, without section headers, and with a zeroed part of .data
+    let mut emu = load("003_external.elf");
+    assert_eq!(emu.memory.endian, mips_emulator::Endian::Big);
+    assert_eq!(emu.register.get(Register::GP), 0x10010000 + 0x8000);
+    emu.run().unwrap();
+    assert_eq!(emu.stdout_history, "420");
+}
+
+#[test]
+fn test_hostile() {
+    let elf = std::fs::read("./tests/load_elf/003_external.elf").unwrap();
+    let mut emu = Emulator::new();
+
+    // memsz of the data segment
+    let mut bin = elf.clone();
+    bin[52 + 32 + 20..52 + 32 + 24].copy_from_slice(&0x7fff_ffffu32.to_be_bytes());
+    assert_eq!(
+        emu.load_elf_from_u8(&bin).unwrap_err().to_string(),
+        "segment at 0x10010000 of 0x7fffffff bytes is outside of the memory map"
+    );
+
+    // filesz past the end of the file
+    let mut bin = elf.clone();
+    bin[52 + 16..52 + 20].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+    assert!(emu.load_elf_from_u8(&bin).is_err());
+
+    // phoff at the end of the address space
+    let mut bin = elf;
+    bin[28..32].copy_from_slice(&0xffff_ffffu32.to_be_bytes());
+    assert!(emu.load_elf_from_u8(&bin).is_err());
+}
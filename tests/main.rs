@@ -1,2 +1,3 @@
 mod assemble_to_emulate;
 mod compile_to_emulate;
+mod load_elf;